@@ -0,0 +1,62 @@
+// Rendering benchmarks on straight, curved and hilly tracks at a few screen
+// sizes. Run with: cargo bench --features bench
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use poisjuoksu::counting::CountingPainter;
+use poisjuoksu::{RoadRenderer, Segment, SideInclination, FP_POS};
+
+const MAX_Z: i32 = 10000 << FP_POS;
+
+fn tracks() -> [(&'static str, [Segment; 4]); 3] {
+    use SideInclination::*;
+    let straight = Segment::new((Flat, Flat), 1000 << FP_POS, 0, 0);
+    [
+        ("straight", [straight; 4]),
+        (
+            "curved",
+            [
+                Segment::new((Flat, Flat), 300 << FP_POS, 10, 0),
+                Segment::new((Flat, Flat), 300 << FP_POS, -20, 0),
+                Segment::new((Flat, Flat), 300 << FP_POS, 15, 0),
+                straight,
+            ],
+        ),
+        (
+            "hilly",
+            [
+                Segment::new((Uphill, Downhill), 300 << FP_POS, 0, -2),
+                Segment::new((Downhill, Uphill), 300 << FP_POS, 5, 2),
+                Segment::new((Uphill, Uphill), 300 << FP_POS, -5, -1),
+                straight,
+            ],
+        ),
+    ]
+}
+
+// A macro rather than a generic function, since the screen size has to be
+// known for the renderer's const generic bounds.
+macro_rules! bench_size {
+    ($c:expr, $w:expr, $h:expr) => {
+        for (name, segments) in tracks().iter() {
+            let mut road = RoadRenderer::new(segments, $w / 4);
+            let mut painter = CountingPainter::new(50 << (2 * FP_POS), 4 << (2 * FP_POS));
+            $c.bench_function(&format!("{} {}x{}", name, $w, $h), |b| {
+                let mut frame = 0;
+                b.iter(|| {
+                    road.set((frame % 500) << FP_POS);
+                    road.render::<CountingPainter, $w, $h>(&mut painter, 0, 3000, MAX_Z, frame);
+                    frame += 1;
+                    black_box(painter.pixels)
+                })
+            });
+        }
+    };
+}
+
+fn render(c: &mut Criterion) {
+    bench_size!(c, 160, 120);
+    bench_size!(c, 320, 240);
+    bench_size!(c, 640, 480);
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
@@ -0,0 +1,33 @@
+// Drives down a short track in the terminal, drawn with characters.
+use poisjuoksu::ascii::{AsciiSink, AsciiStyle};
+use poisjuoksu::{RoadRenderer, Segment, StyledPainter, FP_POS};
+use std::{thread, time};
+
+const SCREEN_WIDTH: i32 = 78;
+const SCREEN_HEIGHT: i32 = 22;
+
+fn main() {
+    use poisjuoksu::SideInclination::*;
+    let segments = [
+        Segment::new((Flat, Flat), 100 << FP_POS, 0, 0),
+        Segment::new((Flat, Flat), 100 << FP_POS, -20, 0),
+        Segment::new((Flat, Uphill), 200 << FP_POS, 0, -1),
+        Segment::new((Downhill, Flat), 100 << FP_POS, 20, 2),
+        Segment::new((Wall, Wall), 200 << FP_POS, 5, 0),
+        Segment::new((Flat, Flat), 400 << FP_POS, 0, 0),
+    ];
+    let mut road = RoadRenderer::new(&segments, 16);
+    let style = AsciiStyle::default();
+    let mut buffer = [0u8; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize];
+
+    for frame in 0..600 {
+        let mut sink = AsciiSink::new(&mut buffer, SCREEN_WIDTH, SCREEN_HEIGHT, |c: &u8| *c).unwrap();
+        let mut painter = StyledPainter::new(&mut sink, &style);
+        road.render::<_, { SCREEN_WIDTH as usize }, { SCREEN_HEIGHT as usize }>(&mut painter, 0, 3000, 10000 * FP_POS, frame);
+        // Move the cursor to the top left corner before each frame.
+        print!("\x1b[H{}", sink);
+        road.advance(1 << FP_POS);
+        thread::sleep(time::Duration::from_millis(30));
+    }
+    println!();
+}
@@ -1,5 +1,4 @@
-use poisjuoksu::{Painter, RoadRenderer, Segment, FP_POS};
-use sdl2;
+use poisjuoksu::{markings, Painter, RoadRenderer, Segment, FP_POS};
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
@@ -38,7 +37,7 @@ impl<'a> Painter for SdlPainter<'a> {
         }
     }
 
-    fn sky_color(&self, y: i32) -> Self::ColorType {
+    fn sky_color(&self, y: i32, _frame: i32) -> Self::ColorType {
         let dither = if y & 0xF < 0x8 { y & 1 } else { 0 };
         let r = 16 - (y >> 4) + dither;
         let g = 19 - (y >> 4) + dither;
@@ -46,16 +45,16 @@ impl<'a> Painter for SdlPainter<'a> {
         ((r << 11) | (g << 5) | b) as u16
     }
 
-    fn road_color(&self, tx: i32, t: i32) -> Self::ColorType {
+    fn road_color(&self, tx: i32, t: i32, _frame: i32) -> Self::ColorType {
         let atx = if tx < 0 { -tx } else { tx };
-        if atx < ROAD_EDGE_X1 && atx >= ROAD_EDGE_X0 || atx < ROAD_LINE_WIDTH && (t & 0xFFF) < 0x800 {
+        if (ROAD_EDGE_X0..ROAD_EDGE_X1).contains(&atx) || atx < ROAD_LINE_WIDTH && markings::is_dash(t, 0x1000, 0x800) {
             ROAD_EDGE_COLOR
         } else {
             ROAD_COLOR
         }
     }
 
-    fn ground_color(&self, tx: i32, t: i32) -> Self::ColorType {
+    fn ground_color(&self, _tx: i32, t: i32, _frame: i32) -> Self::ColorType {
         if (t & 0x3FFF) < 0x2000 {
             GROUND_COLOR
         } else {
@@ -71,7 +70,7 @@ impl<'a> Painter for SdlPainter<'a> {
 fn main() -> Result<(), String> {
     let sdl_context = sdl2::init()?;
     let video = sdl_context.video()?;
-    let mut timer = sdl_context.timer()?;
+    let timer = sdl_context.timer()?;
 
     let window = video
         .window("Night Cruising", SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
@@ -145,6 +144,7 @@ fn main() -> Result<(), String> {
         )
         .map_err(|e| e.to_string())?;
 
+    let mut frame = 0;
     'mainloop: loop {
         for event in event_pump.poll_iter() {
             match event {
@@ -157,22 +157,10 @@ fn main() -> Result<(), String> {
             }
         }
         road.advance(1 << FP_POS);
+        frame += 1;
         let camera_x = (-10000.0 * f32::sin(timer.ticks() as f32 * 0.001)) as i32;
         let camera_y = 10000;
-        let mut x_px = 0;
-        let mut y_px = 0;
-        let mut inv_z = 0;
-        road.get_screen_pos(
-            (SCREEN_WIDTH, SCREEN_HEIGHT),
-            camera_x,
-            camera_y,
-            10000,
-            12800,
-            0,
-            &mut x_px,
-            &mut y_px,
-            &mut inv_z
-        );
+        let _pos = road.screen_pos((SCREEN_WIDTH, SCREEN_HEIGHT), camera_x, camera_y, 10000, 12800, 0);
         screen_buffer.with_lock(
             Rect::new(0, 0, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32),
             |pixels, pitch| {
@@ -185,14 +173,15 @@ fn main() -> Result<(), String> {
                     }
                 }
                 */
-                road.render::<SdlPainter, SCREEN_WIDTH, SCREEN_HEIGHT>(
+                road.render::<SdlPainter, { SCREEN_WIDTH as usize }, { SCREEN_HEIGHT as usize }>(
                     &mut painter,
                     camera_x,
                     camera_y,
-                    10000*FP_POS
+                    10000*FP_POS,
+                    frame
                 );
-                //if x_px >= 0 && x_px < 320 && y_px >= 0 && y_px < 240 {
-                //    painter.draw(x_px, y_px, &0xF00F);
+                //if _pos.x >= 0 && _pos.x < 320 && _pos.y >= 0 && _pos.y < 240 {
+                //    painter.draw(_pos.x, _pos.y, &0xF00F);
                 //}
                 //println!("{} vs {}", painter.count, SCREEN_WIDTH*SCREEN_HEIGHT);
             },
@@ -0,0 +1,74 @@
+// Drives down a track in the terminal. Left and right steer, up and down
+// change speed, q or Esc quits. Run with
+//
+//   cargo run --example terminal --features terminal
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::{cursor, execute, terminal};
+use poisjuoksu::terminal::{HalfBlockSink, TerminalStyle};
+use poisjuoksu::{RoadRenderer, Segment, StyledPainter, FP_POS};
+use std::io::{self, Write};
+use std::time::Duration;
+
+const SCREEN_WIDTH: i32 = 80;
+const SCREEN_HEIGHT: i32 = 46;
+
+fn run<W: Write>(out: &mut W) -> io::Result<()> {
+    use poisjuoksu::SideInclination::*;
+    let segments = [
+        Segment::new((Flat, Flat), 200 << FP_POS, 0, 0),
+        Segment::new((Flat, Flat), 100 << FP_POS, -20, 0),
+        Segment::new((Flat, Uphill), 300 << FP_POS, 0, -1),
+        Segment::new((Downhill, Uphill), 200 << FP_POS, 10, 1),
+        Segment::new((Wall, Wall), 300 << FP_POS, 0, 0),
+        Segment::new((Wall, Wall), 100 << FP_POS, 30, 0),
+        Segment::new((Flat, Downhill), 400 << FP_POS, -5, 2),
+        Segment::new((Flat, Flat), 600 << FP_POS, 0, 0),
+    ];
+    let length: i32 = segments.iter().map(|seg| seg.length).sum();
+    let mut road = RoadRenderer::new(&segments, 24);
+    let style = TerminalStyle::new(SCREEN_HEIGHT);
+    let mut sink = HalfBlockSink::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    let mut camera_x = 0;
+    let mut speed = 1 << FP_POS;
+    let mut frame = 0;
+
+    loop {
+        while event::poll(Duration::from_millis(0))? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Release {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Left => camera_x -= 4 << FP_POS,
+                    KeyCode::Right => camera_x += 4 << FP_POS,
+                    KeyCode::Up => speed = (speed + (1 << (FP_POS - 1))).min(8 << FP_POS),
+                    KeyCode::Down => speed = (speed - (1 << (FP_POS - 1))).max(0),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut painter = StyledPainter::new(&mut sink, &style);
+        road.render::<_, { SCREEN_WIDTH as usize }, { SCREEN_HEIGHT as usize }>(&mut painter, camera_x, 3000, 2000 << FP_POS, frame);
+        sink.present(out, 0, 0)?;
+
+        if road.position() + speed >= length {
+            road.set(0);
+        } else {
+            road.advance(speed);
+        }
+        frame += 1;
+        std::thread::sleep(Duration::from_millis(30));
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+    let result = run(&mut out);
+    execute!(out, cursor::Show, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
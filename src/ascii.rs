@@ -0,0 +1,139 @@
+// Renders frames as text, one character per pixel. Useful for readable
+// snapshots of the road geometry in tests and for previewing tracks in a
+// terminal. AsciiSink stores the characters in a caller-provided buffer, so
+// this works without std; print it with its Display implementation.
+use crate::{markings, PixelSink, RoadStyle, FP_POS};
+use core::fmt;
+
+// A PixelSink that stores characters. to_char converts the colors of the
+// RoadStyle it's used with; with AsciiStyle the colors already are
+// characters.
+pub struct AsciiSink<'b, C, F: Fn(&C) -> u8> {
+    chars: &'b mut [u8], // w * h characters, rows from top to bottom
+    w: i32,
+    h: i32,
+    to_char: F,
+    marker: core::marker::PhantomData<fn(&C)>,
+}
+
+impl<'b, C, F: Fn(&C) -> u8> AsciiSink<'b, C, F> {
+    // Returns None if the buffer is smaller than w * h.
+    pub fn new(buffer: &'b mut [u8], w: i32, h: i32, to_char: F) -> Option<Self> {
+        if w < 0 || h < 0 {
+            return None;
+        }
+        let chars = buffer.get_mut(..(w as usize) * (h as usize))?;
+        chars.fill(b' ');
+        Some(AsciiSink { chars, w, h, to_char, marker: core::marker::PhantomData })
+    }
+
+    pub fn row(&self, y: i32) -> &[u8] {
+        let start = (y * self.w) as usize;
+        &self.chars[start..start + self.w as usize]
+    }
+
+    pub fn clear(&mut self) {
+        self.chars.fill(b' ');
+    }
+}
+
+impl<'b, C, F: Fn(&C) -> u8> PixelSink for AsciiSink<'b, C, F> {
+    type ColorType = C;
+
+    fn draw(&mut self, x: i32, y: i32, color: &C) {
+        if x >= 0 && x < self.w && y >= 0 && y < self.h {
+            self.chars[(y * self.w + x) as usize] = (self.to_char)(color);
+        }
+    }
+
+    fn fill_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &C) {
+        if y >= 0 && y < self.h {
+            let c = (self.to_char)(color);
+            let start = (y * self.w) as usize;
+            let (begin, end) = (x_begin.max(0), x_end.min(self.w));
+            if begin < end {
+                self.chars[start + begin as usize..start + end as usize].fill(c);
+            }
+        }
+    }
+
+    fn copy_row(&mut self, y: i32, x_begin: i32, x_end: i32, src_y: i32, x_shift: i32) -> bool {
+        let (w, h) = (self.w, self.h);
+        if y >= 0 && y < h && src_y >= 0 && src_y < h {
+            for x in x_begin.max(0)..x_end.min(w) {
+                let sx = x + x_shift;
+                if sx >= 0 && sx < w {
+                    self.chars[(y * w + x) as usize] = self.chars[(src_y * w + sx) as usize];
+                }
+            }
+        }
+        true
+    }
+}
+
+// Rows separated by newlines, with no newline after the last one. Non-ASCII
+// bytes from to_char are shown as '?'.
+impl<'b, C, F: Fn(&C) -> u8> fmt::Display for AsciiSink<'b, C, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for y in 0..self.h {
+            if y > 0 {
+                f.write_str("\n")?;
+            }
+            for &c in self.row(y) {
+                let c = if c.is_ascii() { c as char } else { '?' };
+                fmt::Write::write_char(f, c)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// A RoadStyle whose colors are characters: blank sky, '#' for the road edge
+// lines, '|' for the dashed center line, '=' for the road, and ground in
+// alternating bands of '.' and ',' so that movement is visible.
+#[derive(Copy, Clone, Debug)]
+pub struct AsciiStyle {
+    pub road_width: i32, // FP2
+}
+
+impl Default for AsciiStyle {
+    fn default() -> Self {
+        AsciiStyle { road_width: 50 << (2 * FP_POS) }
+    }
+}
+
+impl RoadStyle for AsciiStyle {
+    type ColorType = u8;
+
+    fn sky_color(&self, _y: i32, _frame: i32) -> u8 {
+        b' '
+    }
+
+    fn road_color(&self, tx: i32, t: i32, _frame: i32) -> u8 {
+        let atx = tx.abs();
+        let line = 2 << (2 * FP_POS);
+        if atx >= self.road_width - 2 * line {
+            b'#'
+        } else if atx < line && markings::is_dash(t, 0x1000, 0x800) {
+            b'|'
+        } else {
+            b'='
+        }
+    }
+
+    fn ground_color(&self, _tx: i32, t: i32, _frame: i32) -> u8 {
+        if (t & 0x3FFF) < 0x2000 {
+            b'.'
+        } else {
+            b','
+        }
+    }
+
+    fn wall_color(&self, _t: i32, _height: i32, _frame: i32) -> u8 {
+        b'H'
+    }
+
+    fn road_width(&self) -> i32 {
+        self.road_width
+    }
+}
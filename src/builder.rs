@@ -0,0 +1,281 @@
+// Configures a RoadRenderer in one place and checks the settings before
+// anything is rendered with them.
+
+use crate::{
+    check_segments, markings::CatsEyes, RoadRenderer, Segment, SegmentIndex, SkyLayer, ValidationError, FP_POS,
+    MAX_NEAR,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    // The near plane must be positive and at most MAX_NEAR.
+    InvalidNear,
+    // The segment at this index doesn't have a positive length.
+    InvalidSegment(usize),
+    // The track would overflow the renderer, see check_segments().
+    Unrenderable(ValidationError),
+    // Row LOD needs a positive number of rows.
+    InvalidRowLod,
+    // The segment index was built for a different track.
+    IndexMismatch,
+    // The draw distance must be positive.
+    InvalidDrawDistance,
+    // Fog must start at a depth of zero or more and end past it.
+    InvalidFog,
+    // The roll can't tilt the view by more than one row per column.
+    InvalidRoll,
+}
+
+// Default draw distance of the builder, FP1. About as far as the examples
+// draw, and the same distance whatever FP_POS is.
+pub const DEFAULT_DRAW_DISTANCE: i32 = 312 << FP_POS;
+
+pub struct RoadRendererBuilder<'a> {
+    segments: &'a [Segment],
+    near: i32,
+    look: (i32, i32),
+    max_rows: Option<i32>,
+    row_lod: Option<(i32, i32)>,
+    horizon_fade: i32,
+    sky_layers: &'a [SkyLayer],
+    cats_eyes: Option<CatsEyes<'a>>,
+    index: Option<&'a SegmentIndex<'a>>,
+    start_t: i32,
+    looping: bool,
+    t_period: i32,
+    camera: (i32, i32),
+    draw_distance: i32,
+    roll: i32,
+    fog: Option<(i32, i32)>,
+    #[cfg(feature = "motion-blur")]
+    motion_blur: (i32, i32),
+}
+
+impl<'a> RoadRendererBuilder<'a> {
+    // The near plane and draw distance default to the ones of the example,
+    // and everything else to the defaults of RoadRenderer::new().
+    pub fn new(segments: &'a [Segment]) -> Self {
+        RoadRendererBuilder {
+            segments,
+            near: 32,
+            look: (0, 0),
+            max_rows: None,
+            row_lod: None,
+            horizon_fade: 0,
+            sky_layers: &[],
+            cats_eyes: None,
+            index: None,
+            start_t: 0,
+            looping: false,
+            t_period: 0,
+            camera: (0, 0),
+            draw_distance: DEFAULT_DRAW_DISTANCE,
+            roll: 0,
+            fog: None,
+            #[cfg(feature = "motion-blur")]
+            motion_blur: (0, 0),
+        }
+    }
+
+    // Near plane, practically controls the field of view.
+    pub fn near(mut self, near: i32) -> Self {
+        self.near = near;
+        self
+    }
+
+    // Moves the horizon up by the given number of rows, see
+    // RoadRenderer::set_look_offset().
+    pub fn horizon_offset(mut self, rows: i32) -> Self {
+        self.look.1 = rows;
+        self
+    }
+
+    pub fn look_offset(mut self, x: i32, y: i32) -> Self {
+        self.look = (x, y);
+        self
+    }
+
+    pub fn max_rows(mut self, rows: Option<i32>) -> Self {
+        self.max_rows = rows;
+        self
+    }
+
+    pub fn row_lod(mut self, z: i32, rows: i32) -> Self {
+        self.row_lod = Some((z, rows));
+        self
+    }
+
+    // See RoadRenderer::set_horizon_fade().
+    pub fn horizon_fade(mut self, rows: i32) -> Self {
+        self.horizon_fade = rows;
+        self
+    }
+
+    // See RoadRenderer::set_sky_layers().
+    pub fn sky_layers(mut self, layers: &'a [SkyLayer]) -> Self {
+        self.sky_layers = layers;
+        self
+    }
+
+    // See RoadRenderer::set_cats_eyes().
+    pub fn cats_eyes(mut self, cats_eyes: CatsEyes<'a>) -> Self {
+        self.cats_eyes = Some(cats_eyes);
+        self
+    }
+
+    pub fn segment_index(mut self, index: &'a SegmentIndex<'a>) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    // Distance from the start of the road to place the camera at.
+    pub fn start(mut self, t: i32) -> Self {
+        self.start_t = t;
+        self
+    }
+
+    // See RoadRenderer::set_looping().
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    // See RoadRenderer::set_t_period(), FP1.
+    pub fn t_period(mut self, period: i32) -> Self {
+        self.t_period = period;
+        self
+    }
+
+    // See RoadRenderer::set_camera().
+    pub fn camera(mut self, x_offset: i32, y_offset: i32) -> Self {
+        self.camera = (x_offset, y_offset);
+        self
+    }
+
+    // See RoadRenderer::set_draw_distance(), FP1.
+    pub fn draw_distance(mut self, max_z: i32) -> Self {
+        self.draw_distance = max_z;
+        self
+    }
+
+    // See RoadRenderer::set_roll(), FP1.
+    pub fn roll(mut self, slope: i32) -> Self {
+        self.roll = slope;
+        self
+    }
+
+    // See RoadRenderer::set_fog(), FP1.
+    pub fn fog(mut self, start: i32, end: i32) -> Self {
+        self.fog = Some((start, end));
+        self
+    }
+
+    #[cfg(feature = "motion-blur")]
+    pub fn motion_blur(mut self, threshold: i32, rows: i32) -> Self {
+        self.motion_blur = (threshold, rows);
+        self
+    }
+
+    pub fn build(self) -> Result<RoadRenderer<'a>, BuildError> {
+        if self.near <= 0 || self.near > MAX_NEAR {
+            return Err(BuildError::InvalidNear);
+        }
+        match check_segments(self.segments) {
+            Ok(()) => {}
+            Err(ValidationError::BadLength(i)) => return Err(BuildError::InvalidSegment(i)),
+            Err(err) => return Err(BuildError::Unrenderable(err)),
+        }
+        if let Some(index) = self.index {
+            if !index.is_for(self.segments) {
+                return Err(BuildError::IndexMismatch);
+            }
+        }
+        if self.draw_distance <= 0 {
+            return Err(BuildError::InvalidDrawDistance);
+        }
+        if let Some((start, end)) = self.fog {
+            if start < 0 || end <= start {
+                return Err(BuildError::InvalidFog);
+            }
+        }
+        if self.roll.abs() > 1 << FP_POS {
+            return Err(BuildError::InvalidRoll);
+        }
+        let mut road = RoadRenderer::new(self.segments, self.near);
+        road.set_look_offset(self.look.0, self.look.1);
+        road.set_max_rows(self.max_rows);
+        if let Some((z, rows)) = self.row_lod {
+            if rows < 1 {
+                return Err(BuildError::InvalidRowLod);
+            }
+            road.set_row_lod(z, rows);
+        }
+        road.set_horizon_fade(self.horizon_fade);
+        road.set_sky_layers(self.sky_layers);
+        road.set_cats_eyes(self.cats_eyes);
+        road.set_segment_index(self.index);
+        #[cfg(feature = "motion-blur")]
+        road.set_motion_blur(self.motion_blur.0, self.motion_blur.1);
+        road.set_looping(self.looping);
+        road.set_t_period(self.t_period);
+        road.set_camera(self.camera.0, self.camera.1);
+        road.set_draw_distance(self.draw_distance);
+        road.set_roll(self.roll);
+        road.set_fog(self.fog);
+        road.set(self.start_t);
+        Ok(road)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SideInclination::Flat;
+
+    const TRACK: [Segment; 2] = [
+        Segment::new((Flat, Flat), 10 << FP_POS, 0, 0),
+        Segment::new((Flat, Flat), 20 << FP_POS, 3, -1),
+    ];
+
+    #[test]
+    fn rejects_what_try_new_rejects() {
+        let zero = [TRACK[0], Segment::new((Flat, Flat), 0, 0, 0)];
+        let sharp = [TRACK[0], Segment::new((Flat, Flat), 4000 << FP_POS, i32::MAX / 2, 0)];
+        let long = [Segment::new((Flat, Flat), i32::MAX / 2 + 1, 0, 0); 2];
+        assert!(RoadRenderer::try_new(&zero, 32).is_err());
+        assert!(matches!(RoadRendererBuilder::new(&zero).build(), Err(BuildError::InvalidSegment(1))));
+        assert!(RoadRenderer::try_new(&sharp, 32).is_err());
+        assert!(matches!(
+            RoadRendererBuilder::new(&sharp).build(),
+            Err(BuildError::Unrenderable(ValidationError::CurveTooSharp(1)))
+        ));
+        assert!(matches!(
+            RoadRendererBuilder::new(&long).build(),
+            Err(BuildError::Unrenderable(ValidationError::TooLong))
+        ));
+        assert!(RoadRendererBuilder::new(&TRACK).build().is_ok());
+    }
+
+    #[test]
+    fn near_is_limited() {
+        for near in [0, -1, MAX_NEAR + 1] {
+            assert!(matches!(RoadRendererBuilder::new(&TRACK).near(near).build(), Err(BuildError::InvalidNear)));
+            assert_eq!(RoadRenderer::try_new(&TRACK, near).err(), Some(ValidationError::InvalidNear));
+        }
+        assert!(RoadRendererBuilder::new(&TRACK).near(MAX_NEAR).build().is_ok());
+        assert!(RoadRenderer::try_new(&TRACK, MAX_NEAR).is_ok());
+    }
+
+    #[test]
+    fn fog_and_roll_are_checked() {
+        let unit = 1 << FP_POS;
+        for (start, end) in [(-1, 10), (10, 10), (10, 5)] {
+            assert!(matches!(RoadRendererBuilder::new(&TRACK).fog(start, end).build(), Err(BuildError::InvalidFog)));
+        }
+        for roll in [unit + 1, -unit - 1] {
+            assert!(matches!(RoadRendererBuilder::new(&TRACK).roll(roll).build(), Err(BuildError::InvalidRoll)));
+        }
+        let road = RoadRendererBuilder::new(&TRACK).fog(0, 50 * unit).roll(-unit).build();
+        assert!(road.is_ok());
+    }
+}
@@ -0,0 +1,172 @@
+// Captures rendered frames into RGB images, for screenshots and reference
+// images. PPM is always available, PNG with the png feature. The PNG encoder
+// stores the image uncompressed, so it needs no dependencies.
+use crate::diff::{self, FrameDiff};
+use crate::{Painter, FP_POS};
+use std::io::{self, Write};
+use std::vec;
+use std::vec::Vec;
+
+// Wraps a painter and keeps a copy of everything drawn through it. to_rgb
+// converts the painter's colors.
+pub struct Capture<'p, P: Painter, F: Fn(&P::ColorType) -> [u8; 3]> {
+    pub painter: &'p mut P,
+    to_rgb: F,
+    w: i32,
+    h: i32,
+    pixels: Vec<u8>, // RGB, rows from top to bottom
+}
+
+impl<'p, P: Painter, F: Fn(&P::ColorType) -> [u8; 3]> Capture<'p, P, F> {
+    pub fn new(painter: &'p mut P, w: i32, h: i32, to_rgb: F) -> Self {
+        Capture {
+            painter,
+            to_rgb,
+            w,
+            h,
+            pixels: vec![0; (w * h * 3) as usize],
+        }
+    }
+
+    // Returns the captured image, three bytes per pixel.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    // Compares the captured image with another one of the same size, e.g. a
+    // golden image loaded from disk, see the diff module.
+    pub fn diff(&self, other: &[u8]) -> Option<FrameDiff> {
+        diff::diff(&self.pixels, other, self.w, 3)
+    }
+
+    pub fn write_ppm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.w, self.h)?;
+        out.write_all(&self.pixels)
+    }
+
+    #[cfg(feature = "png")]
+    pub fn write_png<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        png::write(out, self.w as u32, self.h as u32, &self.pixels)
+    }
+}
+
+impl<'p, P: Painter, F: Fn(&P::ColorType) -> [u8; 3]> Painter for Capture<'p, P, F> {
+    type ColorType = P::ColorType;
+
+    fn draw(&mut self, x: i32, y: i32, color: &Self::ColorType) {
+        if x >= 0 && x < self.w && y >= 0 && y < self.h {
+            let i = ((y * self.w + x) * 3) as usize;
+            self.pixels[i..i + 3].copy_from_slice(&(self.to_rgb)(color));
+        }
+        self.painter.draw(x, y, color);
+    }
+
+    forward_painter_colors!(painter);
+
+    fn fill_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType) {
+        if y >= 0 && y < self.h {
+            let rgb = (self.to_rgb)(color);
+            for x in x_begin.max(0)..x_end.min(self.w) {
+                let i = ((y * self.w + x) * 3) as usize;
+                self.pixels[i..i + 3].copy_from_slice(&rgb);
+            }
+        }
+        self.painter.fill_span(x_begin, x_end, y, color);
+    }
+
+    // The captured pixels are blended in RGB, regardless of how the wrapped
+    // painter blends.
+    fn blend_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType, amount: i32) {
+        if y >= 0 && y < self.h {
+            let rgb = (self.to_rgb)(color);
+            let amount = amount.clamp(0, 1 << FP_POS);
+            for x in x_begin.max(0)..x_end.min(self.w) {
+                let i = ((y * self.w + x) * 3) as usize;
+                for (c, &b) in self.pixels[i..i + 3].iter_mut().zip(rgb.iter()) {
+                    *c = ((*c as i32 * ((1 << FP_POS) - amount) + b as i32 * amount) >> FP_POS) as u8;
+                }
+            }
+        }
+        self.painter.blend_span(x_begin, x_end, y, color, amount);
+    }
+
+    fn begin_road_row(&mut self, y: i32, left: i32, right: i32, t: i32) {
+        self.painter.begin_road_row(y, left, right, t);
+    }
+
+    fn copy_row(&mut self, y: i32, x_begin: i32, x_end: i32, src_y: i32, x_shift: i32) -> bool {
+        if !self.painter.copy_row(y, x_begin, x_end, src_y, x_shift) {
+            return false;
+        }
+        let (w, h) = (self.w, self.h);
+        if y >= 0 && y < h && src_y >= 0 && src_y < h {
+            for x in x_begin.max(0)..x_end.min(w) {
+                let sx = x + x_shift;
+                if sx >= 0 && sx < w {
+                    let (d, s) = (((y * w + x) * 3) as usize, ((src_y * w + sx) * 3) as usize);
+                    self.pixels.copy_within(s..s + 3, d);
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(feature = "png")]
+mod png {
+    use crate::track::crc32;
+    use std::io::{self, Write};
+
+    fn write_chunk<W: Write>(out: &mut W, id: &[u8; 4], data: &[u8]) -> io::Result<()> {
+        out.write_all(&(data.len() as u32).to_be_bytes())?;
+        out.write_all(id)?;
+        out.write_all(data)?;
+        let crc = crc32(crc32(0, id), data);
+        out.write_all(&crc.to_be_bytes())
+    }
+
+    // Writes an 8-bit RGB PNG. The zlib stream uses stored deflate blocks,
+    // which are at most 65535 bytes each.
+    pub fn write<W: Write>(out: &mut W, w: u32, h: u32, rgb: &[u8]) -> io::Result<()> {
+        out.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+        let mut header = [0u8; 13];
+        header[0..4].copy_from_slice(&w.to_be_bytes());
+        header[4..8].copy_from_slice(&h.to_be_bytes());
+        header[8] = 8; // Bit depth
+        header[9] = 2; // RGB
+        write_chunk(out, b"IHDR", &header)?;
+
+        // Each row starts with filter type 0 (none).
+        let stride = w as usize * 3;
+        let mut raw = std::vec::Vec::with_capacity((stride + 1) * h as usize);
+        for row in rgb.chunks(stride).take(h as usize) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+
+        let mut zlib = std::vec::Vec::with_capacity(raw.len() + raw.len() / 65535 * 5 + 11);
+        zlib.extend_from_slice(&[0x78, 0x01]);
+        let mut blocks = raw.chunks(65535).peekable();
+        if blocks.peek().is_none() {
+            zlib.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+        }
+        while let Some(block) = blocks.next() {
+            let len = block.len() as u16;
+            zlib.push(blocks.peek().is_none() as u8);
+            zlib.extend_from_slice(&len.to_le_bytes());
+            zlib.extend_from_slice(&(!len).to_le_bytes());
+            zlib.extend_from_slice(block);
+        }
+        // Adler-32
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in &raw {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        zlib.extend_from_slice(&((b << 16) | a).to_be_bytes());
+        write_chunk(out, b"IDAT", &zlib)?;
+
+        write_chunk(out, b"IEND", &[])
+    }
+}
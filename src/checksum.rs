@@ -0,0 +1,203 @@
+// Checksums of rendered frames, for checking that replays and lockstep games
+// render identically on every target.
+//
+// The renderer only uses i32/i64 arithmetic, whose results Rust defines the
+// same way everywhere: >> of a negative value is an arithmetic shift (rounds
+// towards negative infinity), / rounds towards zero, and usize is only used
+// for indexing. So the same inputs produce the same pixels on desktop and on
+// a microcontroller, and this checksum can be compared between them.
+
+use core::hash::{Hash, Hasher};
+
+use crate::track::crc32;
+use crate::Painter;
+
+// CRC-32 Hasher that writes integers as little-endian, and usize as 64-bit.
+// The default Hasher methods use the native byte order and width, which
+// would make the checksum depend on the target.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct CrcHasher {
+    pub crc: u32,
+}
+
+impl Hasher for CrcHasher {
+    fn finish(&self) -> u64 {
+        self.crc as u64
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.crc = crc32(self.crc, bytes);
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as i64 as u64);
+    }
+}
+
+// Wraps a painter and hashes every pixel drawn through it. Spans are hashed
+// pixel by pixel, so the checksum doesn't depend on whether the painter
+// overrides fill_span(). Rows are never copied with copy_row(), so that every
+// pixel goes through the hash.
+pub struct FrameChecksum<'p, P: Painter> {
+    pub painter: &'p mut P,
+    pub hasher: CrcHasher,
+}
+
+impl<'p, P: Painter> FrameChecksum<'p, P> {
+    pub fn new(painter: &'p mut P) -> Self {
+        FrameChecksum { painter, hasher: CrcHasher::default() }
+    }
+
+    pub fn checksum(&self) -> u32 {
+        self.hasher.crc
+    }
+}
+
+impl<'p, P: Painter> Painter for FrameChecksum<'p, P>
+where
+    P::ColorType: Hash,
+{
+    type ColorType = P::ColorType;
+
+    fn draw(&mut self, x: i32, y: i32, color: &Self::ColorType) {
+        x.hash(&mut self.hasher);
+        y.hash(&mut self.hasher);
+        color.hash(&mut self.hasher);
+        self.painter.draw(x, y, color);
+    }
+
+    forward_painter_colors!(painter);
+
+    fn fill_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType) {
+        for x in x_begin..x_end {
+            x.hash(&mut self.hasher);
+            y.hash(&mut self.hasher);
+            color.hash(&mut self.hasher);
+        }
+        self.painter.fill_span(x_begin, x_end, y, color);
+    }
+
+    fn blend_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType, amount: i32) {
+        for x in x_begin..x_end {
+            x.hash(&mut self.hasher);
+            y.hash(&mut self.hasher);
+            color.hash(&mut self.hasher);
+            amount.hash(&mut self.hasher);
+        }
+        self.painter.blend_span(x_begin, x_end, y, color, amount);
+    }
+
+    fn begin_road_row(&mut self, y: i32, left: i32, right: i32, t: i32) {
+        self.painter.begin_road_row(y, left, right, t);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hasher_is_standard_crc32() {
+        let mut hasher = CrcHasher::default();
+        hasher.write(b"1234");
+        hasher.write(b"56789");
+        assert_eq!(hasher.finish(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn integers_are_little_endian() {
+        let mut a = CrcHasher::default();
+        a.write_i32(0x0403_0201);
+        a.write_usize(5);
+        let mut b = CrcHasher::default();
+        b.write(&[1, 2, 3, 4, 5, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(a.crc, b.crc);
+    }
+
+    // Colors from the painter's inputs, so that a change in where the
+    // renderer samples the road shows up in the checksum. Sky, road, ground
+    // and walls are told apart by the top bits.
+    struct Inputs;
+
+    impl Painter for Inputs {
+        type ColorType = u32;
+
+        fn draw(&mut self, _x: i32, _y: i32, _color: &u32) {}
+
+        fn sky_color(&self, y: i32, _frame: i32) -> u32 {
+            y as u32
+        }
+
+        fn road_color(&self, tx: i32, t: i32, _frame: i32) -> u32 {
+            0x1000_0000 | ((tx >> crate::FP_POS) as u32 & 0xFFF) << 12 | ((t >> crate::FP_POS) as u32 & 0xFFF)
+        }
+
+        fn ground_color(&self, tx: i32, t: i32, _frame: i32) -> u32 {
+            0x2000_0000 | ((tx >> (2 * crate::FP_POS)) as u32 & 0xFFF) << 12 | ((t >> crate::FP_POS) as u32 & 0xFFF)
+        }
+
+        fn wall_color(&self, t: i32, height: i32, _frame: i32) -> u32 {
+            0x3000_0000 | ((height >> crate::FP_POS) as u32 & 0xFFF) << 12 | ((t >> crate::FP_POS) as u32 & 0xFFF)
+        }
+
+        fn road_width(&self) -> i32 {
+            3 << (2 * crate::FP_POS)
+        }
+    }
+
+    #[test]
+    fn pinned_frame() {
+        let track = crate::track! {
+            straight 20;
+            curve left 3 for 40, crest 2;
+            curve right 5 for 30, dip 3, wall/flat;
+            straight 20, uphill/downhill;
+            curve left 2 for 60, dip 1;
+        };
+        let mut road = crate::RoadRenderer::new(&track, 32);
+        let mut painter = Inputs;
+        let mut checksums = [0; 3];
+        for (i, t) in [0, 35, 70].iter().enumerate() {
+            road.set(t << crate::FP_POS);
+            let mut checksum = FrameChecksum::new(&mut painter);
+            road.render::<_, 64, 48>(&mut checksum, 0, 1 << crate::FP_POS, 100 << crate::FP_POS, 0);
+            checksums[i] = checksum.checksum();
+        }
+        // The same on every target and with every math backend, only the
+        // precision changes the rounding.
+        let expected = match crate::FP_POS {
+            4 => [4040069145, 728375639, 935628398],
+            8 => [2417769492, 1086260851, 3610452733],
+            _ => [4265657231, 16043615, 3538693262],
+        };
+        assert_eq!(checksums, expected);
+    }
+}
@@ -0,0 +1,77 @@
+// A painter that draws nothing and counts what it was asked to draw, for
+// benchmarking the renderer without a display (see benches/render.rs) and
+// for checking how much work a frame takes.
+use crate::Painter;
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CountingPainter {
+    pub road_width: i32, // FP2
+    // Road colors change every band_width (FP2) across the road, so that
+    // fill_span() is used like with a painted lane. Zero colors every pixel
+    // separately.
+    pub band_width: i32,
+    pub pixels: u32,      // Pixels drawn, including those in spans
+    pub spans: u32,       // fill_span() calls
+    pub copied_rows: u32, // copy_row() calls
+}
+
+impl CountingPainter {
+    pub fn new(road_width: i32, band_width: i32) -> Self {
+        CountingPainter {
+            road_width,
+            band_width,
+            ..Default::default()
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.pixels = 0;
+        self.spans = 0;
+        self.copied_rows = 0;
+    }
+}
+
+impl Painter for CountingPainter {
+    type ColorType = u8;
+
+    fn draw(&mut self, _x: i32, _y: i32, _color: &u8) {
+        self.pixels += 1;
+    }
+
+    fn sky_color(&self, y: i32, _frame: i32) -> u8 {
+        y as u8
+    }
+
+    fn road_color(&self, tx: i32, t: i32, _frame: i32) -> u8 {
+        let band = if self.band_width > 0 { tx.div_euclid(self.band_width) } else { tx };
+        (band ^ (t >> 12)) as u8
+    }
+
+    fn ground_color(&self, _tx: i32, t: i32, _frame: i32) -> u8 {
+        (t >> 12) as u8
+    }
+
+    fn road_width(&self) -> i32 {
+        self.road_width
+    }
+
+    fn road_color_span(&self, tx: i32, t: i32, frame: i32) -> (u8, i32) {
+        let run = if self.band_width > 0 {
+            self.band_width - 1 - tx.rem_euclid(self.band_width)
+        } else {
+            0
+        };
+        (self.road_color(tx, t, frame), run)
+    }
+
+    fn fill_span(&mut self, x_begin: i32, x_end: i32, _y: i32, _color: &u8) {
+        self.spans += 1;
+        self.pixels += (x_end - x_begin).max(0) as u32;
+    }
+
+    fn copy_row(&mut self, _y: i32, x_begin: i32, x_end: i32, _src_y: i32, _x_shift: i32) -> bool {
+        self.copied_rows += 1;
+        self.pixels += (x_end - x_begin).max(0) as u32;
+        true
+    }
+}
@@ -0,0 +1,55 @@
+// Compares rendered frames, e.g. a capture against a golden image, or an
+// optimized painter against a simple reference one. Frames are slices of
+// pixel_size values per pixel, rows from top to bottom, like the pixels of
+// capture::Capture (pixel_size 3) or a buffer of one color per pixel
+// (pixel_size 1).
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameDiff {
+    pub differing: usize, // Number of pixels that differ
+    // Smallest rectangle (x0, y0, x1, y1) containing the differing pixels,
+    // x1 and y1 exclusive. None if the frames are equal.
+    pub bounds: Option<(i32, i32, i32, i32)>,
+}
+
+impl FrameDiff {
+    pub fn is_equal(&self) -> bool {
+        self.differing == 0
+    }
+}
+
+// Compares two frames of width w. Returns None if their sizes differ.
+pub fn diff<T: PartialEq>(a: &[T], b: &[T], w: i32, pixel_size: usize) -> Option<FrameDiff> {
+    if a.len() != b.len() || w <= 0 {
+        return None;
+    }
+    let size = pixel_size.max(1);
+    let mut result = FrameDiff::default();
+    for (i, (pa, pb)) in a.chunks_exact(size).zip(b.chunks_exact(size)).enumerate() {
+        if pa != pb {
+            let (x, y) = (i as i32 % w, i as i32 / w);
+            result.differing += 1;
+            result.bounds = Some(match result.bounds {
+                Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x + 1), y1.max(y + 1)),
+                None => (x, y, x + 1, y + 1),
+            });
+        }
+    }
+    Some(result)
+}
+
+// Writes a mask with one value per pixel, 0xFF where the frames differ and 0
+// elsewhere, and returns the number of differing pixels. Returns None if the
+// frame sizes differ or the mask is too small.
+pub fn diff_mask<T: PartialEq>(a: &[T], b: &[T], pixel_size: usize, mask: &mut [u8]) -> Option<usize> {
+    let size = pixel_size.max(1);
+    if a.len() != b.len() || mask.len() < a.len() / size {
+        return None;
+    }
+    let mut differing = 0;
+    for (m, (pa, pb)) in mask.iter_mut().zip(a.chunks_exact(size).zip(b.chunks_exact(size))) {
+        *m = if pa != pb { 0xFF } else { 0 };
+        differing += (pa != pb) as usize;
+    }
+    Some(differing)
+}
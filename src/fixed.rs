@@ -0,0 +1,71 @@
+// A road that owns its segments in a fixed-size array, so that it can be a
+// static or an RTIC resource without a borrowed track to go with it, and
+// still be edited at runtime, e.g. from a live track editor. The renderer
+// borrows its track, so one is built around the segments for each use, and
+// the camera is kept here in between.
+//
+//     static mut ROAD: FixedRoad<64> = FixedRoad::new([SEGMENT; 64], 64, 32);
+//     ...
+//     ROAD.with_renderer(|renderer| {
+//         renderer.advance(speed);
+//         renderer.render::<_, 160, 128>(&mut painter, 0, 3000, max_z, frame);
+//     });
+use crate::track::{self, TrackError, TrackPatch};
+use crate::{CameraState, RoadRenderer, Segment};
+
+pub struct FixedRoad<const N: usize> {
+    segments: [Segment; N],
+    len: usize,         // Number of segments in use, the rest is room for edits
+    pub near: i32,      // See RoadRenderer::new()
+    pub looping: bool,  // See RoadRenderer::set_looping()
+    camera: CameraState,
+}
+
+impl<const N: usize> FixedRoad<N> {
+    // Uses the first len segments, at most N.
+    pub const fn new(segments: [Segment; N], len: usize, near: i32) -> Self {
+        FixedRoad {
+            segments,
+            len: if len < N { len } else { N },
+            near,
+            looping: false,
+            camera: CameraState { t: 0, t_origin: 0, look: (0, 0), speed: 0 },
+        }
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments[..self.len]
+    }
+
+    // Changes to the segments show up from the next with_renderer().
+    pub fn segments_mut(&mut self) -> &mut [Segment] {
+        &mut self.segments[..self.len]
+    }
+
+    // See track::apply_patch().
+    pub fn patch(&mut self, patch: &TrackPatch) -> Result<(), TrackError> {
+        track::apply_patch(&mut self.segments, &mut self.len, patch)
+    }
+
+    pub fn camera(&self) -> CameraState {
+        self.camera
+    }
+
+    pub fn set_camera(&mut self, camera: CameraState) {
+        self.camera = camera;
+    }
+
+    // Builds a renderer of the segments with the kept camera, and keeps the
+    // camera it's left with. The renderer is gone afterwards, so buffers and
+    // settings other than the ones kept here must be attached in f, and
+    // buffers must be statics. Finding the camera's segment again walks the
+    // track, which is cheap next to rendering a frame.
+    pub fn with_renderer<R>(&mut self, f: impl FnOnce(&mut RoadRenderer<'_>) -> R) -> R {
+        let mut renderer = RoadRenderer::new(&self.segments[..self.len], self.near);
+        renderer.set_looping(self.looping);
+        renderer.restore_camera(self.camera);
+        let result = f(&mut renderer);
+        self.camera = renderer.camera_state();
+        result
+    }
+}
@@ -0,0 +1,297 @@
+// Conversions from other representations of roads into segments.
+use crate::{
+    check_segments, integrate_segment, track::MACRO_MAX_LENGTH, Segment, SideInclination, FP_POS,
+};
+
+// Largest y_curve considered when fitting elevation.
+const MAX_Y_CURVE: i32 = 255;
+// Longest segment written, FP1. The same limit as in track!().
+const MAX_LENGTH: i32 = MACRO_MAX_LENGTH << FP_POS;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImportError {
+    // The output buffer ran out before the whole input was converted.
+    BufferTooSmall,
+    // The sample interval must be positive.
+    BadInterval,
+}
+
+// How far the road drops (FP1) over length units, starting with slope y_slope.
+fn drop_over(y_curve: i32, y_slope: i32, length: i32) -> (i32, i32) {
+    let mut x_offset = 0;
+    let mut y_offset = 0;
+    let mut z_offset = 0;
+    let mut x_slope = 0;
+    let mut slope = y_slope;
+    integrate_segment(
+        0,
+        y_curve,
+        length,
+        &mut x_offset,
+        &mut y_offset,
+        &mut z_offset,
+        &mut x_slope,
+        &mut slope,
+    );
+    (y_offset, slope)
+}
+
+// Binary search for the y_curve at which f stops being below target. f must
+// grow with y_curve, at least roughly.
+fn search_y_curve<F: Fn(i32) -> i32>(f: F, target: i32) -> i32 {
+    let mut lo = -MAX_Y_CURVE;
+    let mut hi = MAX_Y_CURVE;
+    while lo < hi {
+        let mid = (lo + hi) >> 1;
+        if f(mid) < target {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+// Extends last by length, unless the joined segment would be too long or fail
+// check_segments(). Returns whether they were joined.
+fn join(last: &mut Segment, length: i32) -> bool {
+    let joined = match last.length.checked_add(length) {
+        Some(length) if length <= MAX_LENGTH => Segment { length, ..*last },
+        _ => return false,
+    };
+    if check_segments(core::slice::from_ref(&joined)).is_err() {
+        return false;
+    }
+    *last = joined;
+    true
+}
+
+// Finds the y_curve that best reaches both target drop and target slope at
+// the end of length. Matching only the drop makes consecutive segments
+// overshoot the slope in alternating directions, so both are weighed, the
+// slope error by how much height it costs over interval.
+fn fit_y_curve(y_slope: i32, length: i32, interval: i32, target: i32, target_slope: i32) -> i32 {
+    let by_drop = search_y_curve(|c| drop_over(c, y_slope, length).0, target);
+    let by_slope = search_y_curve(|c| drop_over(c, y_slope, length).1, target_slope);
+    let cost = |c: i32| {
+        let (d, slope) = drop_over(c, y_slope, length);
+        (d - target).abs() + (((slope - target_slope).abs() * interval) >> FP_POS)
+    };
+    let mut best = by_drop;
+    for c in by_drop.min(by_slope) - 1..=by_drop.max(by_slope) + 1 {
+        if c.abs() <= MAX_Y_CURVE && cost(c) < cost(best) {
+            best = c;
+        }
+    }
+    best
+}
+
+// Approximates a height profile with y_curve segments. heights has one sample
+// (FP1, up is positive) every interval units of t, starting from t = 0. A
+// segment is extended for as long as the rendered road stays within tolerance
+// of all samples it covers, up to the length limit of track!(). The segments
+// are straight and use side_style.
+// Returns the number of segments written to out.
+pub fn elevation_from_heights(
+    heights: &[i32],
+    interval: i32,
+    tolerance: i32,
+    side_style: (SideInclination, SideInclination),
+    out: &mut [Segment],
+) -> Result<usize, ImportError> {
+    if interval <= 0 {
+        return Err(ImportError::BadInterval);
+    }
+    if heights.len() < 2 {
+        return Ok(0);
+    }
+
+    // The renderer measures drop relative to the camera, i.e. down is
+    // positive.
+    let target = |i: usize| heights[0] - heights[i];
+    // Slope of the profile at sample i, FP1.
+    let target_slope = |i: usize| {
+        let a = if i > 0 { i - 1 } else { i };
+        let b = (i + 1).min(heights.len() - 1);
+        ((target(b) - target(a)) << FP_POS) / ((b - a) as i32 * interval)
+    };
+    let mut count = 0;
+    let mut start = 0;
+    let mut drop = 0;
+    let mut y_slope = 0;
+
+    while start + 1 < heights.len() {
+        let mut best_end = start + 1;
+        let mut best_curve = fit_y_curve(
+            y_slope,
+            interval,
+            interval,
+            target(best_end) - drop,
+            target_slope(best_end),
+        );
+
+        for end in start + 2..heights.len() {
+            let length = match ((end - start) as i32).checked_mul(interval) {
+                Some(length) if length <= MAX_LENGTH => length,
+                _ => break,
+            };
+            let curve = fit_y_curve(
+                y_slope,
+                length,
+                interval,
+                target(end) - drop,
+                target_slope(end),
+            );
+            let fits = (start + 1..=end).all(|i| {
+                let d = drop_over(curve, y_slope, (i - start) as i32 * interval).0;
+                (drop + d - target(i)).abs() <= tolerance
+            });
+            if !fits {
+                break;
+            }
+            best_end = end;
+            best_curve = curve;
+        }
+
+        let length = (best_end - start) as i32 * interval;
+        // Straight pieces can be joined without changing the result.
+        let joined = best_curve == 0
+            && count > 0
+            && out[count - 1].y_curve == 0
+            && join(&mut out[count - 1], length);
+        if !joined {
+            if count >= out.len() {
+                return Err(ImportError::BufferTooSmall);
+            }
+            out[count] = Segment::new(side_style, length, 0, best_curve);
+            count += 1;
+        }
+
+        let (d, slope) = drop_over(best_curve, y_slope, length);
+        drop += d;
+        y_slope = slope;
+        start = best_end;
+    }
+    Ok(count)
+}
+
+// Converts a polyline of (latitude, longitude) points in degrees, e.g. a GPX
+// trace of a real road, into x_curve segments. scale is the number of world
+// units per meter. The turn at each point is spread over the two edges next to
+// it, so the road bends smoothly instead of having kinks. Edges that end up
+// with the same x_curve are joined while the segment stays renderable. The
+// road is flat and uses side_style throughout.
+#[cfg(feature = "std")]
+pub fn segments_from_polyline(
+    points: &[(f64, f64)],
+    scale: f64,
+    side_style: (SideInclination, SideInclination),
+) -> std::vec::Vec<Segment> {
+    use std::vec::Vec;
+
+    const EARTH_RADIUS: f64 = 6_371_000.0;
+
+    // Equirectangular projection around the first point is accurate enough
+    // for the length of a stage.
+    let (lat0, _) = match points.first() {
+        Some(&p) => p,
+        None => return Vec::new(),
+    };
+    let cos_lat0 = lat0.to_radians().cos();
+    let project = |(lat, lon): (f64, f64)| {
+        (
+            lon.to_radians() * cos_lat0 * EARTH_RADIUS,
+            lat.to_radians() * EARTH_RADIUS,
+        )
+    };
+
+    // Length and heading of each edge, heading is clockwise from north like
+    // in RoadRenderer::heading_at().
+    let edges: Vec<(f64, f64)> = points
+        .windows(2)
+        .map(|w| {
+            let a = project(w[0]);
+            let b = project(w[1]);
+            let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+            ((dx * dx + dy * dy).sqrt(), dx.atan2(dy))
+        })
+        .filter(|&(length, _)| length > 0.0)
+        .collect();
+
+    let wrap = |a: f64| {
+        let pi = core::f64::consts::PI;
+        (a + pi).rem_euclid(2.0 * pi) - pi
+    };
+    let turn_at = |i: usize| -> f64 {
+        if i == 0 || i >= edges.len() {
+            0.0
+        } else {
+            wrap(edges[i].1 - edges[i - 1].1)
+        }
+    };
+
+    let mut segments: Vec<Segment> = Vec::new();
+    // Turn that was lost to rounding x_curve, carried to the next edge.
+    let mut residual = 0.0;
+    for (i, &(length, _)) in edges.iter().enumerate() {
+        let length_fp = (length * scale * (1 << FP_POS) as f64).round() as i32;
+        if length_fp <= 0 {
+            continue;
+        }
+        let turn = (turn_at(i) + turn_at(i + 1)) * 0.5 + residual;
+        // A segment turns by -2 * x_curve * length / (1 << 2*FP_POS) radians,
+        // see top_down_step().
+        let x_curve = (-turn * (1 << (2 * FP_POS)) as f64 / (2.0 * length_fp as f64)).round() as i32;
+        residual = turn + 2.0 * x_curve as f64 * length_fp as f64 / (1 << (2 * FP_POS)) as f64;
+
+        let joined = match segments.last_mut() {
+            Some(last) if last.x_curve == x_curve => join(last, length_fp),
+            _ => false,
+        };
+        if !joined {
+            segments.push(Segment::new(side_style, length_fp, x_curve, 0));
+        }
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate_segments;
+    use crate::SideInclination::Flat;
+
+    #[test]
+    fn straight_pieces_stay_renderable() {
+        let heights = [0; 101];
+        let mut out = [Segment::new((Flat, Flat), 0, 0, 0); 8];
+        let count = elevation_from_heights(&heights, 100 << FP_POS, 1 << FP_POS, (Flat, Flat), &mut out).unwrap();
+        let out = &out[..count];
+        assert!(count > 1);
+        assert!(validate_segments(out).is_ok());
+        assert!(out.iter().all(|seg| seg.length <= MAX_LENGTH));
+        assert_eq!(out.iter().map(|seg| seg.length).sum::<i32>(), 10000 << FP_POS);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn long_bend_stays_renderable() {
+        // 3 km around a circle with a radius of 128 m, sampled every 20 m.
+        let (lat0, lon0) = (60.0f64, 25.0f64);
+        let radius = 128.0;
+        let meters_per_degree = 6_371_000.0f64.to_radians();
+        let points: std::vec::Vec<(f64, f64)> = (0..=150)
+            .map(|i| {
+                let angle = (i * 20) as f64 / radius;
+                let (x, y) = (radius * angle.cos(), radius * angle.sin());
+                (
+                    lat0 + y / meters_per_degree,
+                    lon0 + x / (meters_per_degree * lat0.to_radians().cos()),
+                )
+            })
+            .collect();
+        let out = segments_from_polyline(&points, 1.0, (Flat, Flat));
+        assert!(out.len() > 1);
+        assert!(validate_segments(&out).is_ok());
+    }
+}
@@ -1,6 +1,6 @@
 #![no_std]
-#![allow(incomplete_features)]
-#![feature(const_generics, const_evaluatable_checked)]
+// The renderer threads a lot of fixed point state through its helpers.
+#![allow(clippy::too_many_arguments)]
 
 
 // Position of fixed point, in general. Some situations need more precision or
@@ -8,113 +8,2050 @@
 // In functions which do lots of fixed point calculations, the point is
 // annotated with comments like FP1, FP2 where the number determines the
 // multiple of FP_POS.
-pub const FP_POS: i32 = 8;
+//
+// The POISJUOKSU_FP_POS environment variable, read at build time, trades
+// range for precision: 10 for large screens where 8 bits of fraction show as
+// jitter, 4 for tiny ones that need the range more. Lengths and positions
+// written with FP_POS follow along, but curves and road widths are in its
+// units too, so a track tuned for one precision looks different with another.
+// Binary tracks record the precision they were written with, see track.
+//
+// It's not a pair of features because Cargo unifies features across the
+// dependency graph, and exclusive ones break builds that pull in both. Nor is
+// it a const generic of RoadRenderer: segments, painters and track formats
+// all share these units, and stable Rust can't derive the other constants
+// from a generic parameter.
+pub const FP_POS: i32 = match option_env!("POISJUOKSU_FP_POS") {
+    None => 8,
+    Some(value) => parse_fp_pos(value),
+};
 
-// http://www.azillionmonkeys.com/qed/ulerysqroot.pdf
-fn isqrt(num: i32) -> i32 {
-    let mut v = num;
-    let mut n = 0;
-    let mut b = 0x8000;
-    let mut bshft = 15;
+const fn parse_fp_pos(value: &str) -> i32 {
+    match value.as_bytes() {
+        b"4" => 4,
+        b"8" => 8,
+        b"10" => 10,
+        _ => panic!("POISJUOKSU_FP_POS must be 4, 8 or 10"),
+    }
+}
+
+// Position of the fixed point of 1 / near. With fewer than 8 bits, near
+// planes further than 1 << FP_POS would round it to 0, so it keeps at least 8
+// and is shifted down to FP1 after multiplying.
+const INV_NEAR_POS: i32 = if FP_POS < 8 { 8 } else { FP_POS };
+const INV_NEAR_SHIFT: i32 = INV_NEAR_POS - FP_POS;
+
+// Largest near plane the renderer handles, as 1 / near rounds to 0 past it.
+// Near planes close to it already give a coarse field of view.
+pub const MAX_NEAR: i32 = 1 << INV_NEAR_POS;
+
+// The square roots of curved hills halve FP_POS, and FP3 must fit in an i32.
+const _: () = assert!(FP_POS % 2 == 0 && FP_POS <= 10);
+
+// Forwards the color functions of Painter to the given field, a Painter or
+// RoadStyle, for painters that wrap another and only change how pixels are
+// drawn. New color functions go here, so that no wrapper falls back to the
+// default.
+macro_rules! forward_painter_colors {
+    ($inner:ident) => {
+        fn sky_color(&self, y: i32, frame: i32) -> Self::ColorType {
+            self.$inner.sky_color(y, frame)
+        }
+
+        fn road_color(&self, tx: i32, t: i32, frame: i32) -> Self::ColorType {
+            self.$inner.road_color(tx, t, frame)
+        }
+
+        fn ground_color(&self, tx: i32, t: i32, frame: i32) -> Self::ColorType {
+            self.$inner.ground_color(tx, t, frame)
+        }
+
+        fn wall_color(&self, t: i32, height: i32, frame: i32) -> Self::ColorType {
+            self.$inner.wall_color(t, height, frame)
+        }
+
+        fn road_width(&self) -> i32 {
+            self.$inner.road_width()
+        }
+
+        fn road_color_span(&self, tx: i32, t: i32, frame: i32) -> (Self::ColorType, i32) {
+            self.$inner.road_color_span(tx, t, frame)
+        }
+
+        fn blend_color(
+            &self,
+            a: &Self::ColorType,
+            b: &Self::ColorType,
+            amount: i32
+        ) -> Option<Self::ColorType> {
+            self.$inner.blend_color(a, b, amount)
+        }
+
+        fn sky_layer_color(&self, layer: usize, x: i32, y: i32, frame: i32) -> Option<Self::ColorType> {
+            self.$inner.sky_layer_color(layer, x, y, frame)
+        }
+
+        fn marker_color(&self, lane: usize, brightness: i32, frame: i32) -> Option<Self::ColorType> {
+            self.$inner.marker_color(lane, brightness, frame)
+        }
+
+        fn speed_line_color(&self, frame: i32) -> Option<Self::ColorType> {
+            self.$inner.speed_line_color(frame)
+        }
+
+        fn fog_color(&self, frame: i32) -> Option<Self::ColorType> {
+            self.$inner.fog_color(frame)
+        }
+    };
+}
+
+#[cfg(feature = "std")]
+extern crate std;
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod track;
+pub mod import;
+pub mod markings;
+pub mod palette;
+pub mod objects;
+pub mod tunnels;
+pub mod views;
+pub mod sync;
+pub mod checksum;
+pub mod timescale;
+pub mod builder;
+pub mod stream;
+pub mod counting;
+pub mod noise;
+pub mod ascii;
+pub mod overdraw;
+pub mod stress;
+pub mod diff;
+pub mod speedlines;
+pub mod rumble;
+pub mod quality;
+pub mod fixed;
+mod varint;
+mod math;
+#[cfg(feature = "alloc")]
+pub mod owned;
+#[cfg(feature = "std")]
+pub mod svg;
+#[cfg(feature = "std")]
+pub mod capture;
+#[cfg(feature = "terminal")]
+pub mod terminal;
+#[cfg(feature = "proptest")]
+pub mod reference;
+
+use math::{Backend, Math};
+
+// How many times the quotient may change in one step of StepDivider before it
+// falls back to dividing.
+const MAX_DIVIDER_STEPS: i64 = 4;
+
+// Keeps track of floor(n / d) for a fixed n while d moves by one at a time,
+// like the per-row denominator of the road plane. Stepping only needs a few
+// additions, which matters on cores without a hardware divider. When the
+// quotient changes too fast, e.g. near the horizon, it divides instead.
+struct StepDivider {
+    n: i64, // Numerator, sign-adjusted so that d is positive
+    d: i64,
+    q: i64, // floor(n / d)
+    r: i64, // n - q * d, in [0, d)
+    sign: i64,
+}
+
+impl StepDivider {
+    fn new(n: i64, d: i64) -> Self {
+        let mut divider = StepDivider { n, d: 1, q: 0, r: 0, sign: 1 };
+        divider.divide(n, d);
+        divider
+    }
+
+    fn divide(&mut self, n: i64, d: i64) {
+        self.sign = if d < 0 { -1 } else { 1 };
+        self.n = n * self.sign;
+        self.d = d * self.sign;
+        self.q = self.n.div_euclid(self.d);
+        self.r = self.n.rem_euclid(self.d);
+    }
+
+    // Moves to a new denominator, which must not be zero.
+    fn set(&mut self, d: i64) {
+        let sign = if d < 0 { -1 } else { 1 };
+        let new_d = d * sign;
+        if sign != self.sign
+            || (new_d - self.d).abs() != 1
+            || self.q.abs() > MAX_DIVIDER_STEPS * new_d
+        {
+            self.divide(self.n * self.sign, d);
+            return;
+        }
+        // n = q * d + r = q * new_d + (r + q * (d - new_d)), where d - new_d
+        // is 1 or -1, so this needs no multiply either.
+        if new_d < self.d {
+            self.r += self.q;
+        } else {
+            self.r -= self.q;
+        }
+        self.d = new_d;
+        while self.r < 0 {
+            self.r += self.d;
+            self.q -= 1;
+        }
+        while self.r >= self.d {
+            self.r -= self.d;
+            self.q += 1;
+        }
+    }
+
+    // Returns trunc(n / d - offset), i.e. what (n - offset * d) / d gives.
+    fn quotient_minus(&self, offset: i64) -> i64 {
+        let q = self.q - offset;
+        if q < 0 && self.r != 0 {
+            q + 1
+        } else {
+            q
+        }
+    }
+}
+
+// Angles are binary angles, where a full turn is 1 << 16.
+pub const FULL_TURN: i32 = 1 << 16;
+
+// Sine of a binary angle in FP2, using Bhaskara I's approximation. The error
+// is below 0.2%, which is plenty for placing things on a map.
+pub fn isin(angle: i32) -> i32 {
+    let half = FULL_TURN / 2;
+    let a = angle & (FULL_TURN - 1);
+    let (x, sign) = if a < half { (a, 1) } else { (a - half, -1) };
+    let p = (x as i64) * ((half - x) as i64);
+    let h2 = (half as i64) * (half as i64);
+    sign * (((16 * p) << (2 * FP_POS)) / (5 * h2 - 4 * p)) as i32
+}
+
+pub fn icos(angle: i32) -> i32 {
+    isin(angle + FULL_TURN / 4)
+}
+
+// Advances a top-down position along a piece of road with constant curvature.
+// The heading is a binary angle, zero points towards +z and positive values
+// turn towards +x. Positions are FP1.
+fn top_down_step(x_curve: i32, length: i32, heading: &mut i32, world_x: &mut i32, world_z: &mut i32) {
+    // Positive x_curve bends the road towards -x. The rate of the turn
+    // matches the change of x_slope when rendering,
+    // 2 * x_curve / (1 << FP_POS) radians per unit.
+    let turn = -(((x_curve as i64) * (length as i64) * 10430) >> (2 * FP_POS - 1)) as i32;
+    let h0 = *heading;
+    let h1 = h0 + turn;
+    let (dx, dz) = if turn.abs() < 64 {
+        // Nearly straight, the arc formula would lose all precision.
+        let h = h0 + turn / 2;
+        (
+            ((length as i64) * (isin(h) as i64)) >> (2 * FP_POS),
+            ((length as i64) * (icos(h) as i64)) >> (2 * FP_POS),
+        )
+    } else {
+        // Exact integral over an arc of constant curvature.
+        let l = length as i64;
+        (
+            ((l * (icos(h0) - icos(h1)) as i64) / turn as i64 * 10430) >> (2 * FP_POS),
+            ((l * (isin(h1) - isin(h0)) as i64) / turn as i64 * 10430) >> (2 * FP_POS),
+        )
+    };
+    *world_x += dx as i32;
+    *world_z += dz as i32;
+    *heading = h1;
+}
+
+// Advances the road state (relative to the camera) along length units of a
+// segment with the given curvature.
+fn integrate_segment(
+    x_curve: i32,
+    y_curve: i32,
+    length: i32,
+    x_offset: &mut i32, // FP1
+    y_offset: &mut i32, // FP1
+    z_offset: &mut i32, // FP1
+    x_slope: &mut i32,  // FP1
+    y_slope: &mut i32,  // FP1
+) {
+    let z;
+
+    if y_curve == 0 {
+        // Flat plane as far as Y axis is concerned
+        let t_factor = Backend::isqrt((1 << (2 * FP_POS)) + *y_slope * *y_slope); // FP1
+
+        z = (length << FP_POS) / t_factor; // FP1
+        *y_offset += (*y_slope * z) >> FP_POS; // FP1
+    } else {
+        let abs_y_curve = if y_curve < 0 { -y_curve } else { y_curve };
+        let tsqrtcurve = Backend::isqrt(abs_y_curve << FP_POS); // FP1
+        let z2 = 4 * length / tsqrtcurve;
+        z = Backend::isqrt(z2 << FP_POS) << (FP_POS / 2); // FP1
+
+        *y_offset += y_curve * z2 + ((*y_slope * z) >> FP_POS); // FP1
+        *y_slope += (y_curve * z * 2) >> FP_POS; // FP1
+    }
+    *z_offset += z;
+
+    if x_curve == 0 {
+        // X-axis is linear.
+        *x_offset += (*x_slope * z) >> FP_POS; // FP1
+    } else {
+        *x_offset += ((((x_curve * z) >> FP_POS) * z) >> FP_POS) + ((*x_slope * z) >> FP_POS); // FP1
+        *x_slope += (2 * x_curve * z) >> FP_POS; // FP1
+    }
+}
+
+pub trait Painter {
+    type ColorType;
+
+    // This function should draw a single pixel of the given color.
+    fn draw(&mut self, x: i32, y: i32, color: &Self::ColorType);
+    // frame is the value given to RoadRenderer::render(), it can be used for
+    // animated colors.
+    fn sky_color(&self, y: i32, frame: i32) -> Self::ColorType;
+    // tx world-space X in FP2, t is world-space distance from start.
+    fn road_color(&self, tx: i32, t: i32, frame: i32) -> Self::ColorType;
+    fn ground_color(&self, tx: i32, t: i32, frame: i32) -> Self::ColorType;
+    // Color of SideInclination::Wall sides, height is the world-space height
+    // above the road in FP1. Walls use the ground color unless overridden.
+    // See markings::WindowGrid for windows of city buildings.
+    fn wall_color(&self, t: i32, height: i32, frame: i32) -> Self::ColorType {
+        let _ = height;
+        self.ground_color(0, t, frame)
+    }
+    fn road_width(&self) -> i32;
+    // Like road_color(), but also returns how far (FP2) from tx towards the
+    // right the color stays the same, so that flat-colored parts of the road
+    // can be drawn with fill_span(). Zero means only at tx.
+    fn road_color_span(&self, tx: i32, t: i32, frame: i32) -> (Self::ColorType, i32) {
+        (self.road_color(tx, t, frame), 0)
+    }
+    // Blends color a towards b, amount is FP1 from 0 (a) to 1 << FP_POS (b).
+    // Used by RoadRenderer::set_fog() and set_horizon_fade(). Painters that
+    // can't blend return None, and the fog and fade are skipped.
+    fn blend_color(
+        &self,
+        a: &Self::ColorType,
+        b: &Self::ColorType,
+        amount: i32
+    ) -> Option<Self::ColorType> {
+        let _ = (a, b, amount);
+        None
+    }
+    // Color of a sky layer given to RoadRenderer::set_sky_layers(), by its
+    // index. x is the screen column plus the scroll offset of the layer. None
+    // shows the layers behind it, and sky_color() behind all of them.
+    fn sky_layer_color(&self, layer: usize, x: i32, y: i32, frame: i32) -> Option<Self::ColorType> {
+        let _ = (layer, x, y, frame);
+        None
+    }
+    // Color of the cat's eyes on a lane given to RoadRenderer::set_cats_eyes(),
+    // by its index. brightness is FP1, see markings::CatsEyes::brightness().
+    // None leaves the studs out.
+    fn marker_color(&self, lane: usize, brightness: i32, frame: i32) -> Option<Self::ColorType> {
+        let _ = (lane, brightness, frame);
+        None
+    }
+    // Color of the streaks of speedlines::SpeedLines, None leaves them out.
+    fn speed_line_color(&self, frame: i32) -> Option<Self::ColorType> {
+        let _ = frame;
+        None
+    }
+    // Color that road and terrain fade towards with distance when
+    // RoadRenderer::set_fog() is enabled, blended with blend_color(). None
+    // leaves the fog out.
+    fn fog_color(&self, frame: i32) -> Option<Self::ColorType> {
+        let _ = frame;
+        None
+    }
+    // Draws pixels from x_begin to x_end on row y with the same color.
+    fn fill_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType) {
+        for x in x_begin..x_end {
+            self.draw(x, y, color);
+        }
+    }
+    // Called before the road row y is drawn, with the columns of the left and
+    // right road edges (not clipped to the screen) and the distance t used
+    // for its colors, for per-row effects like anti-aliased edges.
+    fn begin_road_row(&mut self, y: i32, left: i32, right: i32, t: i32) {
+        let _ = (y, left, right, t);
+    }
+    // Blends color over what was already drawn from x_begin to x_end on row
+    // y, amount is FP1 opacity. Used for overlays like speedlines. Painters
+    // that can't read back what they drew draw the color if it's at least
+    // half opaque.
+    fn blend_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType, amount: i32) {
+        if amount >= 1 << (FP_POS - 1) {
+            self.fill_span(x_begin, x_end, y, color);
+        }
+    }
+    // Copies the pixels from x_begin to x_end on row src_y to row y, shifted
+    // by x_shift. Used for the rows skipped by RoadRenderer::set_row_lod().
+    // Painters that can't read back what they drew return false, and the
+    // colors are computed as usual.
+    fn copy_row(&mut self, y: i32, x_begin: i32, x_end: i32, src_y: i32, x_shift: i32) -> bool {
+        let _ = (y, x_begin, x_end, src_y, x_shift);
+        false
+    }
+}
+
+// The output half of Painter, for backends that are used with several
+// RoadStyles. Combine the two with StyledPainter.
+pub trait PixelSink {
+    type ColorType;
+
+    fn draw(&mut self, x: i32, y: i32, color: &Self::ColorType);
+    fn fill_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType) {
+        for x in x_begin..x_end {
+            self.draw(x, y, color);
+        }
+    }
+    fn blend_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType, amount: i32) {
+        if amount >= 1 << (FP_POS - 1) {
+            self.fill_span(x_begin, x_end, y, color);
+        }
+    }
+    fn begin_road_row(&mut self, y: i32, left: i32, right: i32, t: i32) {
+        let _ = (y, left, right, t);
+    }
+    fn copy_row(&mut self, y: i32, x_begin: i32, x_end: i32, src_y: i32, x_shift: i32) -> bool {
+        let _ = (y, x_begin, x_end, src_y, x_shift);
+        false
+    }
+}
+
+// The coloring half of Painter, see the Painter methods of the same names.
+// Styles only take &self, so they can be swapped at runtime through
+// &dyn RoadStyle.
+pub trait RoadStyle {
+    type ColorType;
+
+    fn sky_color(&self, y: i32, frame: i32) -> Self::ColorType;
+    fn road_color(&self, tx: i32, t: i32, frame: i32) -> Self::ColorType;
+    fn ground_color(&self, tx: i32, t: i32, frame: i32) -> Self::ColorType;
+    fn wall_color(&self, t: i32, height: i32, frame: i32) -> Self::ColorType {
+        let _ = height;
+        self.ground_color(0, t, frame)
+    }
+    fn road_width(&self) -> i32;
+    fn road_color_span(&self, tx: i32, t: i32, frame: i32) -> (Self::ColorType, i32) {
+        (self.road_color(tx, t, frame), 0)
+    }
+    fn blend_color(
+        &self,
+        a: &Self::ColorType,
+        b: &Self::ColorType,
+        amount: i32
+    ) -> Option<Self::ColorType> {
+        let _ = (a, b, amount);
+        None
+    }
+    // Color of a sky layer given to RoadRenderer::set_sky_layers(), by its
+    // index. x is the screen column plus the scroll offset of the layer. None
+    // shows the layers behind it, and sky_color() behind all of them.
+    fn sky_layer_color(&self, layer: usize, x: i32, y: i32, frame: i32) -> Option<Self::ColorType> {
+        let _ = (layer, x, y, frame);
+        None
+    }
+    // Color of the cat's eyes on a lane given to RoadRenderer::set_cats_eyes(),
+    // by its index. brightness is FP1, see markings::CatsEyes::brightness().
+    // None leaves the studs out.
+    fn marker_color(&self, lane: usize, brightness: i32, frame: i32) -> Option<Self::ColorType> {
+        let _ = (lane, brightness, frame);
+        None
+    }
+    // Color of the streaks of speedlines::SpeedLines, None leaves them out.
+    fn speed_line_color(&self, frame: i32) -> Option<Self::ColorType> {
+        let _ = frame;
+        None
+    }
+    // Color of the fog of RoadRenderer::set_fog(), None leaves it out.
+    fn fog_color(&self, frame: i32) -> Option<Self::ColorType> {
+        let _ = frame;
+        None
+    }
+}
+
+// A Painter drawing with a RoadStyle into a PixelSink.
+pub struct StyledPainter<'a, S: PixelSink, R: RoadStyle<ColorType = S::ColorType> + ?Sized> {
+    pub sink: &'a mut S,
+    pub style: &'a R,
+}
+
+impl<'a, S: PixelSink, R: RoadStyle<ColorType = S::ColorType> + ?Sized> StyledPainter<'a, S, R> {
+    pub fn new(sink: &'a mut S, style: &'a R) -> Self {
+        StyledPainter { sink, style }
+    }
+}
+
+impl<'a, S: PixelSink, R: RoadStyle<ColorType = S::ColorType> + ?Sized> Painter for StyledPainter<'a, S, R> {
+    type ColorType = S::ColorType;
+
+    fn draw(&mut self, x: i32, y: i32, color: &Self::ColorType) {
+        self.sink.draw(x, y, color);
+    }
+
+    forward_painter_colors!(style);
+
+    fn fill_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType) {
+        self.sink.fill_span(x_begin, x_end, y, color);
+    }
+
+    fn blend_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType, amount: i32) {
+        self.sink.blend_span(x_begin, x_end, y, color, amount);
+    }
+
+    fn begin_road_row(&mut self, y: i32, left: i32, right: i32, t: i32) {
+        self.sink.begin_road_row(y, left, right, t);
+    }
+
+    fn copy_row(&mut self, y: i32, x_begin: i32, x_end: i32, src_y: i32, x_shift: i32) -> bool {
+        self.sink.copy_row(y, x_begin, x_end, src_y, x_shift)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum SideInclination {
+    Uphill = 0,
+    Flat = 1,
+    Downhill = 2,
+    // A vertical wall of unlimited height, e.g. buildings lining a city
+    // street. Colored with Painter::wall_color().
+    Wall = 3,
+}
+
+// The layout is fixed, so that binary tracks can be used in-place (see the
+// track module).
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Segment {
+    pub side_style: (SideInclination, SideInclination),
+    pub length: i32,
+    pub x_curve: i32,
+    pub y_curve: i32,
+}
+
+impl Segment {
+    pub const fn new(side_style: (SideInclination, SideInclination), length: i32, x_curve: i32, y_curve: i32) -> Self {
+        Segment {
+            side_style,
+            length,
+            x_curve,
+            y_curve,
+        }
+    }
+}
+
+// A point on the center of the road seen from above, see
+// RoadRenderer::centerline().
+#[derive(Copy, Clone)]
+pub struct CenterlinePoint {
+    pub t: i32,       // Distance from the start of the road
+    pub x: i32,       // World X, FP1
+    pub z: i32,       // World Z, FP1
+    pub heading: i32, // Binary angle, see FULL_TURN
+}
+
+pub struct Centerline<'a> {
+    segments: &'a [Segment],
+    step: i32,
+    index: usize,
+    local_t: i32,
+    point: Option<CenterlinePoint>,
+}
+
+impl<'a> Iterator for Centerline<'a> {
+    type Item = CenterlinePoint;
+
+    fn next(&mut self) -> Option<CenterlinePoint> {
+        let cur = self.point?;
+        let mut next = cur;
+        let mut t_left = self.step;
+        while t_left > 0 && self.index < self.segments.len() {
+            let seg = &self.segments[self.index];
+            let length = t_left.min(seg.length - self.local_t);
+            top_down_step(seg.x_curve, length, &mut next.heading, &mut next.x, &mut next.z);
+            next.t += length;
+            t_left -= length;
+            self.local_t += length;
+            if self.local_t >= seg.length {
+                self.index += 1;
+                self.local_t = 0;
+            }
+        }
+        self.point = if next.t != cur.t { Some(next) } else { None };
+        Some(cur)
+    }
+}
+
+// A segment of road in view, see RoadRenderer::visible_segments().
+#[derive(Copy, Clone, Debug)]
+pub struct VisibleSegment {
+    pub index: usize, // Index of the segment in the track
+    pub top: i32,     // Topmost screen row of the segment
+    pub bottom: i32,  // One past the lowest screen row of the segment
+    pub z_entry: i32, // Depth where the segment comes into view, FP1
+    pub z_exit: i32,  // Depth where it ends or the draw distance is reached, FP1
+}
+
+pub struct VisibleSegments<'a> {
+    segments: &'a [Segment],
+    near: i32,
+    h: i32,
+    center_y: i32,
+    max_z: i32,
+    index: usize,
+    local_t: i32, // Part of the current segment behind the camera
+    y_offset: i32,
+    z_offset: i32,
+    y_slope: i32,
+    top: i32, // Topmost row covered by nearer segments
+}
+
+impl<'a> VisibleSegments<'a> {
+    fn row(&self, y_offset: i32, z_offset: i32) -> i32 {
+        if z_offset <= 0 {
+            return self.h;
+        }
+        (self.center_y + (self.near * y_offset) / z_offset).max(0).min(self.h)
+    }
+}
+
+impl<'a> Iterator for VisibleSegments<'a> {
+    type Item = VisibleSegment;
+
+    fn next(&mut self) -> Option<VisibleSegment> {
+        while self.index < self.segments.len() && self.top > 0 && self.z_offset <= self.max_z {
+            let seg = &self.segments[self.index];
+            let length = seg.length - self.local_t;
+            let z_entry = self.z_offset;
+            let bottom = self.top.min(self.row(self.y_offset, self.z_offset));
+            let mut top = bottom;
+            // Hills can peak in the middle of the segment, so those are
+            // sampled at a few points.
+            let pieces = if seg.y_curve != 0 { 4 } else { 1 };
+            let (mut x_offset, mut x_slope) = (0, 0);
+            for piece in 0..pieces {
+                let step = length * (piece + 1) / pieces - length * piece / pieces;
+                integrate_segment(
+                    seg.x_curve,
+                    seg.y_curve,
+                    step,
+                    &mut x_offset,
+                    &mut self.y_offset,
+                    &mut self.z_offset,
+                    &mut x_slope,
+                    &mut self.y_slope,
+                );
+                top = top.min(self.row(self.y_offset, self.z_offset));
+                if self.z_offset > self.max_z {
+                    break;
+                }
+            }
+            let index = self.index;
+            self.index += 1;
+            self.local_t = 0;
+            if top < bottom {
+                self.top = top;
+                return Some(VisibleSegment {
+                    index,
+                    top,
+                    bottom,
+                    z_entry,
+                    z_exit: self.z_offset.min(self.max_z),
+                });
+            }
+        }
+        None
+    }
+}
+
+// The stages of render(), in the order they run. The sides are drawn row by
+// row together with the road, so their time is included in Road. Objects
+// aren't drawn by the renderer, so time them around the game's own drawing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RenderStage {
+    Road,
+    Sky,
+    Overlay, // Speed lines, only when enabled
+}
+
+// Callbacks for following the renderer's progress without polling it, e.g.
+// to change music or load scenery when a segment is reached. Attach with
+// RoadRenderer::set_event_handler(). All methods do nothing by default.
+pub trait RoadEvents {
+    // Called at the start and end of render(), with the frame given to it.
+    fn frame_start(&mut self, frame: i32) {
+        let _ = frame;
+    }
+    fn frame_end(&mut self, frame: i32) {
+        let _ = frame;
+    }
+    // Called around each stage of render(), e.g. to read a cycle counter and
+    // attribute frame time to the stages.
+    fn stage_start(&mut self, stage: RenderStage) {
+        let _ = stage;
+    }
+    fn stage_end(&mut self, stage: RenderStage) {
+        let _ = stage;
+    }
+    // Called by advance() and set() when the camera moves from one segment
+    // to another, with the index of the segment.
+    fn segment_left(&mut self, index: usize) {
+        let _ = index;
+    }
+    fn segment_entered(&mut self, index: usize) {
+        let _ = index;
+    }
+    // Called when the camera moves past the last segment. On looping tracks,
+    // see RoadRenderer::set_looping(), the first segment is entered next.
+    fn track_ended(&mut self) {}
+}
+
+// Errors from the fallible parts of the crate. The modules have their own
+// error types with more detail, which convert into this one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    // The track is malformed or couldn't be loaded.
+    InvalidTrack,
+    // The track is too long or curves too sharply for the fixed-point math.
+    Overflow,
+    // An attached buffer is too small for the screen size.
+    BadDimensions,
+    // The screen size can't be rendered.
+    UnsupportedResolution,
+    // A renderer setting is out of range, see builder::BuildError.
+    InvalidSettings,
+}
+
+impl From<track::TrackError> for Error {
+    fn from(_: track::TrackError) -> Self {
+        Error::InvalidTrack
+    }
+}
+
+impl From<track::RangeError> for Error {
+    fn from(_: track::RangeError) -> Self {
+        Error::Overflow
+    }
+}
+
+impl From<import::ImportError> for Error {
+    fn from(_: import::ImportError) -> Self {
+        Error::InvalidTrack
+    }
+}
+
+impl From<builder::BuildError> for Error {
+    fn from(_: builder::BuildError) -> Self {
+        Error::InvalidSettings
+    }
+}
+
+// Why a track or renderer was rejected, see check_segments() and
+// RoadRenderer::try_new().
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    // The near plane must be positive and at most MAX_NEAR.
+    InvalidNear,
+    // The segment at this index doesn't have a positive length.
+    BadLength(usize),
+    // The total length of the track doesn't fit in an i32.
+    TooLong,
+    // The x_curve of the segment at this index overflows over its length.
+    CurveTooSharp(usize),
+    // The y_curve of the segment at this index overflows over its length or
+    // in the square root of the hill.
+    HillTooSteep(usize),
+}
+
+impl From<ValidationError> for Error {
+    fn from(err: ValidationError) -> Self {
+        match err {
+            ValidationError::InvalidNear => Error::InvalidSettings,
+            ValidationError::BadLength(_) => Error::InvalidTrack,
+            _ => Error::Overflow,
+        }
+    }
+}
+
+// Checks that the segments can be rendered: lengths must be positive, and
+// the total length and the curve terms integrated over each segment must fit
+// in an i32. Tells which segment is at fault, e.g. for track editors.
+pub fn check_segments(segments: &[Segment]) -> Result<(), ValidationError> {
+    let mut total: i32 = 0;
+    for (i, seg) in segments.iter().enumerate() {
+        if seg.length <= 0 {
+            return Err(ValidationError::BadLength(i));
+        }
+        total = total.checked_add(seg.length).ok_or(ValidationError::TooLong)?;
+        // Like the (curve * z >> FP_POS) * z terms of integrate_segment().
+        let overflows = |curve: i32| {
+            let first = curve as i64 * seg.length as i64;
+            if first != first as i32 as i64 {
+                return true;
+            }
+            let second = (first >> FP_POS) * seg.length as i64;
+            second != second as i32 as i64
+        };
+        if overflows(seg.x_curve) {
+            return Err(ValidationError::CurveTooSharp(i));
+        }
+        // The y_curve << FP_POS given to isqrt() too.
+        if overflows(seg.y_curve) || seg.y_curve.unsigned_abs() > (i32::MAX >> FP_POS) as u32 {
+            return Err(ValidationError::HillTooSteep(i));
+        }
+    }
+    Ok(())
+}
+
+// Same as check_segments(), with the crate's general error.
+pub fn validate_segments(segments: &[Segment]) -> Result<(), Error> {
+    check_segments(segments)?;
+    Ok(())
+}
+
+// Where a RoadRenderer's segments come from. Derefs to the segments in both
+// cases.
+enum Segments<'a> {
+    Slice(&'a [Segment]),
+    Window(&'a mut dyn stream::SegmentWindow), // See set_segments_window()
+}
+
+impl core::ops::Deref for Segments<'_> {
+    type Target = [Segment];
+
+    fn deref(&self) -> &[Segment] {
+        match self {
+            Segments::Slice(segments) => segments,
+            Segments::Window(window) => window.segments(),
+        }
+    }
+}
+
+// Blends color towards the fog of the row, see RoadRenderer::row_fog().
+fn fogged<P: Painter>(painter: &P, fog: &Option<(P::ColorType, i32)>, color: P::ColorType) -> P::ColorType {
+    match fog {
+        Some((fog, amount)) => painter.blend_color(&color, fog, *amount).unwrap_or(color),
+        None => color,
+    }
+}
+
+// Moves the columns drawn by the renderer up or down for
+// RoadRenderer::set_roll(). The top and bottom rows are repeated over the
+// rows left uncovered, so every pixel of the screen is still drawn.
+struct Rolled<'p, P: Painter> {
+    painter: &'p mut P,
+    w: i32,
+    h: i32,
+    roll: i32, // FP1
+}
+
+impl<P: Painter> Rolled<'_, P> {
+    // Rows that the pixel at x, y covers once rolled.
+    fn rows(&self, x: i32, y: i32) -> core::ops::Range<i32> {
+        let y0 = y + (((x - self.w / 2) * self.roll) >> FP_POS);
+        let begin = if y == 0 { 0 } else { y0.max(0) };
+        let end = if y == self.h - 1 { self.h } else { (y0 + 1).min(self.h) };
+        begin..end
+    }
+}
+
+impl<P: Painter> Painter for Rolled<'_, P> {
+    type ColorType = P::ColorType;
+
+    fn draw(&mut self, x: i32, y: i32, color: &Self::ColorType) {
+        for y0 in self.rows(x, y) {
+            self.painter.draw(x, y0, color);
+        }
+    }
+
+    forward_painter_colors!(painter);
+
+    fn blend_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType, amount: i32) {
+        for x in x_begin..x_end {
+            for y0 in self.rows(x, y) {
+                self.painter.blend_span(x, x + 1, y0, color, amount);
+            }
+        }
+    }
+
+    fn begin_road_row(&mut self, y: i32, left: i32, right: i32, t: i32) {
+        self.painter.begin_road_row(y, left, right, t);
+    }
+}
+
+pub struct RoadRenderer<'a> {
+    segments: Segments<'a>,  // The road is built out of segments with constant curvature and style.
+    cursor: RoadCursor,      // Position of the camera along the road
+    near: i32,               // Near plane, practically just controls field of view
+    frame: i32,              // Frame counter given to render(), forwarded to the painter
+    horizon: i32,            // Topmost row covered by terrain in the last frame
+    horizon_fade: i32,       // Rows of sky blended towards the terrain below
+    sky_layers: &'a [SkyLayer], // Scrolling layers drawn over the sky
+    far_t: i32,              // Distance of the farthest road row drawn
+    cats_eyes: Option<markings::CatsEyes<'a>>, // See set_cats_eyes()
+    marker_t: i32,           // Distance of the previous road row, for cats_eyes
+    speed_lines: Option<speedlines::SpeedLines<'a>>, // See set_speed_lines()
+    column_horizons: Option<&'a mut [i32]>, // Same as horizon, but per column
+    road_samples: Option<&'a mut [Option<RoadSample>]>, // Nearest visible road per column
+    row_depths: Option<&'a mut [i32]>, // Depth of the road drawn on each row
+    projection: Option<ProjectionTables<'a>>, // Cached per-row constants
+    last_view: Option<ViewKey>, // What the last frame was rendered from
+    segment_states: Option<&'a mut [SegmentState]>, // See prepare_frame()
+    index: Option<&'a SegmentIndex<'a>>, // Shared start distances of the segments
+    max_rows: i32,           // Road rows allowed per frame, negative for no limit
+    rows_left: i32,          // Road rows left in the current frame
+    prepared: Option<(i32, usize)>, // cur_t and number of valid segment_states
+    lod_z: i32,              // Depth after which rows may be skipped
+    lod_rows: i32,           // Every lod_rows'th row is evaluated past lod_z
+    lod_count: i32,          // Rows since the last evaluated one
+    lod_center: i32,         // Road center column on the previous row
+    look_x: i32,             // Columns the view is turned right by
+    look_y: i32,             // Rows the view is turned down by
+    roll: i32,               // Rows the view tilts down by per column, FP1
+    fog: Option<(i32, i32)>, // Depths where fog starts and covers all, FP1
+    t_origin: i32,           // Distance of the first segment from the start of the road
+    t_period: i32,           // Period of the distances given to the painter, 0 for none
+    events: Option<&'a mut dyn RoadEvents>, // See set_event_handler()
+    speed: i32,              // Absolute value of the last advance() step
+    looping: bool,           // See set_looping()
+    camera: (i32, i32),      // Camera offsets for render_camera(), FP1
+    draw_distance: i32,      // max_z for render_camera(), FP1
+    #[cfg(feature = "motion-blur")]
+    blur_threshold: i32,     // Speed above which near rows are blurred
+    #[cfg(feature = "motion-blur")]
+    blur_rows: i32,          // Number of blurred rows at the bottom of the screen
+    #[cfg(feature = "motion-blur")]
+    blur_t: i32,             // Distance of the last row that wasn't blurred
+    #[cfg(feature = "strict")]
+    strict_y: i32,           // Last road row rendered in the current frame
+}
+
+// A layer of the sky, e.g. clouds or distant mountains, see
+// RoadRenderer::set_sky_layers(). Layers scroll sideways as the road turns,
+// down as it climbs and drift with the frame counter; farther layers should
+// scroll less.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SkyLayer {
+    pub columns_per_turn: i32, // Columns scrolled when the road turns a full circle
+    pub rows_per_unit: i32,    // Rows scrolled per unit the road climbs, FP1
+    pub drift: i32,            // Columns scrolled per frame, FP1
+}
+
+impl SkyLayer {
+    // Scroll offset of the layer in columns and rows, for a heading and
+    // elevation from RoadRenderer::heading_at() and elevation_at(). Add it
+    // to a screen position to get the position on the layer.
+    pub fn offset(&self, heading: i32, elevation: i32, frame: i32) -> (i32, i32) {
+        let x = (heading as i64 * self.columns_per_turn as i64) / FULL_TURN as i64
+            + ((frame as i64 * self.drift as i64) >> FP_POS);
+        let y = -((elevation as i64 * self.rows_per_unit as i64) >> (2 * FP_POS));
+        (x as i32, y as i32)
+    }
+}
+
+// The camera of a renderer, kept across rebuilding it for an edited track,
+// see RoadRenderer::camera_state().
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraState {
+    pub t: i32,           // Distance from the start of the road, FP1
+    pub t_origin: i32,    // See RoadRenderer::set_t_origin()
+    pub look: (i32, i32), // See RoadRenderer::set_look_offset()
+    pub speed: i32,       // Last advance() step, for motion blur and speed lines
+}
+
+// Where a point projects to, see RoadRenderer::screen_pos().
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScreenPos {
+    pub x: i32,              // Screen column
+    pub y: i32,              // Screen row
+    pub inv_z: i32,          // 1/z, FP3, negative values are behind camera
+    pub behind_camera: bool, // The position is meaningless if set
+}
+
+// The segments a step of the camera went through, see RoadRenderer::advance().
+// Each boundary crossed was also reported to the RoadEvents handler.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SegmentMove {
+    pub from: usize, // Segment of the camera before the step
+    pub to: usize,   // Segment after it, the segment count past the end
+    pub laps: i32,   // Times around a looping track, negative when reversing
+}
+
+impl SegmentMove {
+    // Whether the step crossed any segment boundaries.
+    pub fn crossed(&self) -> bool {
+        self.from != self.to || self.laps != 0
+    }
+}
+
+// A point on the road surface as seen in the last rendered frame.
+#[derive(Copy, Clone)]
+pub struct RoadSample {
+    pub t: i32,  // Distance from the start of the road, FP1
+    pub tx: i32, // Lateral position like in Painter::road_color, FP2
+    pub z: i32,  // Depth from the camera, FP1
+}
+
+// Road state at the start of a segment, relative to the camera. Filled in by
+// RoadRenderer::prepare_frame().
+#[derive(Copy, Clone, Default)]
+pub struct SegmentState {
+    t_offset: i32, // Distance from the camera
+    x_offset: i32, // FP1
+    y_offset: i32, // FP1
+    z_offset: i32, // FP1
+    x_slope: i32,  // FP1
+    y_slope: i32,  // FP1
+}
+
+// Projection constants that only change with the near plane and screen
+// height, kept between frames so that render() doesn't recompute them. The
+// buffer needs one entry per screen row. Attach with
+// RoadRenderer::set_projection_tables().
+pub struct ProjectionTables<'a> {
+    rows: &'a mut [i32], // (y - h / 2) * inv_near for each row, FP1
+    near: i32,
+    h: i32,
+    inv_near: i32,       // 1 / near, see INV_NEAR_POS
+}
+
+impl<'a> ProjectionTables<'a> {
+    pub fn new(buffer: &'a mut [i32]) -> Self {
+        ProjectionTables {
+            rows: buffer,
+            near: 0,
+            h: 0,
+            inv_near: 0,
+        }
+    }
+
+    // Recomputes the tables if near or h changed since the last call.
+    // Returns false if the buffer is too small for h rows.
+    pub fn update(&mut self, near: i32, h: i32) -> bool {
+        if (h as usize) > self.rows.len() {
+            return false;
+        }
+        if near != self.near || h != self.h {
+            self.near = near;
+            self.h = h;
+            self.inv_near = Backend::div(1 << INV_NEAR_POS, near);
+            for (y, row) in self.rows.iter_mut().enumerate().take(h as usize) {
+                *row = Backend::project(y as i32 - h / 2, self.inv_near);
+            }
+        }
+        true
+    }
+}
+
+// A track that can be shared by any number of views. Only read while
+// rendering, each view keeps its own RoadCursor.
+#[derive(Copy, Clone)]
+pub struct Track<'a> {
+    pub segments: &'a [Segment],
+    pub index: Option<&'a SegmentIndex<'a>>,
+}
+
+impl<'a> Track<'a> {
+    pub fn new(segments: &'a [Segment]) -> Self {
+        Track { segments, index: None }
+    }
+
+    // The index is used only if it was built for these segments.
+    fn index(&self) -> Option<&'a SegmentIndex<'a>> {
+        self.index.filter(|index| index.is_for(self.segments))
+    }
+}
+
+// A position along a Track. Cheap to copy, so games can keep one per car or
+// camera and render from any of them with RoadRenderer::render_view().
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub struct RoadCursor {
+    t: i32,         // Distance from the start of the road
+    base_t: i32,    // Distance of the current segment from the start of the road
+    segment: usize, // Index of the current segment
+}
+
+impl RoadCursor {
+    pub const fn new() -> Self {
+        RoadCursor { t: 0, base_t: 0, segment: 0 }
+    }
+
+    // Distance from the start of the road.
+    pub fn t(&self) -> i32 {
+        self.t
+    }
+
+    // Index of the current segment, the segment count past the end of the
+    // road.
+    pub fn segment(&self) -> usize {
+        self.segment
+    }
+
+    // Distance from the start of the current segment, negative before the
+    // start of the road.
+    pub fn local_t(&self) -> i32 {
+        self.t - self.base_t
+    }
+
+    // Negative steps move backwards. Before the start of the road, the
+    // cursor stays on the first segment.
+    pub fn advance(&mut self, track: &Track, step: i32) {
+        self.t += step;
+        let segments = track.segments;
+        while self.segment > 0 && self.t < self.base_t {
+            self.segment -= 1;
+            self.base_t -= segments[self.segment].length;
+        }
+        while self.segment < segments.len() && self.t >= self.base_t + segments[self.segment].length {
+            self.base_t += segments[self.segment].length;
+            self.segment += 1;
+        }
+    }
+
+    pub fn set(&mut self, track: &Track, t: i32) {
+        match track.index() {
+            Some(index) => {
+                (self.segment, self.base_t) = index.find(t);
+                self.t = t;
+            }
+            None => {
+                *self = RoadCursor::new();
+                self.advance(track, t);
+            }
+        }
+    }
+}
+
+// Start distances of the segments of a track, computed once so that set()
+// and total_length() don't need to walk the whole track. Only read after
+// construction, so one index can be shared by any number of renderers of the
+// same track, see RoadRenderer::set_segment_index().
+pub struct SegmentIndex<'a> {
+    segments: &'a [Segment],
+    starts: &'a [i32], // One entry per segment, plus the total length
+}
+
+impl<'a> SegmentIndex<'a> {
+    // The buffer needs one entry more than there are segments. Returns None
+    // if it's too small.
+    pub fn new(segments: &'a [Segment], buffer: &'a mut [i32]) -> Option<Self> {
+        let starts = buffer.get_mut(..segments.len() + 1)?;
+        let mut t = 0;
+        for (start, seg) in starts.iter_mut().zip(segments.iter()) {
+            *start = t;
+            t += seg.length;
+        }
+        starts[segments.len()] = t;
+        Some(SegmentIndex { segments, starts })
+    }
+
+    pub fn total_length(&self) -> i32 {
+        self.starts[self.segments.len()]
+    }
+
+    // Returns true if the index was built for these segments.
+    pub fn is_for(&self, segments: &[Segment]) -> bool {
+        core::ptr::eq(self.segments, segments)
+    }
+
+    // Returns the index and start distance of the segment containing t, like
+    // RoadRenderer::set() finds it.
+    pub fn find(&self, t: i32) -> (usize, i32) {
+        let index = self.starts[1..].partition_point(|&end| end <= t);
+        (index, self.starts[index])
+    }
+}
+
+// Everything that affects the geometry of a rendered frame, see
+// RoadRenderer::render_if_changed(). The segments are compared by address.
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct ViewKey {
+    segments: usize,
+    segment_count: usize,
+    cur_t: i32,
+    near: i32,
+    x_offset: i32,
+    y_offset: i32,
+    max_z: i32,
+    size: (i32, i32),
+    look: (i32, i32),
+    roll: i32,
+    fog: Option<(i32, i32)>,
+}
+
+// Storage type of screen columns in LineVisibility. The i16-coords feature
+// halves the size of the per-row array render() keeps on the stack, for
+// microcontrollers with very little RAM. Screens must then be narrower than
+// 32768 pixels, which render() checks at compile time and render_dyn() when
+// called. Arithmetic on columns is still done in i32.
+#[cfg(feature = "i16-coords")]
+type Column = i16;
+#[cfg(not(feature = "i16-coords"))]
+type Column = i32;
+
+// Intermediate type of the road's texture coordinates across a row. At
+// column 0 of wide screens, the coordinate can be far off the road and
+// overflow an i32 even though the road itself is in range. The wide-math
+// feature computes it in an i64, for screens of hundreds of columns.
+#[cfg(feature = "wide-math")]
+type Wide = i64;
+#[cfg(not(feature = "wide-math"))]
+type Wide = i32;
+
+// Per-line visibility information, needed for road rendering. Only the
+// scratch buffer of render_dyn() needs these to be created outside of the
+// renderer, and it's filled in there, so the default is as good as any.
+#[derive(Copy, Clone, Default)]
+pub struct LineVisibility {
+    // If the line is above road horizon, the range between begin and end is
+    // available. Otherwise, it is masked.
+    begin: Column,
+    end: Column,
+}
+
+// The casts are no-ops without i16-coords.
+#[allow(clippy::unnecessary_cast)]
+impl LineVisibility {
+    #[inline(always)]
+    fn begin(&self) -> i32 {
+        self.begin as i32
+    }
+
+    #[inline(always)]
+    fn end(&self) -> i32 {
+        self.end as i32
+    }
+
+    #[inline(always)]
+    fn set_begin(&mut self, x: i32) {
+        self.begin = x as Column;
+    }
+
+    #[inline(always)]
+    fn set_end(&mut self, x: i32) {
+        self.end = x as Column;
+    }
+}
+
+impl<'a> RoadRenderer<'a> {
+    // Like new(), but checks the track and near plane first, see
+    // check_segments().
+    pub fn try_new(segments: &'a [Segment], near: i32) -> Result<Self, ValidationError> {
+        if near <= 0 || near > MAX_NEAR {
+            return Err(ValidationError::InvalidNear);
+        }
+        check_segments(segments)?;
+        Ok(RoadRenderer::new(segments, near))
+    }
+
+    // A const fn, so that a renderer of a 'static track can be a static
+    // itself. See fixed::FixedRoad for tracks that change at runtime.
+    pub const fn new(segments: &'a [Segment], near: i32) -> Self {
+        Self {
+            segments: Segments::Slice(segments),
+            cursor: RoadCursor::new(),
+            near,
+            frame: 0,
+            horizon: 0,
+            horizon_fade: 0,
+            sky_layers: &[],
+            far_t: 0,
+            cats_eyes: None,
+            marker_t: 0,
+            speed_lines: None,
+            column_horizons: None,
+            road_samples: None,
+            row_depths: None,
+            projection: None,
+            last_view: None,
+            segment_states: None,
+            index: None,
+            max_rows: -1,
+            rows_left: -1,
+            prepared: None,
+            lod_z: 0,
+            lod_rows: 1,
+            lod_count: 0,
+            lod_center: 0,
+            look_x: 0,
+            look_y: 0,
+            roll: 0,
+            fog: None,
+            t_origin: 0,
+            t_period: 0,
+            events: None,
+            speed: 0,
+            looping: false,
+            camera: (0, 0),
+            draw_distance: 0,
+            #[cfg(feature = "motion-blur")]
+            blur_threshold: 0,
+            #[cfg(feature = "motion-blur")]
+            blur_rows: 0,
+            #[cfg(feature = "motion-blur")]
+            blur_t: 0,
+            #[cfg(feature = "strict")]
+            strict_y: 0,
+        }
+    }
+
+    // Attaches a buffer with one entry per screen column. During render(), the
+    // topmost row covered by terrain in each column is written into it.
+    pub fn set_column_horizon_buffer(&mut self, buffer: Option<&'a mut [i32]>) {
+        self.column_horizons = buffer;
+    }
+
+    // Returns the topmost screen row covered by road or terrain in the last
+    // rendered frame. Rows above it only contain sky. If nothing was covered,
+    // the screen height is returned.
+    pub fn horizon(&self) -> i32 {
+        self.horizon
+    }
+
+    // Same as horizon(), but for a single column. Only available if a buffer
+    // was given with set_column_horizon_buffer().
+    pub fn column_horizon(&self, x: i32) -> Option<i32> {
+        self.column_horizons.as_ref()?.get(x as usize).copied()
+    }
+
+    // Softens the horizon line: the given number of sky rows above the
+    // terrain in each column are blended from the ground color of the
+    // farthest road row towards the sky color, with Painter::blend_color().
+    // Needs the column horizon buffer, zero rows disables the fade.
+    pub fn set_horizon_fade(&mut self, rows: i32) {
+        self.horizon_fade = rows.max(0);
+    }
+
+    // Sky layers drawn over sky_color() with Painter::sky_layer_color(), from
+    // back to front. Empty by default.
+    pub fn set_sky_layers(&mut self, layers: &'a [SkyLayer]) {
+        self.sky_layers = layers;
+    }
+
+    // Draws reflective studs over the road on the rows where they are, see
+    // markings::CatsEyes. The road under the studs is drawn first, so their
+    // pixels are written twice.
+    pub fn set_cats_eyes(&mut self, cats_eyes: Option<markings::CatsEyes<'a>>) {
+        self.cats_eyes = cats_eyes;
+    }
+
+    // Draws speed lines over the sky and terrain at the end of render(), when
+    // the last advance() step was fast enough.
+    pub fn set_speed_lines(&mut self, lines: Option<speedlines::SpeedLines<'a>>) {
+        self.speed_lines = lines;
+    }
+
+    // Attaches a buffer with one entry per screen column. During render(), the
+    // nearest visible road pixel of each column is written into it.
+    pub fn set_road_sample_buffer(&mut self, buffer: Option<&'a mut [Option<RoadSample>]>) {
+        self.road_samples = buffer;
+    }
+
+    // Returns the nearest road pixel visible in column x in the last rendered
+    // frame, if any. This is effectively a ray cast into the screen. Only
+    // available if a buffer was given with set_road_sample_buffer().
+    pub fn nearest_road_at(&self, x: i32) -> Option<RoadSample> {
+        *self.road_samples.as_ref()?.get(x as usize)?
+    }
+
+    // The camera offsets from the road center (FP1) and the draw distance
+    // used by render_camera(), for games where they rarely change.
+    pub fn set_camera(&mut self, x_offset: i32, y_offset: i32) {
+        self.camera = (x_offset, y_offset);
+    }
+
+    pub fn set_draw_distance(&mut self, max_z: i32) {
+        self.draw_distance = max_z;
+    }
+
+    // Moves the center of the view by x columns left and y rows up, so that
+    // the camera appears turned right and down. The road is sheared rather
+    // than rotated, which looks right for small angles. Combined with a large
+    // y_offset, this keeps the road on screen when the camera is high above
+    // it, e.g. for a photo mode.
+    pub fn set_look_offset(&mut self, x: i32, y: i32) {
+        self.look_x = x;
+        self.look_y = y;
+    }
+
+    // Rolls the camera, tilting the picture about the center of the screen by
+    // slope rows per column (FP1). Positive slopes lower the right side, as
+    // when the camera banks left. Each column is moved up or down as a whole
+    // and the top and bottom rows are stretched over the gaps, which stays
+    // cheap and gap-free for the small angles of banking in curves. Spans are
+    // drawn pixel by pixel while rolled, and rows aren't copied for
+    // set_row_lod(). Positions from screen_pos() and the buffers aren't
+    // tilted, add roll_shift() to their rows.
+    pub fn set_roll(&mut self, slope: i32) {
+        self.roll = slope;
+    }
+
+    // Rows that column x of a w pixels wide screen is moved down by
+    // set_roll().
+    pub fn roll_shift(&self, w: i32, x: i32) -> i32 {
+        ((x - w / 2) * self.roll) >> FP_POS
+    }
+
+    // Fades road and terrain towards Painter::fog_color() with distance, from
+    // nothing at depth start to fully fogged at depth end (FP1). Painters
+    // without blend_color() or fog_color() aren't fogged. None disables the
+    // fog.
+    pub fn set_fog(&mut self, fog: Option<(i32, i32)>) {
+        self.fog = fog;
+    }
+
+    // Returns the screen position of the point straight ahead of the camera.
+    fn view_center(&self, (w, h): (i32, i32)) -> (i32, i32) {
+        (w / 2 - self.look_x, h / 2 - self.look_y)
+    }
+
+    // Limits how many rows of road a single render() may draw, so that the
+    // worst-case frame time is bounded. Rows past the limit are drawn as sky,
+    // i.e. the draw distance shrinks on frames that would take too long.
+    // None removes the limit.
+    pub fn set_max_rows(&mut self, rows: Option<i32>) {
+        self.max_rows = rows.map_or(-1, |rows| rows.max(0));
+    }
+
+    // Checks that a w x h screen can be rendered, and that the attached
+    // buffers cover it. Otherwise, render() skips writing past the end of
+    // short buffers without telling.
+    pub fn check_size(&self, (w, h): (i32, i32)) -> Result<(), Error> {
+        if w <= 0 || h <= 0 {
+            return Err(Error::UnsupportedResolution);
+        }
+        let (w, h) = (w as usize, h as usize);
+        let short = self.column_horizons.as_ref().is_some_and(|b| b.len() < w)
+            || self.road_samples.as_ref().is_some_and(|b| b.len() < w)
+            || self.row_depths.as_ref().is_some_and(|b| b.len() < h)
+            || self.projection.as_ref().is_some_and(|p| p.rows.len() < h);
+        if short {
+            return Err(Error::BadDimensions);
+        }
+        Ok(())
+    }
+
+    // Returns true if the last rendered frame ran out of rows, see
+    // set_max_rows().
+    pub fn row_limit_hit(&self) -> bool {
+        self.rows_left == 0
+    }
+
+    // Number of road rows the last rendered frame drew, including the rows
+    // repeated by the row LOD.
+    pub fn rows_rendered(&self) -> i32 {
+        self.max_rows - self.rows_left
+    }
+
+    // Attaches a buffer for prepare_frame(), with one entry per segment that
+    // should be covered starting from the camera.
+    pub fn set_segment_state_buffer(&mut self, buffer: Option<&'a mut [SegmentState]>) {
+        self.segment_states = buffer;
+        self.prepared = None;
+    }
+
+    // Integrates the road once from the camera and stores the state at the
+    // start of each segment. Until the camera moves, screen_pos(),
+    // is_visible() and the other queries relative to the camera then start
+    // from the nearest stored segment instead of walking the road from the
+    // camera, which makes projecting many sprites per frame cheap. Call
+    // after advance() or set(). Does nothing without a buffer attached with
+    // set_segment_state_buffer().
+    pub fn prepare_frame(&mut self) {
+        self.prepared = None;
+        let states = match self.segment_states.take() {
+            Some(states) => states,
+            None => return,
+        };
+        let mut state = SegmentState::default();
+        let mut count = 0;
+        for (k, entry) in states.iter_mut().enumerate() {
+            let Some(index) = self.segment_ahead(k) else { break };
+            *entry = state;
+            count += 1;
+            let length = if k == 0 {
+                self.segments[index].length - (self.cursor.t - self.cursor.base_t)
+            } else {
+                self.segments[index].length
+            };
+            self.update_state_at_segment_length(
+                index,
+                length,
+                &mut state.x_offset,
+                &mut state.y_offset,
+                &mut state.z_offset,
+                &mut state.x_slope,
+                &mut state.y_slope,
+            );
+            state.t_offset += length;
+        }
+        self.segment_states = Some(states);
+        self.prepared = Some((self.cursor.t, count));
+    }
+
+    // Attaches a buffer with one entry per screen row. During render(), the
+    // depth (FP1) of the road drawn on each row is written into it, or
+    // i32::MAX if the row has no road.
+    pub fn set_row_depth_buffer(&mut self, buffer: Option<&'a mut [i32]>) {
+        self.row_depths = buffer;
+    }
+
+    // Enables a cheap motion blur: when advance() is called with steps larger
+    // than threshold, every other row of the rows nearest to the camera
+    // repeats the colors of the row below it. Zero rows disables the effect.
+    #[cfg(feature = "motion-blur")]
+    pub fn set_motion_blur(&mut self, threshold: i32, rows: i32) {
+        self.blur_threshold = threshold;
+        self.blur_rows = rows;
+    }
+
+    // Attaches projection tables, which are updated by render() when the
+    // near plane or screen height changes and reused otherwise.
+    pub fn set_projection_tables(&mut self, tables: Option<ProjectionTables<'a>>) {
+        self.projection = tables;
+    }
+
+    // Enables level of detail for rows near the horizon, where many rows map
+    // to nearly the same distance. Past depth z (FP1), road colors are only
+    // evaluated on every rows'th row and the rows in between repeat them with
+    // Painter::copy_row(). Geometry and visibility are still computed for
+    // every row. rows of 1 disables this.
+    pub fn set_row_lod(&mut self, z: i32, rows: i32) {
+        self.lod_z = z;
+        self.lod_rows = rows.max(1);
+    }
+
+    // Moves the camera step along the road, backwards if it's negative.
+    // Returns the segments it went through, e.g. for checkpoints and sound
+    // cues.
+    pub fn advance(&mut self, step: i32) -> SegmentMove {
+        self.speed = step.abs();
+        let track = Track { segments: &self.segments, index: self.index };
+        let from = self.cursor.segment;
+        self.cursor.advance(&track, step);
+        self.report_move(from);
+        let laps = self.wrap();
+        SegmentMove { from, to: self.cursor.segment, laps }
+    }
+
+    // Jumping isn't movement, so it doesn't change the speed used for motion
+    // blur and speed lines.
+    pub fn set(&mut self, t: i32) {
+        let track = Track { segments: &self.segments, index: self.index };
+        let from = self.cursor.segment;
+        self.cursor.set(&track, t);
+        self.report_move(from);
+        self.wrap();
+    }
+
+    // Makes the track a closed loop: the camera wraps around to the start
+    // when it passes the end of the road and back when it reverses past the
+    // start, and the road past the end is drawn from the start again, up to
+    // one lap. The distances given to the painter start over from the origin
+    // at the wrap, so the start and end of the track should match in style
+    // and position.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+        self.last_view = None;
+        self.prepared = None;
+        self.wrap();
+    }
+
+    // Moves the camera back onto the track when looping. The segment events
+    // continue around the loop, after the track_ended() of the end. Returns
+    // the number of laps the camera moved by.
+    fn wrap(&mut self) -> i32 {
+        let total = self.total_length();
+        let t = self.cursor.t;
+        if !self.looping || total <= 0 || (0..total).contains(&t) {
+            return 0;
+        }
+        let count = self.segments.len();
+        let track = Track { segments: &self.segments, index: self.index };
+        self.cursor.set(&track, t.rem_euclid(total));
+        let start = if t < 0 {
+            if let Some(events) = self.events.as_mut() {
+                events.segment_left(0);
+                events.segment_entered(count - 1);
+            }
+            count - 1
+        } else {
+            if let Some(events) = self.events.as_mut() {
+                events.segment_entered(0);
+            }
+            0
+        };
+        self.report_move(start);
+        t.div_euclid(total)
+    }
+
+    // Index of the k'th segment from the camera's, continuing from the start
+    // of the track for up to one lap when looping.
+    fn segment_ahead(&self, k: usize) -> Option<usize> {
+        let count = self.segments.len();
+        let index = self.cursor.segment + k;
+        if index < count {
+            Some(index)
+        } else if self.looping && k < count {
+            Some(index - count)
+        } else {
+            None
+        }
+    }
+
+    // Sends the segment events for a move of the camera from segment from
+    // to the current one.
+    fn report_move(&mut self, from: usize) {
+        let to = self.cursor.segment;
+        let count = self.segments.len();
+        let events = match self.events.as_mut() {
+            Some(events) => events,
+            None => return,
+        };
+        if to == from {
+            return;
+        }
+        if to < from {
+            // Reversed, or jumped back with set(). Past the end of the road,
+            // there's no segment to leave.
+            if from < count {
+                events.segment_left(from);
+            }
+            for index in (to..from).rev() {
+                events.segment_entered(index);
+                if index > to {
+                    events.segment_left(index);
+                }
+            }
+            return;
+        }
+        for index in from..to {
+            events.segment_left(index);
+            if index + 1 < count {
+                events.segment_entered(index + 1);
+            } else {
+                events.track_ended();
+            }
+        }
+    }
+
+    // Attaches a handler for the events of RoadEvents, or removes it.
+    pub fn set_event_handler(&mut self, events: Option<&'a mut dyn RoadEvents>) {
+        self.events = events;
+    }
+
+    // Returns the camera's position along the road.
+    pub fn cursor(&self) -> RoadCursor {
+        self.cursor
+    }
+
+    // Moves the camera to a position kept with a cursor of the same track.
+    pub fn set_cursor(&mut self, cursor: RoadCursor) {
+        self.cursor = cursor;
+    }
+
+    // Returns the distance from the start of the road, i.e. the value that
+    // set() takes.
+    pub fn position(&self) -> i32 {
+        self.cursor.t
+    }
+
+    // Sets the distance of the first segment from the start of the road. It's
+    // added to the t given to the painter and stored in road samples, so that
+    // colors stay in place when the segments are a window of a longer road,
+    // see stream::SegmentStream. Positions given to set() and advance() are
+    // still relative to the first segment. Wraps around on overflow.
+    pub fn set_t_origin(&mut self, origin: i32) {
+        if origin != self.t_origin {
+            self.last_view = None;
+        }
+        self.t_origin = origin;
+    }
+
+    // Makes the distances given to the painter and stored in road samples
+    // wrap around to 0 every period (FP1), or not at all if 0. On long
+    // sessions they would otherwise overflow and the stripes of the road
+    // would glitch where they do. The period should be a multiple of the
+    // painter's patterns, e.g. the track length or a large power of two, and
+    // a power of two if the origin wraps around too.
+    pub fn set_t_period(&mut self, period: i32) {
+        self.t_period = period.max(0);
+        self.last_view = None;
+    }
+
+    // Distance given to the painter for t from the start of the road.
+    fn painter_t(&self, t: i32) -> i32 {
+        if self.t_period > 0 {
+            t.rem_euclid(self.t_period)
+        } else {
+            t
+        }
+    }
+
+    // Returns the phase of a dashed line with the given period (FP1) under the
+    // camera, see markings::dash_phase().
+    pub fn dash_phase(&self, period: i32) -> i32 {
+        markings::dash_phase(self.painter_t(self.cursor.t.wrapping_add(self.t_origin)), period)
+    }
+
+    // Replaces the track, e.g. for the next stage, keeping the near plane and
+    // other settings. The position along the road is kept, clamped to the
+    // length of the new track, and the current segment is found again from
+    // it. An index of the old track is ignored from here on, attach one for
+    // the new track with set_segment_index().
+    pub fn set_segments(&mut self, segments: &'a [Segment]) {
+        self.segments = Segments::Slice(segments);
+        self.prepared = None;
+        self.last_view = None;
+        let t = self.cursor.t.clamp(0, self.total_length());
+        self.set(t);
+    }
+
+    // Renders the window of a stream::SegmentStream, which the renderer holds
+    // on to from here on. Call update_window() after moving the camera to
+    // keep the window around it. Settings other than the segment index are
+    // kept, and the position is clamped like in set_segments().
+    pub fn set_segments_window(&mut self, window: &'a mut dyn stream::SegmentWindow) {
+        self.t_origin = window.origin();
+        self.segments = Segments::Window(window);
+        self.index = None;
+        self.prepared = None;
+        self.last_view = None;
+        let t = self.cursor.t.clamp(0, self.total_length());
+        self.set(t);
+    }
+
+    // Moves the window given to set_segments_window() along with the camera:
+    // segments far enough behind it are dropped and new ones pulled in ahead.
+    // The position and origin are rebased, so the view doesn't change and no
+    // segment events are sent. Returns how much positions relative to the
+    // window moved, for ones kept outside the renderer, or 0 without a
+    // window.
+    pub fn update_window(&mut self) -> i32 {
+        let Segments::Window(window) = &mut self.segments else { return 0 };
+        let count = window.segments().len();
+        let shift = window.update(self.cursor.t);
+        self.t_origin = window.origin();
+        if shift != 0 || window.segments().len() != count {
+            self.prepared = None;
+            self.last_view = None;
+            let t = self.cursor.t - shift;
+            let track = Track { segments: &self.segments, index: None };
+            self.cursor.set(&track, t);
+        }
+        shift
+    }
+
+    // Swaps in a new version of the track on a running renderer, e.g. from a
+    // live track editor, together with its index if it has one. The position
+    // is clamped like in set_segments(), and nothing cached from the last
+    // frame is reused even if the new track sits where the old one was. The
+    // camera settings are kept. Segment numbers change with edits, so unlike
+    // set_segments(), no segment events are sent.
+    pub fn reload_segments(&mut self, segments: &'a [Segment], index: Option<&'a SegmentIndex<'a>>) {
+        self.segments = Segments::Slice(segments);
+        self.index = index;
+        self.prepared = None;
+        self.last_view = None;
+        let t = self.cursor.t.clamp(0, self.total_length());
+        let track = Track { segments: &self.segments, index: self.index };
+        self.cursor.set(&track, t);
+    }
 
-    loop {
-        let tmp = ((n << 1) + b) << bshft;
-        bshft -= 1;
-        if v >= tmp {
-            n += b;
-            v -= tmp;
+    // The renderer borrows the track, so editing it in place with
+    // track::apply_patch() means building a new renderer. Take the camera
+    // state before dropping the old one and restore it on the new one to
+    // continue from the same view.
+    pub fn camera_state(&self) -> CameraState {
+        CameraState {
+            t: self.cursor.t,
+            t_origin: self.t_origin,
+            look: (self.look_x, self.look_y),
+            speed: self.speed,
         }
-        b >>= 1;
-        if b == 0 {
-            break;
+    }
+
+    // The position is clamped to the length of the track like in
+    // reload_segments().
+    pub fn restore_camera(&mut self, camera: CameraState) {
+        self.t_origin = camera.t_origin;
+        (self.look_x, self.look_y) = camera.look;
+        self.speed = camera.speed;
+        self.last_view = None;
+        self.prepared = None;
+        let t = camera.t.clamp(0, self.total_length());
+        let track = Track { segments: &self.segments, index: self.index };
+        self.cursor.set(&track, t);
+    }
+
+    // Shares a precomputed index of the track between renderers. It's ignored
+    // while it was built for other segments than the ones being rendered.
+    pub fn set_segment_index(&mut self, index: Option<&'a SegmentIndex<'a>>) {
+        self.index = index;
+    }
+
+    fn index(&self) -> Option<&'a SegmentIndex<'a>> {
+        self.index.filter(|index| index.is_for(&self.segments))
+    }
+
+    // Returns the segments and index being rendered as a Track, for moving
+    // RoadCursors along it.
+    pub fn track(&self) -> Track<'_> {
+        Track {
+            segments: &self.segments,
+            index: self.index,
         }
     }
-    n
-}
 
-pub trait Painter {
-    type ColorType;
+    // Returns the track being rendered.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
 
-    // This function should draw a single pixel of the given color.
-    fn draw(&mut self, x: i32, y: i32, color: &Self::ColorType);
-    fn sky_color(&self, y: i32) -> Self::ColorType;
-    // tx world-space X in FP2, t is world-space distance from start.
-    fn road_color(&self, tx: i32, t: i32) -> Self::ColorType;
-    fn ground_color(&self, tx: i32, t: i32) -> Self::ColorType;
-    fn road_width(&self) -> i32;
-}
+    // Index of the segment the camera is on, see RoadCursor::segment().
+    pub fn current_segment(&self) -> usize {
+        self.cursor.segment
+    }
 
-#[derive(Copy, Clone)]
-pub enum SideInclination {
-    Uphill,
-    Flat,
-    Downhill,
-}
+    // Distance of the camera from the start of its segment, see
+    // RoadCursor::local_t().
+    pub fn local_t(&self) -> i32 {
+        self.cursor.local_t()
+    }
 
-pub struct Segment {
-    pub side_style: (SideInclination, SideInclination),
-    pub length: i32,
-    pub x_curve: i32,
-    pub y_curve: i32,
-}
+    // Distance from the camera to the end of its segment, 0 past the end of
+    // the road.
+    pub fn remaining_in_segment(&self) -> i32 {
+        match self.segments.get(self.cursor.segment) {
+            Some(seg) => seg.length - self.cursor.local_t(),
+            None => 0,
+        }
+    }
 
-impl Segment {
-    pub fn new(side_style: (SideInclination, SideInclination), length: i32, x_curve: i32, y_curve: i32) -> Self {
-        Segment {
-            side_style,
-            length,
-            x_curve,
-            y_curve,
+    // Returns the length of the whole road.
+    pub fn total_length(&self) -> i32 {
+        if let Some(index) = self.index() {
+            return index.total_length();
         }
+        self.segments.iter().map(|seg| seg.length).sum()
     }
-}
 
-// The const generics implementation in Rust is just wonderful.
-const fn i32_to_usize(n: i32) -> usize { n as usize }
+    // Returns the signed distance along the road from from_t to to_t. If the
+    // track is a closed loop, the shorter way around is taken, so the result
+    // is in the range (-total_length/2, total_length/2].
+    pub fn distance_between(&self, from_t: i32, to_t: i32, looping: bool) -> i32 {
+        let d = to_t - from_t;
+        if !looping {
+            return d;
+        }
+        let length = self.total_length();
+        if length <= 0 {
+            return d;
+        }
+        let d = d.rem_euclid(length);
+        if d > length / 2 {
+            d - length
+        } else {
+            d
+        }
+    }
 
-pub struct RoadRenderer<'a> {
-    segments: &'a [Segment], // The road is built out of segments with constant curvature and style.
-    cur_segment: usize,      // Index of the current segment
-    near: i32,               // Near plane, practically just controls field of view
-    cur_t: i32,              // Distance from the start of the road
-    base_t: i32,             // Distance of the current segment from the start of the road
-}
+    // Returns the x_curve of the road at distance t from the start of the
+    // road. Past the end of the road, the road is straight.
+    pub fn curvature_at(&self, t: i32) -> i32 {
+        let mut base_t = 0;
+        for seg in self.segments.iter() {
+            if t < base_t + seg.length {
+                return seg.x_curve;
+            }
+            base_t += seg.length;
+        }
+        0
+    }
 
-// Per-line visibility information, needed for road rendering.
-#[derive(Copy, Clone)]
-pub struct LineVisibility {
-    // If the line is above road horizon, the range between begin and end is
-    // available. Otherwise, it is masked.
-    begin: i32,
-    end: i32,
-}
+    // Walks the road from the start until t, like a car would drive along it
+    // seen from above. See top_down_step() for the coordinate system.
+    fn walk_top_down(&self, t: i32, heading: &mut i32, world_x: &mut i32, world_z: &mut i32) {
+        let mut t_left = t;
+        for seg in self.segments.iter() {
+            if t_left <= 0 {
+                break;
+            }
+            let length = t_left.min(seg.length);
+            t_left -= length;
+            top_down_step(seg.x_curve, length, heading, world_x, world_z);
+        }
+    }
 
-impl<'a> RoadRenderer<'a> {
-    pub fn new(segments: &'a [Segment], near: i32) -> Self {
-        Self {
-            segments,
-            cur_segment: 0,
-            near,
-            cur_t: 0,
-            base_t: 0,
+    // Returns the sums of x_curve and y_curve over distance ahead of the
+    // camera, each weighted by the length of road it applies to (FP1), e.g.
+    // for curve warning signs and for AI slowing down before a bend. The
+    // signs are those of the segments: a positive x sum bends left, like
+    // "curve left" in track!(), and a positive y sum is a crest. Curves wrap
+    // around looping tracks, and the road past the end is straight.
+    pub fn lookahead(&self, distance: i32) -> (i32, i32) {
+        let mut left = distance;
+        let mut x_sum: i64 = 0;
+        let mut y_sum: i64 = 0;
+        for k in 0.. {
+            let Some(index) = self.segment_ahead(k) else { break };
+            if left <= 0 {
+                break;
+            }
+            let seg = &self.segments[index];
+            let length = if k == 0 {
+                // Before the start of the road, the camera is on straight
+                // road leading to the first segment.
+                let local_t = self.cursor.local_t();
+                left += local_t.min(0);
+                seg.length - local_t.max(0)
+            } else {
+                seg.length
+            }.clamp(0, left.max(0));
+            left -= length;
+            x_sum += seg.x_curve as i64 * length as i64;
+            y_sum += seg.y_curve as i64 * length as i64;
         }
+        let clamp = |sum: i64| (sum >> FP_POS).clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        (clamp(x_sum), clamp(y_sum))
     }
 
-    pub fn advance(&mut self, step: i32) {
-        self.cur_t += step;
-        while self.cur_segment < self.segments.len()
-            && self.cur_t >= self.base_t + self.segments[self.cur_segment].length
-        {
-            self.base_t += self.segments[self.cur_segment].length;
-            self.cur_segment += 1;
+    // Returns an iterator over points on the center of the road seen from
+    // above, every step units of t from the start to the end of the road.
+    pub fn centerline(&self, step: i32) -> Centerline<'_> {
+        Centerline {
+            segments: &self.segments,
+            step: step.max(1),
+            index: 0,
+            local_t: 0,
+            point: Some(CenterlinePoint {
+                t: 0,
+                x: 0,
+                z: 0,
+                heading: 0,
+            }),
         }
     }
 
-    pub fn set(&mut self, t: i32) {
-        self.cur_t = 0;
-        self.base_t = 0;
-        self.cur_segment = 0;
-        self.advance(t);
+    // Returns the segments in view from the camera, nearest first, with the
+    // rows they cover on a w x h screen. Segments hidden behind nearer road,
+    // e.g. past a crest, are left out. This is computed from the track
+    // without rendering, but gives the same segments render() would draw
+    // with the same arguments, up to the accuracy of sampling hills at a few
+    // points.
+    pub fn visible_segments(
+        &self,
+        (w, h): (i32, i32),
+        camera_y_offset: i32, // FP1
+        max_z: i32,           // FP1
+    ) -> VisibleSegments<'_> {
+        VisibleSegments {
+            segments: &self.segments,
+            near: self.near,
+            h,
+            center_y: self.view_center((w, h)).1,
+            max_z,
+            index: self.cursor.segment,
+            local_t: self.cursor.t - self.cursor.base_t,
+            y_offset: camera_y_offset,
+            z_offset: 0,
+            y_slope: 0,
+            top: h,
+        }
+    }
+
+    // Returns the top-down heading of the road at distance t from the start of
+    // the road, as a binary angle (see FULL_TURN). Zero is the heading at the
+    // start of the road, positive values turn right.
+    pub fn heading_at(&self, t: i32) -> i32 {
+        let mut heading = 0;
+        let mut world_x = 0;
+        let mut world_z = 0;
+        self.walk_top_down(t, &mut heading, &mut world_x, &mut world_z);
+        heading
+    }
+
+    // Returns the height of the road at distance t from the start of the
+    // road in FP1, relative to the start. Like heading_at(), but for the
+    // hills, which are integrated like render() does. Positive heights are
+    // above the start.
+    pub fn elevation_at(&self, t: i32) -> i32 {
+        let (mut x_offset, mut y_offset, mut x_slope, mut y_slope) = (0, 0, 0, 0);
+        let mut t_left = t;
+        for seg in self.segments.iter() {
+            if t_left <= 0 {
+                break;
+            }
+            let length = t_left.min(seg.length);
+            t_left -= length;
+            // Only the vertical state is needed, and the depth is reset so
+            // that long tracks can't overflow it.
+            let mut z_offset = 0;
+            integrate_segment(
+                0,
+                seg.y_curve,
+                length,
+                &mut x_offset,
+                &mut y_offset,
+                &mut z_offset,
+                &mut x_slope,
+                &mut y_slope,
+            );
+        }
+        -y_offset
+    }
+
+    // Converts a road-space position into top-down world coordinates, e.g. for
+    // drawing a minimap. t is the distance from the start of the road, x is
+    // the lateral offset from the road center in FP1 (positive is right). The
+    // start of the road is at the origin, heading towards +z.
+    pub fn world_position(&self, t: i32, x: i32, world_x: &mut i32, world_z: &mut i32) {
+        let mut heading = 0;
+        *world_x = 0;
+        *world_z = 0;
+        self.walk_top_down(t, &mut heading, world_x, world_z);
+        *world_x += (x * (icos(heading) >> FP_POS)) >> FP_POS;
+        *world_z -= (x * (isin(heading) >> FP_POS)) >> FP_POS;
+    }
+
+    // Returns the vertical slope of the road at distance t from the start of
+    // the road, relative to the start of the road. FP1, positive values go
+    // downhill. The slope is accumulated exactly like when rendering.
+    pub fn grade_at(&self, t: i32) -> i32 {
+        let mut x_offset = 0;
+        let mut y_offset = 0;
+        let mut z_offset = 0;
+        let mut x_slope = 0;
+        let mut y_slope = 0;
+        let mut t_left = t;
+        for index in 0..self.segments.len() {
+            if t_left <= 0 {
+                break;
+            }
+            let length = t_left.min(self.segments[index].length);
+            self.update_state_at_segment_length(
+                index,
+                length,
+                &mut x_offset,
+                &mut y_offset,
+                &mut z_offset,
+                &mut x_slope,
+                &mut y_slope,
+            );
+            t_left -= length;
+        }
+        y_slope
     }
 
     fn render_sky<P: Painter>(
@@ -124,26 +2061,139 @@ impl<'a> RoadRenderer<'a> {
         road_horizon: i32,
         visibility: &[LineVisibility]
     ) {
+        if !self.sky_layers.is_empty() || (self.horizon_fade > 0 && self.column_horizons.is_some()) {
+            self.render_layered_sky(painter, (w, h), road_horizon, visibility);
+            return;
+        }
+
         for y in 0..road_horizon {
-            let color = painter.sky_color(y);
+            let color = painter.sky_color(y, self.frame);
             let line = &visibility[y as usize];
-            for x in (line.begin as i32)..(line.end as i32) {
+            for x in line.begin()..line.end() {
                 painter.draw(x, y, &color);
             }
         }
 
         for y in road_horizon..h {
-            let color = painter.sky_color(y);
+            let color = painter.sky_color(y, self.frame);
             let line = &visibility[y as usize];
-            for x in 0..(line.begin as i32) {
+            for x in 0..line.begin() {
                 painter.draw(x, y, &color);
             }
-            for x in (line.end as i32)..w {
+            for x in line.end()..w {
                 painter.draw(x, y, &color);
             }
         }
     }
 
+    // render_sky() with sky layers or the horizon fade, record_horizon() must
+    // have been called first.
+    fn render_layered_sky<P: Painter>(
+        &self,
+        painter: &mut P,
+        (w, h): (i32, i32),
+        road_horizon: i32,
+        visibility: &[LineVisibility]
+    ) {
+        let columns = self.column_horizons.as_deref().unwrap_or(&[]);
+        let rows = if columns.is_empty() { 0 } else { self.horizon_fade };
+        let ground = painter.ground_color(0, self.far_t, self.frame);
+        let (heading, elevation) = if self.sky_layers.is_empty() {
+            (0, 0)
+        } else {
+            (self.heading_at(self.cursor.t), self.elevation_at(self.cursor.t))
+        };
+        for y in 0..h {
+            let sky = painter.sky_color(y, self.frame);
+            let line = &visibility[y as usize];
+            let (a, b) = if y < road_horizon {
+                ((line.begin(), line.end()), (0, 0))
+            } else {
+                ((0, line.begin()), (line.end(), w))
+            };
+            for x in (a.0..a.1).chain(b.0..b.1) {
+                let layered = self.sky_layer_color(painter, (heading, elevation), x, y);
+                let color = layered.as_ref().unwrap_or(&sky);
+                let distance = columns.get(x as usize).map_or(h, |&c| c) - y;
+                let blended = if distance > 0 && distance <= rows {
+                    let amount = (distance << FP_POS) / (rows + 1); // FP1
+                    painter.blend_color(&ground, color, amount)
+                } else {
+                    None
+                };
+                painter.draw(x, y, blended.as_ref().unwrap_or(color));
+            }
+        }
+    }
+
+    // The color of the frontmost sky layer covering the pixel, if any.
+    fn sky_layer_color<P: Painter>(
+        &self,
+        painter: &P,
+        (heading, elevation): (i32, i32),
+        x: i32,
+        y: i32
+    ) -> Option<P::ColorType> {
+        for (index, layer) in self.sky_layers.iter().enumerate().rev() {
+            let (dx, dy) = layer.offset(heading, elevation, self.frame);
+            let color = painter.sky_layer_color(index, x.wrapping_add(dx), y.wrapping_add(dy), self.frame);
+            if color.is_some() {
+                return color;
+            }
+        }
+        None
+    }
+
+    fn record_horizon(
+        &mut self,
+        (w, h): (i32, i32),
+        road_horizon: i32,
+        visibility: &[LineVisibility]
+    ) {
+        self.horizon = h;
+        for y in 0..road_horizon {
+            let line = &visibility[y as usize];
+            if line.begin() > 0 || line.end() < w {
+                self.horizon = y;
+                break;
+            }
+        }
+        if self.horizon == h && road_horizon < h {
+            self.horizon = road_horizon;
+        }
+
+        let columns = match self.column_horizons.as_mut() {
+            Some(columns) => columns,
+            None => return,
+        };
+        for c in columns.iter_mut() {
+            *c = h;
+        }
+        let w = w.min(columns.len() as i32);
+
+        // Above the road horizon, the masked parts of a line are terrain.
+        // Below it, the visible part is.
+        let mut remaining = w;
+        for y in 0..h {
+            let line = &visibility[y as usize];
+            let (a, b) = if y < road_horizon {
+                ((0, line.begin()), (line.end(), w))
+            } else {
+                ((line.begin(), line.end()), (0, 0))
+            };
+            for x in (a.0.max(0)..a.1.min(w)).chain(b.0.max(0)..b.1.min(w)) {
+                let c = &mut columns[x as usize];
+                if *c == h {
+                    *c = y;
+                    remaining -= 1;
+                }
+            }
+            if remaining == 0 {
+                break;
+            }
+        }
+    }
+
     fn update_state_at_segment_length(
         &self,
         index: usize,
@@ -154,95 +2204,402 @@ impl<'a> RoadRenderer<'a> {
         x_slope: &mut i32,  // FP1
         y_slope: &mut i32,  // FP1
     ) {
-        let y_curve = self.segments[index].y_curve;
-        let x_curve = self.segments[index].x_curve;
-        let z;
+        let seg = self.segments[index];
+        integrate_segment(
+            seg.x_curve,
+            seg.y_curve,
+            length,
+            x_offset,
+            y_offset,
+            z_offset,
+            x_slope,
+            y_slope,
+        );
+    }
 
-        if y_curve == 0 {
-            // Flat plane as far as Y axis is concerned
-            let t_factor = isqrt((1 << (2 * FP_POS)) + *y_slope * *y_slope); // FP1
+    // Walks the road from the camera until point_t_offset, accumulating the
+    // same state as rendering does.
+    fn integrate_to(
+        &self,
+        point_t_offset: i32,
+        x_offset: &mut i32, // FP1
+        y_offset: &mut i32, // FP1
+        z_offset: &mut i32, // FP1
+        x_slope: &mut i32,  // FP1
+        y_slope: &mut i32,  // FP1
+    ) {
+        let mut t_left = point_t_offset;
+        let mut first = 0;
 
-            z = (length << FP_POS) / t_factor; // FP1
-            *y_offset += (*y_slope * z) >> FP_POS; // FP1
-        } else {
-            let abs_y_curve = if y_curve < 0 { -y_curve } else { y_curve };
-            let tsqrtcurve = isqrt(abs_y_curve << FP_POS); // FP1
-            let z2 = 4 * length / tsqrtcurve;
-            z = isqrt(z2 << FP_POS) << (FP_POS / 2); // FP1
+        if let (Some((t, count)), Some(states)) = (self.prepared, self.segment_states.as_ref()) {
+            let states = &states[..count];
+            let index = states.partition_point(|state| state.t_offset <= point_t_offset);
+            if t == self.cursor.t && index > 0 {
+                let state = &states[index - 1];
+                *x_offset += state.x_offset;
+                *y_offset += state.y_offset;
+                *z_offset += state.z_offset;
+                *x_slope += state.x_slope;
+                *y_slope += state.y_slope;
+                t_left -= state.t_offset;
+                first = index - 1;
+                if t_left == 0 {
+                    return;
+                }
+            }
+        }
+
+        for k in first.. {
+            let Some(render_segment) = self.segment_ahead(k) else { break };
+            let seg = self.segments[render_segment];
+            let length_left = seg.length - (if k == 0 {
+                self.cursor.t - self.cursor.base_t
+            } else {
+                0
+            });
+            let length = if t_left < length_left { t_left } else { length_left };
+            self.update_state_at_segment_length(
+                render_segment,
+                length,
+                x_offset,
+                y_offset,
+                z_offset,
+                x_slope,
+                y_slope,
+            );
+            t_left -= length;
+            if t_left == 0 {
+                break;
+            }
+        }
+    }
+
+    // Projects a point given relative to the camera: point_t_offset along
+    // the road from the camera, and point_x_offset and point_y_offset (FP1)
+    // from the center of the road surface there.
+    pub fn screen_pos(
+        &self,
+        (w, h): (i32, i32),
+        camera_x_offset: i32,
+        camera_y_offset: i32,
+        point_t_offset: i32,
+        point_x_offset: i32,
+        point_y_offset: i32
+    ) -> ScreenPos {
+        let mut x_offset = camera_x_offset;
+        let mut y_offset = camera_y_offset;
+        let mut z_offset = 0;
+        let mut x_slope = 0;
+        let mut y_slope = 0;
+        self.integrate_to(
+            point_t_offset,
+            &mut x_offset,
+            &mut y_offset,
+            &mut z_offset,
+            &mut x_slope,
+            &mut y_slope,
+        );
+        let behind_camera = z_offset <= 0;
+
+        // Prevent division by zero.
+        if z_offset == 0 {
+            z_offset = 1;
+        }
+
+        let (cx, cy) = self.view_center((w, h));
+        ScreenPos {
+            x: cx+((self.near*(point_x_offset - x_offset))/z_offset),
+            y: cy+((self.near*(y_offset - point_y_offset))/z_offset),
+            inv_z: (1<<(3*FP_POS))/z_offset,
+            behind_camera,
+        }
+    }
+
+    #[deprecated(note = "use screen_pos(), which returns the results in a ScreenPos")]
+    pub fn get_screen_pos(
+        &self,
+        size: (i32, i32),
+        camera_x_offset: i32,
+        camera_y_offset: i32,
+        point_t_offset: i32,
+        point_x_offset: i32,
+        point_y_offset: i32,
+        x_px: &mut i32, // FP1 screen coordinate
+        y_px: &mut i32, // FP1 screen coordinate
+        inv_z: &mut i32  // 1/z, FP3, negative values are behind camera
+    ) {
+        let pos = self.screen_pos(
+            size,
+            camera_x_offset,
+            camera_y_offset,
+            point_t_offset,
+            point_x_offset,
+            point_y_offset
+        );
+        (*x_px, *y_px, *inv_z) = (pos.x, pos.y, pos.inv_z);
+    }
+
+    // Checks if a point would be visible in the last rendered frame. The point
+    // is given like in screen_pos(). Without any buffers attached, this
+    // only checks that the point is on screen and in front of the camera. The
+    // column horizon buffer lets points above all terrain pass, and the row
+    // depth buffer rejects points hidden behind nearer road, e.g. past a
+    // crest.
+    pub fn is_visible(
+        &self,
+        (w, h): (i32, i32),
+        camera_x_offset: i32,
+        camera_y_offset: i32,
+        point_t_offset: i32,
+        point_x_offset: i32,
+        point_y_offset: i32
+    ) -> bool {
+        let mut x_offset = camera_x_offset;
+        let mut y_offset = camera_y_offset;
+        let mut z_offset = 0;
+        let mut x_slope = 0;
+        let mut y_slope = 0;
+        self.integrate_to(
+            point_t_offset,
+            &mut x_offset,
+            &mut y_offset,
+            &mut z_offset,
+            &mut x_slope,
+            &mut y_slope,
+        );
+        if z_offset <= 0 {
+            return false;
+        }
+
+        let (cx, cy) = self.view_center((w, h));
+        let x_px = cx+((self.near*(point_x_offset - x_offset))/z_offset);
+        let y_px = cy+((self.near*(y_offset - point_y_offset))/z_offset);
+        if x_px < 0 || x_px >= w || y_px < 0 || y_px >= h {
+            return false;
+        }
+
+        if let Some(horizon) = self.column_horizon(x_px) {
+            if y_px < horizon {
+                return true;
+            }
+        }
+
+        match self.row_depths.as_ref().and_then(|d| d.get(y_px as usize)) {
+            // Allow some slack, points on the road itself hit the same row.
+            Some(&depth) => depth >= z_offset - (z_offset >> 4),
+            None => true,
+        }
+    }
+
+    // Returns the lateral position of the road center at point_t_offset from
+    // the camera, in FP1. It is measured along the camera's own x axis, so it
+    // changes as the road curves away.
+    pub fn center_offset_at(&self, point_t_offset: i32) -> i32 {
+        let mut x_offset = 0;
+        let mut y_offset = 0;
+        let mut z_offset = 0;
+        let mut x_slope = 0;
+        let mut y_slope = 0;
+        self.integrate_to(
+            point_t_offset,
+            &mut x_offset,
+            &mut y_offset,
+            &mut z_offset,
+            &mut x_slope,
+            &mut y_slope,
+        );
+        -x_offset
+    }
+
+    // Maps a lateral offset from the road center x (FP1) at from_t_offset to
+    // the offset from the road center at to_t_offset that is on the same line
+    // along the camera's x axis. Going the other way, an object which keeps
+    // its offset from the road center stays in its lane through curves,
+    // while one that keeps its position on the camera's x axis drifts across
+    // the road.
+    pub fn transform_lateral(&self, x: i32, from_t_offset: i32, to_t_offset: i32) -> i32 {
+        x + self.center_offset_at(from_t_offset) - self.center_offset_at(to_t_offset)
+    }
+
+    // Returns the screen-space size of one world unit at point_t_offset, in
+    // FP1 pixels. This is the same scale screen_pos() projects with, so
+    // sprites scaled by it stay consistent with projected positions. Negative
+    // values are behind camera.
+    pub fn scale_at(&self, point_t_offset: i32) -> i32 {
+        let mut x_offset = 0;
+        let mut y_offset = 0;
+        let mut z_offset = 0;
+        let mut x_slope = 0;
+        let mut y_slope = 0;
+        self.integrate_to(
+            point_t_offset,
+            &mut x_offset,
+            &mut y_offset,
+            &mut z_offset,
+            &mut x_slope,
+            &mut y_slope,
+        );
 
-            *y_offset += y_curve * z2 + ((*y_slope * z) >> FP_POS); // FP1
-            *y_slope += (y_curve * z * 2) >> FP_POS; // FP1
+        // Prevent division by zero.
+        if z_offset == 0 {
+            z_offset = 1;
         }
-        *z_offset += z;
 
-        if x_curve == 0 {
-            // X-axis is linear.
-            *x_offset += (*x_slope * z) >> FP_POS; // FP1
-        } else {
-            *x_offset += ((x_curve * z >> FP_POS) * z >> FP_POS) + (*x_slope * z >> FP_POS); // FP1
-            *x_slope += 2 * x_curve * z >> FP_POS; // FP1
-        }
+        let inv_z = (1<<(3*FP_POS))/z_offset; // FP3
+        (self.near * inv_z) >> FP_POS
     }
 
-    pub fn get_screen_pos(
+    // Finds where the road heads towards at the draw distance, i.e. the point
+    // where the road would meet the horizon if it continued straight from
+    // there on. max_z should be the same as given to render().
+    pub fn get_vanishing_point(
         &self,
         (w, h): (i32, i32),
-        camera_x_offset: i32,
-        camera_y_offset: i32,
-        point_t_offset: i32,
-        point_x_offset: i32,
-        point_y_offset: i32,
-        x_px: &mut i32, // FP1 screen coordinate
-        y_px: &mut i32, // FP1 screen coordinate
-        inv_z: &mut i32  // 1/z, FP3, negative values are behind camera
+        max_z: i32,
+        x_px: &mut i32,
+        y_px: &mut i32
     ) {
-        let mut x_offset = camera_x_offset;
-        let mut y_offset = camera_y_offset;
+        let mut x_offset = 0;
+        let mut y_offset = 0;
         let mut z_offset = 0;
         let mut x_slope = 0;
         let mut y_slope = 0;
-        let mut t_left = point_t_offset;
 
-        for render_segment in self.cur_segment..self.segments.len() {
-            let seg = &self.segments[render_segment];
-            let length_left = seg.length - (if render_segment == self.cur_segment {
-                self.cur_t - self.base_t
+        for k in 0.. {
+            let Some(render_segment) = self.segment_ahead(k) else { break };
+            let local_t = if k == 0 {
+                self.cursor.t - self.cursor.base_t
             } else {
                 0
-            });
-            let length = if t_left < length_left { t_left } else { length_left };
+            };
             self.update_state_at_segment_length(
                 render_segment,
-                length,
+                self.segments[render_segment].length - local_t,
                 &mut x_offset,
                 &mut y_offset,
                 &mut z_offset,
                 &mut x_slope,
                 &mut y_slope,
             );
-            t_left -= length;
-            if t_left == 0 {
+            if z_offset > max_z {
                 break;
             }
         }
 
-        // Prevent division by zero.
-        if z_offset == 0 {
-            z_offset = 1;
+        let (cx, cy) = self.view_center((w, h));
+        *x_px = cx - ((self.near * x_slope) >> FP_POS);
+        *y_px = cy + ((self.near * y_slope) >> FP_POS);
+    }
+
+    // Projects a rectangle on the road surface to the screen. t_range is
+    // relative to the camera like point_t_offset in screen_pos(), tx_range
+    // is FP2 like in Painter::road_color. The corners are written in the order
+    // near-left, near-right, far-right, far-left. The part of the rectangle
+    // closer than one unit to the camera is clipped away, false is returned if
+    // nothing remains.
+    pub fn project_road_quad(
+        &self,
+        (w, h): (i32, i32),
+        camera_x_offset: i32,
+        camera_y_offset: i32,
+        t_range: (i32, i32),
+        tx_range: (i32, i32),
+        corners: &mut [(i32, i32); 4]
+    ) -> bool {
+        let t_near = t_range.0.min(t_range.1).max(1 << FP_POS);
+        let t_far = t_range.0.max(t_range.1);
+        if t_far < t_near {
+            return false;
+        }
+
+        let points = [
+            (t_near, tx_range.0),
+            (t_near, tx_range.1),
+            (t_far, tx_range.1),
+            (t_far, tx_range.0),
+        ];
+        for (corner, &(t, tx)) in corners.iter_mut().zip(points.iter()) {
+            let pos = self.screen_pos((w, h), camera_x_offset, camera_y_offset, t, tx >> FP_POS, 0);
+            *corner = (pos.x, pos.y);
+        }
+        true
+    }
+
+    // Returns the distance at which row y is colored.
+    #[cfg(feature = "motion-blur")]
+    fn paint_t(&mut self, h: i32, y: i32, t_global: i32) -> i32 {
+        // Rows are rendered from the bottom up, so the row below is the
+        // previous one. The road's tx step is applied as usual, so the
+        // repeated colors follow the road.
+        let row = h - 1 - y;
+        if self.speed > self.blur_threshold && row < self.blur_rows && row & 1 == 1 {
+            self.blur_t
+        } else {
+            self.blur_t = t_global;
+            t_global
         }
+    }
+
+    #[cfg(not(feature = "motion-blur"))]
+    fn paint_t(&mut self, _h: i32, _y: i32, t_global: i32) -> i32 {
+        t_global
+    }
+
+    // Checks the invariants of the visibility buffer after row y was
+    // rendered: every begin and end is on screen, the row itself isn't
+    // inverted, and rows are rendered bottom-up, each only once. Panics with
+    // the row and its projection on failure, so corrupted masks are caught
+    // where they happen rather than as garbage further up the screen.
+    #[cfg(feature = "strict")]
+    fn check_row(&mut self, w: i32, y: i32, z: i32, tx_step: i32, visibility: &[LineVisibility]) {
+        assert!(
+            y < self.strict_y,
+            "row {} rendered after row {} (z {}, t {})",
+            y, self.strict_y, z, self.cursor.t
+        );
+        self.strict_y = y;
+        let line = &visibility[y as usize];
+        assert!(
+            line.begin() <= line.end(),
+            "row {} inverted: begin {} > end {} (z {}, tx_step {}, t {})",
+            y, line.begin(), line.end(), z, tx_step, self.cursor.t
+        );
+        for (y0, l) in visibility.iter().enumerate() {
+            assert!(
+                (0..=w).contains(&l.begin()) && (0..=w).contains(&l.end()),
+                "row {} out of screen after row {}: begin {}, end {}, width {} (z {}, t {})",
+                y0, y, l.begin(), l.end(), w, z, self.cursor.t
+            );
+        }
+    }
+
+    #[cfg(not(feature = "strict"))]
+    #[inline(always)]
+    fn check_row(&mut self, _w: i32, _y: i32, _z: i32, _tx_step: i32, _visibility: &[LineVisibility]) {
+    }
 
-        *inv_z = (1<<(3*FP_POS))/z_offset;
-        *x_px = w/2+((self.near*(point_x_offset - x_offset))/z_offset);
-        *y_px = h/2+((self.near*(y_offset - point_y_offset))/z_offset);
+    // Returns true if the colors of row y may be copied from the row below.
+    fn skip_row(&mut self, h: i32, y: i32, z: i32) -> bool {
+        if self.lod_rows <= 1 || z <= self.lod_z || y + 1 >= h {
+            self.lod_count = 0;
+            return false;
+        }
+        self.lod_count += 1;
+        if self.lod_count >= self.lod_rows {
+            self.lod_count = 0;
+        }
+        self.lod_count != 0
     }
 
+    #[allow(clippy::unnecessary_cast)] // Wide is i32 without wide-math
     fn render_road_line<P: Painter>(
         &mut self,
         painter: &mut P,
         (w, h): (i32, i32),
         style: (SideInclination, SideInclination),
-        base_tx: i32,  // FP1
-        x_offset: i32, // FP1
+        road_width: i32, // FP2
+        base_tx: i32,  // See INV_NEAR_POS
+        x_offset: i32, // FP2
         x_slope: i32,  // FP1
         x_curve: i32,  // FP1
         y: i32,
@@ -251,42 +2608,63 @@ impl<'a> RoadRenderer<'a> {
         t_global: i32, // FP1
         visibility: &mut [LineVisibility],
     ) {
-        let tx_step = base_tx * z; // FP2
+        let tx_step = (base_tx * z) >> INV_NEAR_SHIFT; // FP2
+        self.far_t = t_global;
+        #[cfg(feature = "strict")]
+        assert!(
+            tx_step > 0 && y >= 0 && y < h,
+            "bad projection on row {}: base_tx {}, z {}, height {}",
+            y, base_tx, z, h
+        );
 
-        let z_tmp = z_local >> (FP_POS / 2); // FP0.5
+        // At column 0
+        let mut tx = tx_step as Wide * -(w as Wide) / 2 + x_offset as Wide
+            + x_slope as Wide * z_local as Wide; // FP2
+        if self.look_x != 0 {
+            tx += tx_step as Wide * self.look_x as Wide;
+        }
+        if x_curve != 0 {
+            // Straight segments, most of a typical track, skip the quadratic
+            // term.
+            tx += x_curve as Wide * Backend::mul_shr(z_local, z_local, FP_POS) as Wide; // FP2
+        }
+        // Within the road, tx fits in an i32 again.
+        let tx_at = |x: i32| (tx + tx_step as Wide * x as Wide) as i32;
 
-        let mut tx =
-            tx_step * -w / 2 + (x_offset << FP_POS) + x_curve * z_tmp * z_tmp + x_slope * z_local; // FP2
+        let clamp = |x: Wide| x.clamp(i32::MIN as Wide, i32::MAX as Wide) as i32;
+        let road_left = clamp(1 - (1 + road_width as Wide + tx) / tx_step as Wide);
+        let road_right = clamp(1 + (road_width as Wide - tx) / tx_step as Wide);
 
-        let road_width = painter.road_width();
-        let road_left = 1 - (1 + road_width + tx) / tx_step;
-        let road_right = 1 + (road_width - tx) / tx_step;
+        // t used for colors, differs from t_global on blurred rows
+        let paint_t = self.paint_t(h, y, t_global);
+        painter.begin_road_row(y, road_left, road_right, paint_t);
 
         let mut line = visibility[y as usize];
-        let road_begin = road_left.max(line.begin as i32).min(line.end as i32);
-        let road_end = road_right.max(line.begin as i32).min(line.end as i32);
+        let road_begin = road_left.max(line.begin()).min(line.end());
+        let road_end = road_right.max(line.begin()).min(line.end());
 
-        let side_color = painter.ground_color(0, t_global);
+        let fog = self.row_fog(painter, z);
+        let side_color = fogged(painter, &fog, painter.ground_color(0, paint_t, self.frame));
         // Left side of road
         match style.0 {
             SideInclination::Uphill => {
-                for x in (line.begin as i32)..road_left {
+                for x in line.begin()..road_left {
                     let mut x0 = x;
                     let mut y_start = y+1;
                     if x0 >= w {
                         y_start -= x0 - w + 1;
                         x0 = w-1;
 
-                        if y_start <= 0 || visibility[(y_start-1) as usize].begin as i32 > x0 {
+                        if y_start <= 0 || visibility[(y_start-1) as usize].begin() > x0 {
                             continue;
                         }
                     }
 
                     for y0 in (0..(y_start)).rev() {
                         let l = &mut visibility[y0 as usize];
-                        l.begin = l.begin.max(x0 + 1);
+                        l.set_begin(l.begin().max(x0 + 1));
 
-                        if l.end as i32 > x0 {
+                        if l.end() > x0 {
                             painter.draw(x0, y0, &side_color);
                         }
                         x0 -= 1;
@@ -298,26 +2676,26 @@ impl<'a> RoadRenderer<'a> {
                     }
                 }
 
-                line.begin = 0;
+                line.set_begin(0);
             },
             SideInclination::Flat => {
-                for x in (line.begin as i32)..road_begin {
+                for x in line.begin()..road_begin {
                     painter.draw(x, y, &side_color);
                 }
-                line.begin = 0;
+                line.set_begin(0);
             },
             SideInclination::Downhill => {
                 let y_start = y+1;
                 if y_start < h {
-                    let end = (visibility[y_start as usize].begin as i32).min(w);
+                    let end = visibility[y_start as usize].begin().min(w);
                     for x in ((road_begin-1).max(0)..end).rev() {
                         let mut x0 = x;
                         for y0 in y_start..h {
                             let l = &mut visibility[y0 as usize];
-                            if l.begin <= x0 {
+                            if l.begin() <= x0 {
                                 break;
                             } else {
-                                l.begin = x0;
+                                l.set_begin(x0);
                                 painter.draw(x0, y0, &side_color);
                             }
                             x0 -= 1;
@@ -330,43 +2708,98 @@ impl<'a> RoadRenderer<'a> {
                     }
                 }
 
-                if line.begin > 0 {
-                    line.begin = 0;
+                if line.begin() > 0 {
+                    line.set_begin(0);
                 } else {
-                    line.begin = road_begin;
+                    line.set_begin(road_begin);
+                }
+            }
+            SideInclination::Wall => {
+                // Kept in FP2 so that steps smaller than one FP1 unit add up.
+                let height_step = Backend::div(z << FP_POS, self.near); // FP2
+                for x0 in line.begin()..road_left.min(w) {
+                    let mut height = 0; // FP2
+                    for y0 in (0..=y).rev() {
+                        let l = &mut visibility[y0 as usize];
+                        if l.begin() <= x0 && l.end() > x0 {
+                            let color = fogged(painter, &fog, painter.wall_color(paint_t, height >> FP_POS, self.frame));
+                            painter.draw(x0, y0, &color);
+                        }
+                        l.set_begin(l.begin().max(x0 + 1));
+                        height += height_step;
+                    }
+                }
+                line.set_begin(0);
+            }
+        }
+
+        if let Some(depth) = self.row_depths.as_mut().and_then(|d| d.get_mut(y as usize)) {
+            *depth = z;
+        }
+
+        // Rows are rendered from near to far, so the first sample written to a
+        // column is the nearest one.
+        if let Some(samples) = self.road_samples.as_mut() {
+            let end = road_end.min(samples.len() as i32);
+            for x in road_begin.max(0)..end {
+                let sample = &mut samples[x as usize];
+                if sample.is_none() {
+                    *sample = Some(RoadSample {
+                        t: t_global,
+                        tx: tx_at(x),
+                        z,
+                    });
                 }
             }
         }
 
         // Center part of road, could be fully hidden in which case
-        // road_begin >= road_end.
-        tx += tx_step * road_begin;
-        for x in road_begin..road_end {
-            let color = painter.road_color(tx, t_global);
-            painter.draw(x, y, &color);
-            tx += tx_step;
+        // road_begin >= road_end. Rows skipped by the LOD mode repeat the
+        // row below, following the road center.
+        let center = (road_left + road_right) >> 1;
+        let copied = self.skip_row(h, y, z)
+            && painter.copy_row(y, road_begin, road_end, y + 1, self.lod_center - center);
+        self.lod_center = center;
+        if !copied {
+            let mut tx = tx_at(road_begin);
+            let mut x = road_begin;
+            while x < road_end {
+                let (color, run) = painter.road_color_span(tx, paint_t, self.frame);
+                let color = fogged(painter, &fog, color);
+                let pixels = if run > tx_step { (run + tx_step - 1) / tx_step } else { 1 };
+                let end = (x + pixels).min(road_end);
+                painter.fill_span(x, end, y, &color);
+                tx += tx_step * (end - x);
+                x = end;
+            }
+        }
+        if let Some(lines) = self.speed_lines.as_mut() {
+            lines.record_road(y, road_begin, road_end);
+        }
+        if self.cats_eyes.is_some() {
+            self.render_cats_eyes(painter, (road_begin, road_end), y, z, tx_at(road_begin), tx_step, t_global);
         }
 
         // Right side of road
         match style.1 {
             SideInclination::Uphill => {
-                for x in road_right..(line.end as i32) {
+                for x in road_right..line.end() {
                     let mut x0 = x;
                     let mut y_start = y+1;
                     if x0 < 0 {
                         y_start += x0;
                         x0 = 0;
 
-                        if y_start <= 0 || visibility[(y_start-1) as usize].end <= 0 {
+                        if y_start <= 0 || visibility[(y_start-1) as usize].end() <= 0 {
                             continue;
                         }
                     }
 
                     for y0 in (0..(y_start)).rev() {
                         let l = &mut visibility[y0 as usize];
-                        l.end = l.end.min(x0);
+                        l.set_end(l.end().min(x0));
 
-                        if l.begin as i32 <= x0 {
+                        if l.begin() <= x0 {
                             painter.draw(x0, y0, &side_color);
                         }
 
@@ -378,27 +2811,26 @@ impl<'a> RoadRenderer<'a> {
                         }
                     }
                 }
-                line.end = w;
+                line.set_end(w);
             },
             SideInclination::Flat => {
-                let color = painter.ground_color(0, t_global);
-                for x in road_end..(line.end as i32) {
-                    painter.draw(x, y, &color);
+                for x in road_end..line.end() {
+                    painter.draw(x, y, &side_color);
                 }
-                line.end = w;
+                line.set_end(w);
             },
             SideInclination::Downhill => {
                 let y_start = y+1;
                 if y_start < h {
-                    let start = (visibility[y_start as usize].end as i32).max(0);
+                    let start = visibility[y_start as usize].end().max(0);
                     for x in start..(road_end+1).min(w) {
                         let mut x0 = x;
                         for y0 in y_start..h {
                             let l = &mut visibility[y0 as usize];
-                            if l.end >= x0 + 1 {
+                            if l.end() > x0 {
                                 break;
                             } else {
-                                l.end = x0 + 1;
+                                l.set_end(x0 + 1);
                                 painter.draw(x0, y0, &side_color);
                             }
                             x0 += 1;
@@ -411,15 +2843,83 @@ impl<'a> RoadRenderer<'a> {
                     }
                 }
 
-                if line.end < w {
-                    line.end = w;
+                if line.end() < w {
+                    line.set_end(w);
                 } else {
-                    line.end = road_end;
+                    line.set_end(road_end);
+                }
+            }
+            SideInclination::Wall => {
+                // Kept in FP2 so that steps smaller than one FP1 unit add up.
+                let height_step = Backend::div(z << FP_POS, self.near); // FP2
+                for x0 in (road_right.max(0)..line.end()).rev() {
+                    let mut height = 0; // FP2
+                    for y0 in (0..=y).rev() {
+                        let l = &mut visibility[y0 as usize];
+                        if l.begin() <= x0 && l.end() > x0 {
+                            let color = fogged(painter, &fog, painter.wall_color(paint_t, height >> FP_POS, self.frame));
+                            painter.draw(x0, y0, &color);
+                        }
+                        l.set_end(l.end().min(x0));
+                        height += height_step;
+                    }
                 }
+                line.set_end(w);
             }
         }
 
         visibility[y as usize] = line;
+        self.check_row(w, y, z, tx_step, visibility);
+    }
+
+    // The fog color and its opacity (FP1) at depth z, see set_fog().
+    fn row_fog<P: Painter>(&self, painter: &P, z: i32) -> Option<(P::ColorType, i32)> {
+        let (start, end) = self.fog?;
+        if z <= start {
+            return None;
+        }
+        let amount = if z >= end {
+            1 << FP_POS
+        } else {
+            ((((z - start) as i64) << FP_POS) / (end - start) as i64) as i32
+        };
+        Some((painter.fog_color(self.frame)?, amount))
+    }
+
+    // Draws the studs of cats_eyes that lie between the previous row and this
+    // one, on the columns road_begin..road_end.
+    fn render_cats_eyes<P: Painter>(
+        &mut self,
+        painter: &mut P,
+        (road_begin, road_end): (i32, i32),
+        y: i32,
+        z: i32,       // FP1
+        tx: i32,      // FP2, at column road_begin
+        tx_step: i32, // FP2
+        t: i32,       // FP1
+    ) {
+        let t0 = core::mem::replace(&mut self.marker_t, t);
+        let cats_eyes = match self.cats_eyes {
+            Some(cats_eyes) => cats_eyes,
+            None => return,
+        };
+        if road_begin >= road_end || !cats_eyes.in_span(t0, t) {
+            return;
+        }
+        let brightness = cats_eyes.brightness(z);
+        if brightness <= 0 {
+            return;
+        }
+        let pixels = (cats_eyes.width / tx_step).clamp(1, 2);
+        for (lane, &lane_tx) in cats_eyes.lanes.iter().enumerate() {
+            let x = road_begin + (lane_tx - tx).div_euclid(tx_step) - pixels / 2;
+            let (begin, end) = (x.max(road_begin), (x + pixels).min(road_end));
+            if begin < end {
+                if let Some(color) = painter.marker_color(lane, brightness, self.frame) {
+                    painter.fill_span(begin, end, y, &color);
+                }
+            }
+        }
     }
 
     fn render_road<P: Painter>(
@@ -438,21 +2938,39 @@ impl<'a> RoadRenderer<'a> {
         length: i32,   // FP1
         t_start: i32,  // FP1
         max_z: i32, // FP1
+        tables: Option<&ProjectionTables>,
         visibility: &mut [LineVisibility],
     ) {
-        let base_tx = (1 << FP_POS) / self.near; // FP1
+        let base_tx = match tables {
+            Some(tables) => tables.inv_near,
+            None => Backend::div(1 << INV_NEAR_POS, self.near),
+        }; // See INV_NEAR_POS
+        let road_width = painter.road_width(); // FP2
+        let x_offset_fp2 = x_offset << FP_POS;
 
         if y_curve == 0 {
             // Simple plane
-            let t_factor = isqrt((1 << (2 * FP_POS)) + y_slope * y_slope); // FP1
-            while *y >= 0 {
-                let vy = *y - h / 2;
-                let div = (self.near * y_slope >> FP_POS) - vy;
+            let t_factor = if y_slope == 0 {
+                1 << FP_POS
+            } else {
+                Backend::isqrt((1 << (2 * FP_POS)) + y_slope * y_slope)
+            }; // FP1
+            // z = z_offset + (z_offset * vy - y_offset * near) / div, where
+            // div = c - vy. The numerator plus z_offset * div doesn't depend
+            // on the row, and div changes by one per row, so the division can
+            // be stepped.
+            let c = (self.near * y_slope) >> FP_POS;
+            let numerator = z_offset as i64 * c as i64 - y_offset as i64 * self.near as i64;
+            let mut divider = StepDivider::new(numerator, 1);
+            while *y >= 0 && self.rows_left != 0 {
+                let vy = *y - h / 2 + self.look_y;
+                let div = c - vy;
                 if div == 0 {
                     break;
                 }
 
-                let z = z_offset + (z_offset * vy - y_offset * self.near) / div; // FP1
+                divider.set(div as i64);
+                let z = z_offset + divider.quotient_minus(z_offset as i64) as i32; // FP1
                 if z < 0 || z > max_z {
                     break;
                 }
@@ -466,32 +2984,37 @@ impl<'a> RoadRenderer<'a> {
                     painter,
                     (w, h),
                     style,
+                    road_width,
                     base_tx,
-                    x_offset,
+                    x_offset_fp2,
                     x_slope,
                     x_curve,
                     *y,
                     z,
                     z - z_offset,
-                    t_start + t_local,
+                    self.painter_t(t_start.wrapping_add(t_local)),
                     visibility
                 );
                 *y -= 1;
+                self.rows_left -= 1;
             }
         } else {
             // Curved plane
-            let inv_near = (1 << FP_POS) / self.near; // FP1
+            let inv_near = base_tx; // See INV_NEAR_POS
             let abs_y_curve = if y_curve < 0 { -y_curve } else { y_curve };
-            let tsqrtcurve = isqrt(abs_y_curve << FP_POS); // FP1
-            while *y >= 0 {
-                let vy = (*y - h / 2) * inv_near; // FP1
+            let tsqrtcurve = Backend::isqrt(abs_y_curve << FP_POS); // FP1
+            while *y >= 0 && self.rows_left != 0 {
+                let vy = match tables {
+                    Some(tables) => tables.rows[*y as usize] + Backend::project(self.look_y, inv_near),
+                    None => Backend::project(*y - h / 2 + self.look_y, inv_near),
+                }; // FP1
                 let vym = vy - y_slope; // FP1
                 let disc = vym * vym + 4 * (((z_offset * vy) >> FP_POS) - y_offset) * y_curve; // FP2
                 if disc < 0 {
                     break;
                 }
-                let sqrt_disc = isqrt(disc << (FP_POS / 2)) << (FP_POS - FP_POS / 4); // FP2
-                let z = ((vym << FP_POS) - sqrt_disc) / (2 * y_curve); // FP1
+                let sqrt_disc = Backend::isqrt(disc << (FP_POS / 4 * 2)) << (FP_POS - FP_POS / 4); // FP2
+                let z = Backend::div((vym << FP_POS) - sqrt_disc, 2 * y_curve); // FP1
                 if z < 0 || z > max_z {
                     break;
                 }
@@ -506,53 +3029,167 @@ impl<'a> RoadRenderer<'a> {
                     painter,
                     (w, h),
                     style,
+                    road_width,
                     base_tx,
-                    x_offset,
+                    x_offset_fp2,
                     x_slope,
                     x_curve,
                     *y,
                     z + z_offset,
                     z,
-                    t_start + t_local,
+                    self.painter_t(t_start.wrapping_add(t_local)),
                     visibility
                 );
                 *y -= 1;
+                self.rows_left -= 1;
             }
         }
     }
 
-    pub fn render<P: Painter, const W: i32, const H: i32>(
+    fn stage_start(&mut self, stage: RenderStage) {
+        if let Some(events) = self.events.as_mut() {
+            events.stage_start(stage);
+        }
+    }
+
+    fn stage_end(&mut self, stage: RenderStage) {
+        if let Some(events) = self.events.as_mut() {
+            events.stage_end(stage);
+        }
+    }
+
+    pub fn render<P: Painter, const W: usize, const H: usize>(
         &mut self,
         painter: &mut P,
         initial_x_offset: i32, // FP1
         initial_y_offset: i32, // FP1
-        max_z: i32
-    ) where [LineVisibility; i32_to_usize(H)]: Sized
-    {
+        max_z: i32,
+        frame: i32
+    ) {
+        // If only VLAs were supported in Rust... If they were supported,
+        // W and H would not have to be const generics and could be dynamically
+        // determined instead. render_dyn() gets around this by taking the
+        // array from the caller.
+        const { assert!(W <= Column::MAX as usize, "the screen is too wide for i16-coords") };
+        let mut visibility = [LineVisibility{begin: 0, end: W as Column}; H];
+        self.render_lines(
+            painter,
+            (W as i32, H as i32),
+            &mut visibility,
+            initial_x_offset,
+            initial_y_offset,
+            max_z,
+            frame
+        );
+    }
+
+    // Like render(), but with the screen size decided at runtime, e.g. for a
+    // resizable window or one binary for several displays. visibility is
+    // scratch space for the frame with at least one entry per row, its
+    // contents don't matter.
+    #[allow(clippy::unnecessary_cast)] // Column is i32 without i16-coords
+    pub fn render_dyn<P: Painter>(
+        &mut self,
+        painter: &mut P,
+        (w, h): (i32, i32),
+        visibility: &mut [LineVisibility],
+        initial_x_offset: i32, // FP1
+        initial_y_offset: i32, // FP1
+        max_z: i32,
+        frame: i32
+    ) -> Result<(), Error> {
+        if w <= 0 || h <= 0 || w > Column::MAX as i32 {
+            return Err(Error::UnsupportedResolution);
+        }
+        let visibility = visibility.get_mut(..h as usize).ok_or(Error::BadDimensions)?;
+        visibility.fill(LineVisibility{begin: 0, end: w as Column});
+        self.render_lines(painter, (w, h), visibility, initial_x_offset, initial_y_offset, max_z, frame);
+        Ok(())
+    }
+
+    fn render_lines<P: Painter>(
+        &mut self,
+        painter: &mut P,
+        (w, h): (i32, i32),
+        visibility: &mut [LineVisibility],
+        initial_x_offset: i32, // FP1
+        initial_y_offset: i32, // FP1
+        max_z: i32,
+        frame: i32
+    ) {
+        if self.roll != 0 {
+            let mut rolled = Rolled { painter, w, h, roll: self.roll };
+            self.render_rows(&mut rolled, (w, h), visibility, initial_x_offset, initial_y_offset, max_z, frame);
+        } else {
+            self.render_rows(painter, (w, h), visibility, initial_x_offset, initial_y_offset, max_z, frame);
+        }
+    }
+
+    fn render_rows<P: Painter>(
+        &mut self,
+        painter: &mut P,
+        (w, h): (i32, i32),
+        visibility: &mut [LineVisibility],
+        initial_x_offset: i32, // FP1
+        initial_y_offset: i32, // FP1
+        max_z: i32,
+        frame: i32
+    ) {
+        self.frame = frame;
+        if let Some(events) = self.events.as_mut() {
+            events.frame_start(frame);
+        }
+        self.rows_left = self.max_rows;
+        #[cfg(feature = "strict")]
+        {
+            self.strict_y = h;
+        }
+        if let Some(samples) = self.road_samples.as_mut() {
+            for sample in samples.iter_mut() {
+                *sample = None;
+            }
+        }
+        if let Some(depths) = self.row_depths.as_mut() {
+            for depth in depths.iter_mut() {
+                *depth = i32::MAX;
+            }
+        }
         let mut x_offset = initial_x_offset;
         let mut y_offset = initial_y_offset;
         let mut x_slope = 0;
         let mut y_slope = 0;
         let mut z_offset = 0;
-        let mut t_start = self.cur_t;
-        let mut y_start = H - 1;
-        // If only VLAs were supported in Rust... If they were supported,
-        // W and H would not have to be const generics and could be dynamically
-        // determined instead.
-        let mut visibility = [
-            LineVisibility{begin: 0, end: W}; i32_to_usize(H)
-        ];
+        let mut t_start = self.painter_t(self.cursor.t.wrapping_add(self.t_origin));
+        self.marker_t = t_start;
+        if let Some(lines) = self.speed_lines.as_mut() {
+            lines.clear();
+        }
+        let mut y_start = h - 1;
+
+        // Taken out for the duration of the frame, so that the tables can be
+        // read while the renderer is borrowed mutably.
+        let mut projection = self.projection.take();
+        let near = self.near;
+        let tables = projection
+            .as_mut()
+            .and_then(|tables| if tables.update(near, h) { Some(&*tables) } else { None });
 
-        for render_segment in self.cur_segment..self.segments.len() {
-            let local_t = if render_segment == self.cur_segment {
-                self.cur_t - self.base_t
+        self.stage_start(RenderStage::Road);
+        for k in 0.. {
+            let Some(render_segment) = self.segment_ahead(k) else { break };
+            let local_t = if k == 0 {
+                self.cursor.t - self.cursor.base_t
             } else {
                 0
             };
-            let seg = &self.segments[render_segment];
+            if k > 0 && render_segment == 0 {
+                // Around the loop.
+                t_start = self.painter_t(self.t_origin);
+            }
+            let seg = self.segments[render_segment];
             self.render_road(
                 painter,
-                (W, H),
+                (w, h),
                 &mut y_start,
                 seg.side_style,
                 x_offset,
@@ -565,7 +3202,8 @@ impl<'a> RoadRenderer<'a> {
                 seg.length - local_t,
                 t_start,
                 max_z,
-                &mut visibility
+                tables,
+                visibility
             );
             self.update_state_at_segment_length(
                 render_segment,
@@ -576,12 +3214,459 @@ impl<'a> RoadRenderer<'a> {
                 &mut x_slope,
                 &mut y_slope,
             );
-            t_start += seg.length - local_t;
-            if z_offset > max_z {
+            t_start = self.painter_t(t_start.wrapping_add(seg.length - local_t));
+            if z_offset > max_z || self.rows_left == 0 {
                 break;
             }
         }
 
-        self.render_sky(painter, (W, H), y_start+1, &visibility);
+        self.stage_end(RenderStage::Road);
+        self.projection = projection;
+
+        self.stage_start(RenderStage::Sky);
+        self.record_horizon((w, h), y_start+1, visibility);
+        self.render_sky(painter, (w, h), y_start+1, visibility);
+        self.stage_end(RenderStage::Sky);
+        if let Some(lines) = self.speed_lines.take() {
+            self.stage_start(RenderStage::Overlay);
+            lines.draw(painter, (w, h), self.view_center((w, h)), self.speed, frame);
+            self.stage_end(RenderStage::Overlay);
+            self.speed_lines = Some(lines);
+        }
+        self.last_view = Some(self.view_key((w, h), initial_x_offset, initial_y_offset, max_z));
+        if let Some(events) = self.events.as_mut() {
+            events.frame_end(frame);
+        }
+    }
+
+    // Like render(), with the camera and draw distance set with set_camera()
+    // and set_draw_distance().
+    pub fn render_camera<P: Painter, const W: usize, const H: usize>(&mut self, painter: &mut P, frame: i32) {
+        let (x_offset, y_offset) = self.camera;
+        self.render::<P, W, H>(painter, x_offset, y_offset, self.draw_distance, frame);
+    }
+
+    // Renders the road as seen from distance camera_t along it, e.g. for
+    // replay and spectator cameras placed at the side of the track. The
+    // position set with advance() and set() is left as it was, so gameplay
+    // can continue from it.
+    pub fn render_from<P: Painter, const W: usize, const H: usize>(
+        &mut self,
+        painter: &mut P,
+        camera_t: i32,
+        initial_x_offset: i32, // FP1
+        initial_y_offset: i32, // FP1
+        max_z: i32,
+        frame: i32
+    ) {
+        // Moved without set(), since the camera isn't really moving.
+        let cursor = self.cursor;
+        let track = Track { segments: &self.segments, index: self.index };
+        self.cursor.set(&track, camera_t);
+        self.render::<P, W, H>(painter, initial_x_offset, initial_y_offset, max_z, frame);
+        self.cursor = cursor;
+    }
+
+    // Renders the given track from the given cursor, using this renderer's
+    // buffers and settings. The renderer's own track and position are kept,
+    // so one renderer can draw several views in turn, e.g. for each player
+    // or camera moving along one shared Track.
+    pub fn render_view<P: Painter, const W: usize, const H: usize>(
+        &mut self,
+        painter: &mut P,
+        track: &Track<'a>,
+        cursor: &RoadCursor,
+        initial_x_offset: i32, // FP1
+        initial_y_offset: i32, // FP1
+        max_z: i32,
+        frame: i32
+    ) {
+        let same_track = core::ptr::eq(track.segments, &*self.segments);
+        if !same_track {
+            self.prepared = None;
+        }
+        let own_segments = core::mem::replace(&mut self.segments, Segments::Slice(track.segments));
+        let own_index = core::mem::replace(&mut self.index, track.index);
+        let own_cursor = core::mem::replace(&mut self.cursor, *cursor);
+        self.render::<P, W, H>(painter, initial_x_offset, initial_y_offset, max_z, frame);
+        self.segments = own_segments;
+        self.index = own_index;
+        self.cursor = own_cursor;
+        if !same_track {
+            self.prepared = None;
+        }
+    }
+
+    fn view_key(&self, size: (i32, i32), x_offset: i32, y_offset: i32, max_z: i32) -> ViewKey {
+        ViewKey {
+            segments: self.segments.as_ptr() as usize,
+            segment_count: self.segments.len(),
+            cur_t: self.cursor.t,
+            near: self.near,
+            x_offset,
+            y_offset,
+            max_z,
+            size,
+            look: (self.look_x, self.look_y),
+            roll: self.roll,
+            fog: self.fog,
+        }
+    }
+
+    // Like render(), but skips the frame entirely if nothing affecting the
+    // view changed since the last rendered frame, e.g. on pause and menu
+    // screens. The frame counter isn't compared, so this is only useful when
+    // the painter's colors don't animate and the previous frame is still
+    // on screen. Returns true if the frame was rendered.
+    pub fn render_if_changed<P: Painter, const W: usize, const H: usize>(
+        &mut self,
+        painter: &mut P,
+        initial_x_offset: i32, // FP1
+        initial_y_offset: i32, // FP1
+        max_z: i32,
+        frame: i32
+    ) -> bool {
+        let key = self.view_key((W as i32, H as i32), initial_x_offset, initial_y_offset, max_z);
+        if self.last_view == Some(key) {
+            return false;
+        }
+        self.render::<P, W, H>(painter, initial_x_offset, initial_y_offset, max_z, frame);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Numerators like z_offset * c - y_offset * near of the flat road, from
+    // the camera height to far-away crests and dips.
+    const NUMERATORS: [i64; 10] = [
+        0, 1, -1, 255, -4096, 123_457, -9_999_991, 1 << 31, -(1 << 40) - 3, (1 << 45) + 17,
+    ];
+
+    fn check(divider: &StepDivider, n: i64, d: i64) {
+        assert_eq!(divider.quotient_minus(0), n / d, "{} / {}", n, d);
+        for offset in [0, 5, -300] {
+            assert_eq!(divider.quotient_minus(offset), (n - offset * d) / d, "{} / {} - {}", n, d, offset);
+        }
+        // floor(n / d), which is n.div_euclid(d) for the positive d of rows
+        // below the horizon.
+        assert_eq!(divider.q, (n * d.signum()).div_euclid(d.abs()), "{} / {}", n, d);
+    }
+
+    #[test]
+    fn step_divider_follows_rows() {
+        // The denominator grows by one per row from below the horizon, where
+        // it's c - vy for the topmost rows, down to the bottom of the screen.
+        for &n in &NUMERATORS {
+            for (start, end) in [(1, 600), (-600, -1), (-3, 400), (40, 41)] {
+                let mut divider = StepDivider::new(n, 1);
+                for d in (start..=end).filter(|d| *d != 0) {
+                    divider.set(d);
+                    check(&divider, n, d);
+                }
+                for d in (start..=end).rev().filter(|d| *d != 0) {
+                    divider.set(d);
+                    check(&divider, n, d);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn step_divider_jumps() {
+        // Skipped rows and the first row of a segment move the denominator
+        // by more than one, and so do the rows near the horizon where the
+        // quotient changes fast.
+        for &n in &NUMERATORS {
+            let mut divider = StepDivider::new(n, 7);
+            check(&divider, n, 7);
+            for d in [9, 2, 1, -1, -2, 1000, 999, 3, 4, -5000, -4999, 1] {
+                divider.set(d);
+                check(&divider, n, d);
+            }
+        }
+    }
+
+    const TRACK: [Segment; 3] = [
+        Segment::new((SideInclination::Flat, SideInclination::Flat), 10 << FP_POS, 0, 0),
+        Segment::new((SideInclination::Wall, SideInclination::Flat), 20 << FP_POS, 4, 0),
+        Segment::new((SideInclination::Flat, SideInclination::Uphill), 30 << FP_POS, 0, -2),
+    ];
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    enum Event {
+        Left(usize),
+        Entered(usize),
+        Ended,
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        events: [Option<Event>; 16],
+        count: usize,
+    }
+
+    impl Recorder {
+        fn push(&mut self, event: Event) {
+            self.events[self.count] = Some(event);
+            self.count += 1;
+        }
+
+        fn take(&mut self) -> [Option<Event>; 16] {
+            self.count = 0;
+            core::mem::take(&mut self.events)
+        }
+    }
+
+    impl RoadEvents for Recorder {
+        fn segment_left(&mut self, index: usize) {
+            self.push(Event::Left(index));
+        }
+        fn segment_entered(&mut self, index: usize) {
+            self.push(Event::Entered(index));
+        }
+        fn track_ended(&mut self) {
+            self.push(Event::Ended);
+        }
+    }
+
+    fn events<const N: usize>(list: [Event; N]) -> [Option<Event>; 16] {
+        let mut events = [None; 16];
+        for (slot, event) in events.iter_mut().zip(list) {
+            *slot = Some(event);
+        }
+        events
+    }
+
+    // Records a frame of numbered colors: sky by row, road, ground and walls,
+    // and the fog color when enabled.
+    struct Frame {
+        pixels: [[u16; 24]; 16],
+        fog: Option<u16>,
+    }
+
+    impl Frame {
+        fn new(fog: Option<u16>) -> Self {
+            Frame { pixels: [[u16::MAX; 24]; 16], fog }
+        }
+
+        fn render(&mut self, road: &mut RoadRenderer) {
+            self.pixels = [[u16::MAX; 24]; 16];
+            road.render::<_, 24, 16>(self, 0, 1 << FP_POS, 60 << FP_POS, 0);
+        }
+    }
+
+    impl Painter for Frame {
+        type ColorType = u16;
+
+        fn draw(&mut self, x: i32, y: i32, color: &u16) {
+            self.pixels[y as usize][x as usize] = *color;
+        }
+
+        fn sky_color(&self, y: i32, _frame: i32) -> u16 {
+            1000 + y as u16
+        }
+
+        fn road_color(&self, _tx: i32, _t: i32, _frame: i32) -> u16 {
+            200
+        }
+
+        fn ground_color(&self, _tx: i32, _t: i32, _frame: i32) -> u16 {
+            300
+        }
+
+        fn wall_color(&self, _t: i32, _height: i32, _frame: i32) -> u16 {
+            400
+        }
+
+        fn road_width(&self) -> i32 {
+            2 << (2 * FP_POS)
+        }
+
+        fn blend_color(&self, a: &u16, b: &u16, amount: i32) -> Option<u16> {
+            Some((*a as i32 + (((*b as i32 - *a as i32) * amount) >> FP_POS)) as u16)
+        }
+
+        fn fog_color(&self, _frame: i32) -> Option<u16> {
+            self.fog
+        }
+    }
+
+    #[test]
+    fn roll_moves_columns() {
+        let mut road = RoadRenderer::new(&TRACK, 32);
+        road.set(5 << FP_POS);
+        let mut level = Frame::new(None);
+        level.render(&mut road);
+        assert!(level.pixels.iter().flatten().all(|&c| c != u16::MAX));
+        for roll in [1 << (FP_POS - 2), -(1 << FP_POS), 1 << FP_POS] {
+            road.set_roll(roll);
+            let mut rolled = Frame::new(None);
+            rolled.render(&mut road);
+            for x in 0..24 {
+                let shift = road.roll_shift(24, x);
+                for y in 0..16 {
+                    let source = (y - shift).clamp(0, 15);
+                    assert_eq!(
+                        rolled.pixels[y as usize][x as usize],
+                        level.pixels[source as usize][x as usize],
+                        "roll {} at {}, {}", roll, x, y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fog_grows_with_depth() {
+        let mut road = RoadRenderer::new(&TRACK, 32);
+        let mut clear = Frame::new(Some(0));
+        clear.render(&mut road);
+        road.set_fog(Some((6 << FP_POS, 40 << FP_POS)));
+        let mut foggy = Frame::new(None);
+        foggy.render(&mut road);
+        // Painters without a fog color aren't fogged.
+        assert_eq!(foggy.pixels, clear.pixels);
+        foggy.fog = Some(0);
+        foggy.render(&mut road);
+        // The road of the center column, from the bottom of the screen
+        // towards the horizon, fades from its own color to the fog.
+        let road_rows = (0..16).rev().map(|y| foggy.pixels[y][12]).take_while(|&c| c <= 200);
+        let mut previous = 200;
+        for color in road_rows {
+            assert!(color <= previous);
+            previous = color;
+        }
+        assert_eq!(foggy.pixels[15][12], 200);
+        assert!(previous < 200);
+        // Sky isn't fogged.
+        let pixels = clear.pixels.iter().flatten().zip(foggy.pixels.iter().flatten());
+        assert!(pixels.filter(|(&c, _)| c >= 1000).all(|(c, f)| c == f));
+    }
+
+    #[test]
+    fn origin_changes_the_view() {
+        use crate::ascii::{AsciiSink, AsciiStyle};
+        let style = AsciiStyle::default();
+        let mut buffer = [0; 16 * 8];
+        let mut sink = AsciiSink::new(&mut buffer, 16, 8, |c: &u8| *c).unwrap();
+        let mut painter = StyledPainter::new(&mut sink, &style);
+        let mut road = RoadRenderer::new(&TRACK, 32);
+        let mut render = |road: &mut RoadRenderer| {
+            road.render_if_changed::<_, 16, 8>(&mut painter, 0, 1 << FP_POS, 100 << FP_POS, 0)
+        };
+        assert!(render(&mut road));
+        assert!(!render(&mut road));
+        road.set_t_origin(0);
+        assert!(!render(&mut road));
+        // The painter's t values move with the origin.
+        road.set_t_origin(3 << FP_POS);
+        assert!(render(&mut road));
+        assert!(!render(&mut road));
+    }
+
+    #[test]
+    fn lookahead_signs() {
+        let track = crate::track! {
+            straight 10;
+            curve left 4 for 10, crest 2;
+            curve right 3 for 10, dip 1;
+        };
+        let mut road = RoadRenderer::new(&track, 32);
+        assert_eq!(road.lookahead(20 << FP_POS), (40, 20));
+        road.set(10 << FP_POS);
+        assert_eq!(road.lookahead(20 << FP_POS), (10, 10));
+        road.set(20 << FP_POS);
+        assert_eq!(road.lookahead(100 << FP_POS), (-30, -10));
+    }
+
+    #[test]
+    fn cursor_walks_backwards() {
+        let mut buffer = [0; 4];
+        let index = SegmentIndex::new(&TRACK, &mut buffer).unwrap();
+        let indexed = Track { segments: &TRACK, index: Some(&index) };
+        let unit = 1 << FP_POS;
+        for step in [1, unit, 7 * unit, 25 * unit, 70 * unit] {
+            let mut cursor = RoadCursor::new();
+            cursor.set(&Track::new(&TRACK), 65 * unit);
+            while cursor.t() > -40 * unit {
+                cursor.advance(&Track::new(&TRACK), -step);
+                // Walking gives the same segment as looking t up.
+                let mut found = RoadCursor::new();
+                found.set(&indexed, cursor.t());
+                assert_eq!(cursor, found, "step {}", step);
+                let (segment, start) = match cursor.t() / unit {
+                    t if t >= 60 => (3, 60),
+                    t if t >= 30 => (2, 30),
+                    t if t >= 10 => (1, 10),
+                    _ => (0, 0),
+                };
+                assert_eq!(cursor.segment(), segment, "t {}", cursor.t());
+                assert_eq!(cursor.local_t(), cursor.t() - (start << FP_POS));
+            }
+        }
+    }
+
+    #[test]
+    fn reversing_reports_segments() {
+        let mut recorder = Recorder::default();
+        let mut road = RoadRenderer::new(&TRACK, 32);
+        road.set(65 << FP_POS);
+        road.set_event_handler(Some(&mut recorder));
+        let unit = 1 << FP_POS;
+
+        let step = road.advance(-10 * unit);
+        assert_eq!(step, SegmentMove { from: 3, to: 2, laps: 0 });
+        let step = road.advance(-50 * unit);
+        assert_eq!(step, SegmentMove { from: 2, to: 0, laps: 0 });
+        assert_eq!(road.local_t(), 5 * unit);
+        // Past the start, the camera stays on the first segment.
+        let step = road.advance(-20 * unit);
+        assert_eq!(step, SegmentMove { from: 0, to: 0, laps: 0 });
+        assert!(!step.crossed());
+        assert_eq!(road.position(), -15 * unit);
+        assert_eq!(road.local_t(), -15 * unit);
+        assert_eq!(road.remaining_in_segment(), 25 * unit);
+        let step = road.advance(30 * unit);
+        assert_eq!(step, SegmentMove { from: 0, to: 1, laps: 0 });
+
+        assert_eq!(recorder.take(), events([
+            Event::Entered(2),
+            Event::Left(2),
+            Event::Entered(1),
+            Event::Left(1),
+            Event::Entered(0),
+            Event::Left(0),
+            Event::Entered(1),
+        ]));
+    }
+
+    #[test]
+    fn reversing_around_a_loop() {
+        let mut recorder = Recorder::default();
+        let mut road = RoadRenderer::new(&TRACK, 32);
+        road.set_looping(true);
+        road.set(5 << FP_POS);
+        road.set_event_handler(Some(&mut recorder));
+        let unit = 1 << FP_POS;
+
+        let step = road.advance(-10 * unit);
+        assert_eq!(step, SegmentMove { from: 0, to: 2, laps: -1 });
+        assert!(step.crossed());
+        assert_eq!(road.position(), 55 * unit);
+        assert_eq!(road.local_t(), 25 * unit);
+        // More than a lap back at once.
+        let step = road.advance(-130 * unit);
+        assert_eq!(step.laps, -2);
+        assert_eq!(road.position(), 45 * unit);
+        assert_eq!(road.current_segment(), 2);
+        let step = road.advance(20 * unit);
+        assert_eq!(step, SegmentMove { from: 2, to: 0, laps: 1 });
+        assert_eq!(road.position(), 5 * unit);
+
+        let recorded = recorder.take();
+        assert_eq!(recorded[..2], [Some(Event::Left(0)), Some(Event::Entered(2))]);
     }
 }
@@ -0,0 +1,560 @@
+// Built-in road markings. A marking is placed on the road as a Decal at a
+// distance t and lateral position tx, and Painter::road_color() asks
+// decal_at() whether a point of the road is painted:
+//
+//   fn road_color(&self, tx: i32, t: i32, _frame: i32) -> Color {
+//       if markings::decal_at(&self.decals, tx, t).is_some() {
+//           MARKING_COLOR
+//       } else {
+//           ROAD_COLOR
+//       }
+//   }
+
+use crate::{noise, FP_POS};
+
+// A 1-bit image of a marking. Rows are listed as seen from the driver, the
+// farthest one first, and the highest of the width bits is the leftmost
+// column.
+pub struct Pattern {
+    pub width: u32,
+    pub rows: &'static [u32],
+}
+
+pub const STRAIGHT_ARROW: Pattern = Pattern {
+    width: 7,
+    rows: &[
+        0b0001000,
+        0b0011100,
+        0b0111110,
+        0b1111111,
+        0b0011100,
+        0b0011100,
+        0b0011100,
+        0b0011100,
+        0b0011100,
+        0b0011100,
+    ],
+};
+
+// Left turn arrows are this mirrored.
+pub const RIGHT_ARROW: Pattern = Pattern {
+    width: 8,
+    rows: &[
+        0b00000100,
+        0b00000110,
+        0b11111111,
+        0b11111111,
+        0b11000110,
+        0b11000100,
+        0b11000000,
+        0b11000000,
+        0b11000000,
+        0b11000000,
+    ],
+};
+
+pub const CROSSWALK: Pattern = Pattern {
+    width: 9,
+    rows: &[0b101010101],
+};
+
+pub const STOP: Pattern = Pattern {
+    width: 19,
+    rows: &[
+        0b1111011110111101111,
+        0b1000001100100101001,
+        0b1111001100100101111,
+        0b0001001100100101000,
+        0b1111001100111101000,
+    ],
+};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Marking {
+    StraightArrow,
+    LeftArrow,
+    RightArrow,
+    Crosswalk,
+    Stop,
+    // Checkerboard of the given size, see finish_line().
+    Checkered { columns: u16, rows: u16 },
+}
+
+impl Marking {
+    // Returns the pattern and whether it's mirrored. Checkered markings are
+    // generated and have no pattern.
+    pub fn pattern(&self) -> Option<(&'static Pattern, bool)> {
+        match self {
+            Marking::StraightArrow => Some((&STRAIGHT_ARROW, false)),
+            Marking::LeftArrow => Some((&RIGHT_ARROW, true)),
+            Marking::RightArrow => Some((&RIGHT_ARROW, false)),
+            Marking::Crosswalk => Some((&CROSSWALK, false)),
+            Marking::Stop => Some((&STOP, false)),
+            Marking::Checkered { .. } => None,
+        }
+    }
+
+    // Returns the number of columns and rows of the marking.
+    pub fn size(&self) -> (u32, u32) {
+        match (self, self.pattern()) {
+            (_, Some((pattern, _))) => (pattern.width, pattern.rows.len() as u32),
+            (Marking::Checkered { columns, rows }, None) => (*columns as u32, *rows as u32),
+            _ => (0, 0),
+        }
+    }
+
+    // Returns true if the cell at column (from the left) and row (from the
+    // nearest one) is painted.
+    pub fn cell(&self, column: u32, row: u32) -> bool {
+        match (self, self.pattern()) {
+            (_, Some((pattern, mirrored))) => {
+                let bits = pattern.rows[pattern.rows.len() - 1 - row as usize];
+                let bit = if mirrored { column } else { pattern.width - 1 - column };
+                (bits >> bit) & 1 != 0
+            }
+            _ => (column + row) & 1 == 0,
+        }
+    }
+}
+
+// A marking placed on the road. The pattern starts at t and extends away from
+// the camera, and it's centered on tx. Each bit of the pattern covers
+// cell_width x cell_length of the road; markings are usually stretched along
+// the road so that they read well in perspective.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Decal {
+    pub t: i32,           // Distance from the start of the road
+    pub tx: i32,          // FP2
+    pub cell_width: i32,  // FP2
+    pub cell_length: i32, // FP1
+    pub marking: Marking,
+}
+
+impl Decal {
+    pub fn new(marking: Marking, t: i32, tx: i32, cell_width: i32, cell_length: i32) -> Self {
+        Decal {
+            t,
+            tx,
+            cell_width,
+            cell_length,
+            marking,
+        }
+    }
+
+    // Length of the decal along the road, FP1.
+    pub fn length(&self) -> i32 {
+        self.marking.size().1 as i32 * self.cell_length
+    }
+
+    // Returns true if the decal paints the road at (tx, t).
+    pub fn covers(&self, tx: i32, t: i32) -> bool {
+        let (columns, rows) = self.marking.size();
+        // In i64, so that decals and points far apart don't wrap around.
+        let width = columns as i64 * self.cell_width as i64;
+        let x = tx as i64 - (self.tx as i64 - width / 2);
+        let z = t as i64 - self.t as i64;
+        if x < 0 || x >= width || z < 0 || z >= rows as i64 * self.cell_length as i64 {
+            return false;
+        }
+        self.marking
+            .cell((x / self.cell_width as i64) as u32, (z / self.cell_length as i64) as u32)
+    }
+}
+
+// Returns a checkered start/finish line across the whole road. line_t is the
+// distance used for lap timing; the checkers are centered on it, so the
+// visible line can't drift from the timing line. road_width is the half width
+// from Painter::road_width() and square_width is FP2, square_length is FP1.
+// Returns None if the squares don't have a positive size. Roads wider than
+// u16::MAX squares are covered up to that.
+pub fn finish_line(line_t: i32, road_width: i32, square_width: i32, square_length: i32, rows: u16) -> Option<Decal> {
+    if square_width <= 0 || square_length <= 0 {
+        return None;
+    }
+    let columns = (2 * road_width as i64 + square_width as i64 - 1) / square_width as i64;
+    let columns = columns.clamp(0, u16::MAX as i64) as u16;
+    let marking = Marking::Checkered { columns, rows };
+    let length = rows as i64 * square_length as i64;
+    let t = (line_t as i64 - length / 2).max(i32::MIN as i64) as i32;
+    Some(Decal::new(marking, t, 0, square_width, square_length))
+}
+
+// Returns the marking painted at (tx, t), if any. decals must be sorted by t.
+pub fn decal_at(decals: &[Decal], tx: i32, t: i32) -> Option<Marking> {
+    for decal in decals {
+        if decal.t > t {
+            break;
+        }
+        if decal.covers(tx, t) {
+            return Some(decal.marking);
+        }
+    }
+    None
+}
+
+// Position within a dash period at distance t, in [0, period). Both are FP1.
+// Unlike masking t, works for any period and for negative t. A period of
+// zero has no phase and returns 0, so is_dash() paints a solid line.
+pub fn dash_phase(t: i32, period: i32) -> i32 {
+    if period == 0 {
+        return 0;
+    }
+    t.rem_euclid(period)
+}
+
+// Returns true if a dashed line is painted at distance t, when each period
+// starts with a dash of dash_length. Both are FP1. The phase follows the road,
+// so dashes move toward the camera at the same speed as the road.
+pub fn is_dash(t: i32, period: i32, dash_length: i32) -> bool {
+    dash_phase(t, period) < dash_length
+}
+
+// Reflective studs ("cat's eyes") along lane lines, drawn by the renderer
+// over the road as 1-2 pixel highlights with Painter::marker_color(), see
+// RoadRenderer::set_cats_eyes(). Studs never shrink below a pixel, so they
+// stay visible far away where a painted marking would disappear.
+#[derive(Copy, Clone, Debug)]
+pub struct CatsEyes<'a> {
+    pub lanes: &'a [i32], // Lateral positions of the studs, FP2
+    pub spacing: i32,     // Distance between studs along the road, FP1
+    pub length: i32,      // Length of a stud, FP1
+    pub width: i32,       // Width of a stud, FP2
+    pub fade_z: i32,      // Depth where studs have faded out, FP1
+}
+
+impl<'a> CatsEyes<'a> {
+    // Returns true if a stud starts or lies in the span of road after t0 up
+    // to t1, i.e. between the distances of two adjacent rows.
+    pub fn in_span(&self, t0: i32, t1: i32) -> bool {
+        dash_phase(t1, self.spacing) - self.length < t1.wrapping_sub(t0)
+    }
+
+    // Brightness of the studs at depth z, FP1 from 1 << FP_POS at the camera
+    // to zero at fade_z.
+    pub fn brightness(&self, z: i32) -> i32 {
+        if z >= self.fade_z {
+            0
+        } else {
+            ((((self.fade_z - z) as i64) << FP_POS) / self.fade_z as i64) as i32
+        }
+    }
+}
+
+// A skid mark left by one wheel during one frame, from (t0, tx0) to (t1, tx1).
+#[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SkidMark {
+    pub t0: i32,    // FP1
+    pub tx0: i32,   // FP2
+    pub t1: i32,    // FP1
+    pub tx1: i32,   // FP2
+    pub frame: i32, // Frame the mark was recorded on
+}
+
+impl SkidMark {
+    // Returns true if the mark, width wide (FP2), covers (tx, t).
+    pub fn covers(&self, tx: i32, t: i32, width: i32) -> bool {
+        let (t0, tx0, t1, tx1) = if self.t0 <= self.t1 {
+            (self.t0, self.tx0, self.t1, self.tx1)
+        } else {
+            (self.t1, self.tx1, self.t0, self.tx0)
+        };
+        if t < t0 || t > t1 {
+            return false;
+        }
+        let center = if t1 == t0 {
+            tx0
+        } else {
+            tx0 + ((tx1 - tx0) as i64 * (t - t0) as i64 / (t1 - t0) as i64) as i32
+        };
+        (tx - center).abs() < width / 2
+    }
+}
+
+// Skid marks kept in a ring buffer given by the caller. When the buffer is
+// full, recording a mark replaces the oldest one. Marks fade out and expire
+// lifetime frames after they were recorded.
+pub struct SkidMarks<'a> {
+    marks: &'a mut [SkidMark],
+    oldest: usize,
+    len: usize,
+    width: i32,    // FP2
+    lifetime: i32, // Frames
+}
+
+impl<'a> SkidMarks<'a> {
+    pub fn new(buffer: &'a mut [SkidMark], width: i32, lifetime: i32) -> Self {
+        SkidMarks {
+            marks: buffer,
+            oldest: 0,
+            len: 0,
+            width,
+            lifetime,
+        }
+    }
+
+    // Records a mark, call on every frame a wheel spins or drifts with its
+    // positions on the previous and current frame.
+    pub fn record(&mut self, t0: i32, tx0: i32, t1: i32, tx1: i32, frame: i32) {
+        if self.marks.is_empty() {
+            return;
+        }
+        let mark = SkidMark { t0, tx0, t1, tx1, frame };
+        if self.len < self.marks.len() {
+            let index = (self.oldest + self.len) % self.marks.len();
+            self.marks[index] = mark;
+            self.len += 1;
+        } else {
+            self.marks[self.oldest] = mark;
+            self.oldest = (self.oldest + 1) % self.marks.len();
+        }
+    }
+
+    // Drops marks that have expired by the given frame.
+    pub fn expire(&mut self, frame: i32) {
+        while self.len > 0 && frame - self.marks[self.oldest].frame >= self.lifetime {
+            self.oldest = (self.oldest + 1) % self.marks.len();
+            self.len -= 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Iterates over the live marks, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &SkidMark> {
+        (0..self.len).map(move |i| &self.marks[(self.oldest + i) % self.marks.len()])
+    }
+
+    // Returns the opacity of skid marks at (tx, t) on the given frame, FP1
+    // from 0 (none) to 1 << FP_POS (fresh mark) like the amount of
+    // Painter::blend_color(). Painters darken the road color by it in
+    // road_color().
+    pub fn opacity_at(&self, tx: i32, t: i32, frame: i32) -> i32 {
+        let mut opacity = 0;
+        for mark in self.iter() {
+            if mark.covers(tx, t, self.width) {
+                let age = (frame - mark.frame).clamp(0, self.lifetime);
+                opacity = opacity.max((1 << FP_POS) - (age << FP_POS) / self.lifetime.max(1));
+            }
+        }
+        opacity
+    }
+}
+
+// Road surface under a point, see surface_at().
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Surface {
+    Asphalt,
+    Ice,
+    Oil,
+    Puddle,
+}
+
+// An elliptical patch of slippery surface. The same list is given to
+// surface_at() by the game's physics and by the painter's road_color(), so
+// what the player sees is what the car drives on.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hazard {
+    pub t: i32,      // Start of the patch, FP1
+    pub length: i32, // FP1
+    pub tx: i32,     // Center of the patch, FP2
+    pub width: i32,  // FP2
+    pub surface: Surface,
+}
+
+impl Hazard {
+    pub fn new(surface: Surface, t: i32, length: i32, tx: i32, width: i32) -> Self {
+        Hazard {
+            t,
+            length,
+            tx,
+            width,
+            surface,
+        }
+    }
+
+    // Returns true if the patch covers (tx, t).
+    pub fn covers(&self, tx: i32, t: i32) -> bool {
+        if self.length <= 0 || self.width <= 0 {
+            return false;
+        }
+        // Widened before subtracting, and points outside the bounding box
+        // are out before squaring, so that far-off points can't overflow.
+        let (dx, dt) = (tx as i64 - self.tx as i64, t as i64 - self.t as i64);
+        if dx.abs() * 2 >= self.width as i64 || dt < 0 || dt >= self.length as i64 {
+            return false;
+        }
+        // Distances from the center relative to the radii, FP1
+        let x = ((dx * 2) << FP_POS) / self.width as i64;
+        let z = ((dt * 2) << FP_POS) / self.length as i64 - (1 << FP_POS);
+        x * x + z * z < 1 << (2 * FP_POS)
+    }
+}
+
+// Returns the surface at (tx, t). hazards must be sorted by t.
+pub fn surface_at(hazards: &[Hazard], tx: i32, t: i32) -> Surface {
+    for hazard in hazards {
+        if hazard.t > t {
+            break;
+        }
+        if hazard.covers(tx, t) {
+            return hazard.surface;
+        }
+    }
+    Surface::Asphalt
+}
+
+// What a WindowGrid has at a point of a wall.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WallPart {
+    Wall,
+    DarkWindow,
+    LitWindow,
+}
+
+// Rows of windows on SideInclination::Wall sides, e.g. office blocks lining
+// a night-time city street. Painter::wall_color() passes on its t and height:
+//
+//   fn wall_color(&self, t: i32, height: i32, _frame: i32) -> Color {
+//       match self.windows.part_at(t, height) {
+//           WallPart::LitWindow => LIT_COLOR,
+//           WallPart::DarkWindow => GLASS_COLOR,
+//           WallPart::Wall => WALL_COLOR,
+//       }
+//   }
+//
+// The wall is divided into cells of spacing along the road and floor_height
+// up, each with a window in its lower left corner. Whether a window is lit
+// is decided by its cell and the seed, so it stays the same between frames.
+#[derive(Copy, Clone, Debug)]
+pub struct WindowGrid {
+    pub spacing: i32,       // Distance between windows along the road, FP1
+    pub floor_height: i32,  // FP1
+    pub window_width: i32,  // FP1
+    pub window_height: i32, // FP1
+    pub ground_floor: i32,  // Height below which there are no windows, FP1
+    pub lit: i32,           // Share of lit windows, 0 to 1 << FP_POS
+    pub seed: u32,
+}
+
+impl WindowGrid {
+    pub fn part_at(&self, t: i32, height: i32) -> WallPart {
+        let height = height - self.ground_floor;
+        if height < 0 || self.spacing <= 0 || self.floor_height <= 0 {
+            return WallPart::Wall;
+        }
+        let (column, floor) = (t.div_euclid(self.spacing), height / self.floor_height);
+        let in_window = t.rem_euclid(self.spacing) < self.window_width
+            && height % self.floor_height < self.window_height;
+        if !in_window {
+            WallPart::Wall
+        } else if noise::threshold(column, floor, self.seed) < self.lit {
+            WallPart::LitWindow
+        } else {
+            WallPart::DarkWindow
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID: WindowGrid = WindowGrid {
+        spacing: 4 << FP_POS,
+        floor_height: 3 << FP_POS,
+        window_width: 2 << FP_POS,
+        window_height: 2 << FP_POS,
+        ground_floor: 1 << FP_POS,
+        lit: 1 << (FP_POS - 1),
+        seed: 7,
+    };
+
+    #[test]
+    fn windows_are_placed_in_cells() {
+        let unit = 1 << FP_POS;
+        assert_eq!(GRID.part_at(unit, 0), WallPart::Wall);
+        assert_eq!(GRID.part_at(3 * unit, 2 * unit), WallPart::Wall);
+        assert_eq!(GRID.part_at(unit, 7 * unit / 2), WallPart::Wall);
+        assert_ne!(GRID.part_at(unit, 2 * unit), WallPart::Wall);
+        assert_ne!(GRID.part_at(-3 * unit, 5 * unit), WallPart::Wall);
+        // The whole window has the same state.
+        assert_eq!(GRID.part_at(0, unit), GRID.part_at(2 * unit - 1, 3 * unit - 1));
+    }
+
+    #[test]
+    fn lit_share_follows_lit() {
+        let unit = 1 << FP_POS;
+        let lit = (0..256)
+            .filter(|i| GRID.part_at(i * GRID.spacing, 2 * unit) == WallPart::LitWindow)
+            .count();
+        assert!((96..160).contains(&lit), "{} of 256 lit", lit);
+        let dark = WindowGrid { lit: 0, ..GRID };
+        assert_eq!(dark.part_at(0, 2 * unit), WallPart::DarkWindow);
+    }
+
+    #[test]
+    fn dash_phase_handles_any_period() {
+        assert_eq!(dash_phase(-1, 16), 15);
+        assert_eq!(dash_phase(35, -16), 3);
+        assert_eq!(dash_phase(12345, 0), 0);
+        assert!(is_dash(12345, 0, 1));
+        assert!(!is_dash(12345, 0, 0));
+    }
+
+    #[test]
+    fn finish_line_covers_the_road() {
+        let unit = 1 << FP_POS;
+        let line = finish_line(100 * unit, 5 << (2 * FP_POS), 2 << (2 * FP_POS), unit, 2).unwrap();
+        assert_eq!(line.marking, Marking::Checkered { columns: 5, rows: 2 });
+        assert_eq!(line.t, 99 * unit);
+    }
+
+    #[test]
+    fn finish_line_rejects_empty_squares() {
+        assert!(finish_line(0, 5 << (2 * FP_POS), 0, 1 << FP_POS, 2).is_none());
+        assert!(finish_line(0, 5 << (2 * FP_POS), 1, -1, 2).is_none());
+    }
+
+    #[test]
+    fn finish_line_saturates_columns() {
+        let line = finish_line(i32::MIN, i32::MAX, 1, i32::MAX, u16::MAX).unwrap();
+        assert_eq!(line.marking, Marking::Checkered { columns: u16::MAX, rows: u16::MAX });
+        assert_eq!(line.t, i32::MIN);
+    }
+
+    #[test]
+    fn covers_handles_distant_points() {
+        let unit = 1 << FP_POS;
+        let hazard = Hazard::new(Surface::Oil, i32::MAX - 4 * unit, 2 * unit, i32::MAX - unit, unit);
+        assert!(!hazard.covers(i32::MIN, i32::MIN));
+        assert!(hazard.covers(i32::MAX - unit, i32::MAX - 3 * unit));
+        let decal = Decal::new(Marking::Stop, i32::MIN, i32::MIN, 1 << (2 * FP_POS), unit);
+        assert!(!decal.covers(i32::MAX, i32::MAX));
+        assert!(!decal.covers(i32::MAX, i32::MIN));
+    }
+
+    #[test]
+    fn skid_marks_fade() {
+        let unit = 1 << FP_POS;
+        let mut buffer = [SkidMark::default(); 2];
+        let mut marks = SkidMarks::new(&mut buffer, 2 << (2 * FP_POS), 10);
+        marks.record(0, 0, 4 * unit, 0, 5);
+        assert_eq!(marks.opacity_at(0, 2 * unit, 5), unit);
+        assert_eq!(marks.opacity_at(0, 2 * unit, 10), unit / 2);
+        assert_eq!(marks.opacity_at(0, 2 * unit, 15), 0);
+        assert_eq!(marks.opacity_at(2 << (2 * FP_POS), 2 * unit, 5), 0);
+        marks.expire(15);
+        assert!(marks.is_empty());
+    }
+}
@@ -0,0 +1,239 @@
+// Arithmetic the renderer is built on. Square roots, divisions, fixed-point
+// products and the projection of screen rows go through the Math trait, and
+// features pick the implementation as a unit: FixedMath only uses integer
+// instructions, for chips without an FPU, while the fpu feature switches to
+// FloatMath, which is faster where floats are done in hardware, e.g. on
+// Cortex-M4F and desktop. FloatMath corrects its results to the exact
+// integer ones, so frames don't change with the feature. The narrow-math
+// feature avoids 64-bit products in FixedMath for 8- and 16-bit chips, with
+// the same results too.
+use crate::INV_NEAR_SHIFT;
+
+pub(crate) trait Math {
+    // floor(sqrt(num)), 0 for negative numbers.
+    fn isqrt(num: i32) -> i32;
+    // n / d rounded toward zero, like the / operator. d must not be zero.
+    fn div(n: i32, d: i32) -> i32;
+    // Fixed-point multiply, (a * b) >> shift.
+    fn mul_shr(a: i32, b: i32, shift: i32) -> i32;
+    // Projects a screen row's distance from the horizon onto the view plane,
+    // given 1 / near at INV_NEAR_POS. FP1.
+    fn project(v: i32, inv_near: i32) -> i32;
+}
+
+#[cfg(not(feature = "fpu"))]
+pub(crate) type Backend = FixedMath;
+#[cfg(feature = "fpu")]
+pub(crate) type Backend = FloatMath;
+
+#[cfg(any(not(feature = "fpu"), test))]
+pub(crate) struct FixedMath;
+
+#[cfg(any(not(feature = "fpu"), test))]
+impl Math for FixedMath {
+    // http://www.azillionmonkeys.com/qed/ulerysqroot.pdf
+    fn isqrt(num: i32) -> i32 {
+        let mut v = num;
+        let mut n = 0;
+        let mut b = 0x8000;
+        let mut bshft = 15;
+
+        loop {
+            let tmp = ((n << 1) + b) << bshft;
+            bshft -= 1;
+            if v >= tmp {
+                n += b;
+                v -= tmp;
+            }
+            b >>= 1;
+            if b == 0 {
+                break;
+            }
+        }
+        n
+    }
+
+    #[inline(always)]
+    fn div(n: i32, d: i32) -> i32 {
+        n / d
+    }
+
+    // This compiles to a single widening multiply (e.g. smull on ARM), so
+    // it's as cheap as shifting the factors first but doesn't lose their low
+    // bits.
+    #[cfg(not(feature = "narrow-math"))]
+    #[inline(always)]
+    fn mul_shr(a: i32, b: i32, shift: i32) -> i32 {
+        ((a as i64 * b as i64) >> shift) as i32
+    }
+
+    // 8- and 16-bit chips have no widening multiply, and a 64-bit product is
+    // a slow library call there. This builds the product from 16x16 -> 32
+    // bit multiplies of the halves instead, which those chips have helpers
+    // for, and gives the same result as the 64-bit version.
+    #[cfg(feature = "narrow-math")]
+    fn mul_shr(a: i32, b: i32, shift: i32) -> i32 {
+        let mul16 = |a: u32, b: u32| (a as u16 as u32) * (b as u16 as u32);
+        let (ua, ub) = (a.unsigned_abs(), b.unsigned_abs());
+        let (mut hi, mut lo) = (mul16(ua >> 16, ub >> 16), mul16(ua, ub));
+        for mid in [mul16(ua >> 16, ub), mul16(ua, ub >> 16)] {
+            let sum = lo.wrapping_add(mid << 16);
+            hi += (mid >> 16) + (sum < lo) as u32;
+            lo = sum;
+        }
+        let (q, rounded) = if shift == 0 {
+            (lo, false)
+        } else {
+            ((lo >> shift) | (hi << (32 - shift)), lo & ((1 << shift) - 1) != 0)
+        };
+        // Rounds toward negative infinity, like >> on the 64-bit product.
+        match ((a < 0) != (b < 0), rounded) {
+            (false, _) => q as i32,
+            (true, false) => (q as i32).wrapping_neg(),
+            (true, true) => (q as i32).wrapping_neg() - 1,
+        }
+    }
+
+    #[inline(always)]
+    fn project(v: i32, inv_near: i32) -> i32 {
+        (v * inv_near) >> INV_NEAR_SHIFT
+    }
+}
+
+// The backends are built for the tests regardless of features, to compare
+// them.
+#[cfg(any(feature = "fpu", test))]
+pub(crate) struct FloatMath;
+
+#[cfg(any(feature = "fpu", test))]
+impl Math for FloatMath {
+    // core has no f32::sqrt(), so this starts from an estimate made by
+    // halving the exponent and refines it with Newton's method. The last
+    // step rounds to the exact integer square root.
+    fn isqrt(num: i32) -> i32 {
+        if num <= 0 {
+            return 0;
+        }
+        let x = num as f32;
+        let mut r = f32::from_bits((x.to_bits() >> 1) + 0x1FBD_1DF5);
+        r = 0.5 * (r + x / r);
+        r = 0.5 * (r + x / r);
+        let num = num as i64;
+        let mut n = r as i64;
+        while n * n > num {
+            n -= 1;
+        }
+        while (n + 1) * (n + 1) <= num {
+            n += 1;
+        }
+        n as i32
+    }
+
+    // f32 has 24 bits of mantissa, so the quotient of large numbers can be
+    // off by a few and is corrected like isqrt().
+    fn div(n: i32, d: i32) -> i32 {
+        let (an, ad) = ((n as i64).abs(), (d as i64).abs());
+        let mut q = (n as f32 / d as f32) as i64;
+        q = q.abs();
+        while q * ad > an {
+            q -= 1;
+        }
+        while (q + 1) * ad <= an {
+            q += 1;
+        }
+        if (n < 0) != (d < 0) {
+            -q as i32
+        } else {
+            q as i32
+        }
+    }
+
+    // Targets with an FPU have a widening multiply too.
+    #[inline(always)]
+    fn mul_shr(a: i32, b: i32, shift: i32) -> i32 {
+        ((a as i64 * b as i64) >> shift) as i32
+    }
+
+    // A float here would need to be rounded back to the integer result, and
+    // the product is a single instruction already.
+    #[inline(always)]
+    fn project(v: i32, inv_near: i32) -> i32 {
+        (v * inv_near) >> INV_NEAR_SHIFT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_isqrt<M: Math>(num: i32) {
+        let n = M::isqrt(num) as i64;
+        let num = num as i64;
+        assert!(n * n <= num && (n + 1) * (n + 1) > num, "isqrt({}) = {}", num, n);
+    }
+
+    // Every 9973th number, plus the ends of the range and the squares next
+    // to them.
+    fn samples() -> impl Iterator<Item = i32> {
+        let squares = (0..=46340).flat_map(|n: i32| [n * n - 1, n * n, n * n + 1]);
+        (0..=i32::MAX / 9973)
+            .map(|i| i * 9973)
+            .chain([i32::MAX - 1, i32::MAX])
+            .chain(squares.filter(|n| *n >= 0))
+    }
+
+    #[test]
+    fn isqrt_is_exact() {
+        for num in samples() {
+            check_isqrt::<FixedMath>(num);
+            check_isqrt::<FloatMath>(num);
+        }
+        assert_eq!(FloatMath::isqrt(-5), FixedMath::isqrt(-5));
+    }
+
+    #[test]
+    #[ignore] // About a minute in release builds.
+    fn isqrt_is_exact_for_every_i32() {
+        for num in 0..=i32::MAX {
+            assert_eq!(FloatMath::isqrt(num), FixedMath::isqrt(num), "isqrt({})", num);
+        }
+    }
+
+    #[test]
+    fn mul_shr_matches_wide_product() {
+        let values = [
+            0, 1, 2, 255, 256, 0xFFFF, 0x1_0000, 0x1_0001, 46341, 1 << 20, 123_456_789,
+            i32::MAX, i32::MIN + 1, i32::MIN,
+        ];
+        for &a in &values {
+            for &b in &values {
+                for (a, b) in [(a, b), (a.wrapping_neg(), b), (a, b.wrapping_neg())] {
+                    for shift in 0..32 {
+                        let wide = (a as i64 * b as i64) >> shift;
+                        if wide != wide as i32 as i64 {
+                            continue;
+                        }
+                        assert_eq!(FixedMath::mul_shr(a, b, shift), wide as i32, "{} * {} >> {}", a, b, shift);
+                        assert_eq!(FloatMath::mul_shr(a, b, shift), wide as i32, "{} * {} >> {}", a, b, shift);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn div_matches_operator() {
+        let values = [
+            1, 2, 3, 7, 255, 256, 1000, 65535, 65536, 16_777_217, 123_456_789,
+            i32::MAX - 1, i32::MAX,
+        ];
+        for &n in values.iter().chain(&[0]) {
+            for &d in &values {
+                for (n, d) in [(n, d), (-n, d), (n, -d), (-n, -d)] {
+                    assert_eq!(FloatMath::div(n, d), n / d, "{} / {}", n, d);
+                    assert_eq!(FixedMath::div(n, d), n / d, "{} / {}", n, d);
+                }
+            }
+        }
+    }
+}
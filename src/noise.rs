@@ -0,0 +1,80 @@
+// Deterministic integer hashing and random numbers. Everything here is
+// seeded explicitly and uses only wrapping integer arithmetic, so the same
+// seed gives the same values on every platform. Use these instead of an
+// external RNG in painters and track generators to keep replays and
+// lockstep multiplayer in sync.
+use crate::FP_POS;
+
+// Mixes a 32-bit value, see https://nullprogram.com/blog/2018/07/31/
+const fn mix(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+// Hashes a road position with a seed. Neighboring positions give unrelated
+// values.
+pub const fn hash(t: i32, x: i32, seed: u32) -> u32 {
+    mix(mix(mix(seed) ^ t as u32) ^ x as u32)
+}
+
+// Like hash(), but for a single coordinate.
+pub const fn hash1(t: i32, seed: u32) -> u32 {
+    mix(mix(seed) ^ t as u32)
+}
+
+// Uniform value in [0, 1 << FP_POS) for a road position, e.g. a per-pixel
+// dithering threshold compared against an FP1 fraction.
+pub const fn threshold(t: i32, x: i32, seed: u32) -> i32 {
+    (hash(t, x, seed) >> (32 - FP_POS)) as i32
+}
+
+// Smooth 1D value noise in [0, 1 << FP_POS], FP1. Random values are placed
+// every 1 << period_shift units of t and interpolated linearly between them,
+// for things like slowly varying fog density or terrain roughness.
+pub const fn value_noise(t: i32, period_shift: i32, seed: u32) -> i32 {
+    let cell = t >> period_shift;
+    let frac = ((t - (cell << period_shift)) as i64) << FP_POS >> period_shift; // FP1
+    let a = (hash1(cell, seed) >> (32 - FP_POS)) as i64; // FP1
+    let b = (hash1(cell.wrapping_add(1), seed) >> (32 - FP_POS)) as i64; // FP1
+    (a + (((b - a) * frac) >> FP_POS)) as i32
+}
+
+// Small PRNG for generating sequences, e.g. procedural tracks. This is
+// PCG-XSH-RR with 64-bit state.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub const fn new(seed: u32) -> Self {
+        Rng { state: 0x853c_49e6_748f_ea9b ^ seed as u64 }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    // Uniform value in [min, max). Returns min if the range is empty.
+    pub fn range(&mut self, min: i32, max: i32) -> i32 {
+        if max <= min {
+            return min;
+        }
+        let span = (max as i64 - min as i64) as u64;
+        (min as i64 + ((self.next_u32() as u64 * span) >> 32) as i64) as i32
+    }
+
+    // Returns true with the given probability, FP1.
+    pub fn chance(&mut self, probability: i32) -> bool {
+        ((self.next_u32() >> (32 - FP_POS)) as i32) < probability
+    }
+}
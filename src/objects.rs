@@ -0,0 +1,105 @@
+// Roadside objects like trees, crowds, flags and signs. The library doesn't
+// draw sprites itself: games keep a list of objects, project them with
+// RoadRenderer::screen_pos() and scale_at(), and draw image() of each.
+
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RoadsideObject {
+    pub t: i32,                // Distance from the start of the road
+    pub x: i32,                // Offset from the road center, FP1
+    pub sprite: u16,           // First image, an index chosen by the game
+    pub frames: u16,           // Number of animation images, 1 if static
+    pub frames_per_image: u16, // Frames each animation image is shown
+    pub phase: u16,            // Animation offset, so that neighbours differ
+    pub mirrored: bool,        // Drawn flipped horizontally
+}
+
+impl RoadsideObject {
+    // A static object.
+    pub fn new(t: i32, x: i32, sprite: u16) -> Self {
+        Self::animated(t, x, sprite, 1, 1)
+    }
+
+    // An object cycling through frames images starting from sprite, e.g. a
+    // waving flag or a flashing arrow sign.
+    pub fn animated(t: i32, x: i32, sprite: u16, frames: u16, frames_per_image: u16) -> Self {
+        RoadsideObject {
+            t,
+            x,
+            sprite,
+            frames,
+            frames_per_image,
+            phase: 0,
+            mirrored: false,
+        }
+    }
+
+    // Returns the image to draw on the given frame, use the frame counter
+    // given to RoadRenderer::render(). Images past u16::MAX wrap around to 0.
+    pub fn image(&self, frame: i32) -> u16 {
+        if self.frames <= 1 {
+            return self.sprite;
+        }
+        let step = frame.div_euclid(self.frames_per_image.max(1) as i32) as i64 + self.phase as i64;
+        self.sprite.wrapping_add(step.rem_euclid(self.frames as i64) as u16)
+    }
+}
+
+// Places copies of object at offset (FP1) on both sides of the road. The left
+// one is mirrored, so that signs and arrows face the road on both sides.
+pub fn both_sides(object: RoadsideObject, offset: i32) -> [RoadsideObject; 2] {
+    let left = RoadsideObject {
+        x: -offset,
+        mirrored: !object.mirrored,
+        ..object
+    };
+    let right = RoadsideObject { x: offset, ..object };
+    [left, right]
+}
+
+// Lines both sides of the road with object from from_t to to_t, e.g. for
+// crowds or barriers. Consecutive pairs get consecutive animation phases so
+// that they don't animate in sync. Returns the number of objects written to
+// out, which is sorted by t.
+pub fn line_both_sides(
+    object: RoadsideObject,
+    offset: i32,
+    from_t: i32,
+    to_t: i32,
+    spacing: i32,
+    out: &mut [RoadsideObject],
+) -> usize {
+    let mut count = 0;
+    let mut t = from_t;
+    let mut phase = object.phase;
+    while t < to_t && count + 2 <= out.len() && spacing > 0 {
+        let pair = both_sides(RoadsideObject { t, phase, ..object }, offset);
+        out[count..count + 2].copy_from_slice(&pair);
+        count += 2;
+        t += spacing;
+        phase = phase.wrapping_add(1);
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_cycles_frames() {
+        let flag = RoadsideObject::animated(0, 0, 10, 3, 2);
+        assert_eq!([flag.image(0), flag.image(2), flag.image(5), flag.image(-1)], [10, 11, 12, 12]);
+        assert_eq!(RoadsideObject { phase: 1, ..flag }.image(0), 11);
+    }
+
+    #[test]
+    fn image_wraps_at_the_ends() {
+        let object = RoadsideObject {
+            phase: u16::MAX,
+            ..RoadsideObject::animated(0, 0, u16::MAX - 1, u16::MAX, 1)
+        };
+        assert_eq!(object.image(i32::MAX), 32765);
+        assert_eq!(object.image(2), 0);
+    }
+}
@@ -0,0 +1,151 @@
+// Counts how many times each pixel is written in a frame, to measure how well
+// the renderer avoids overdraw and to catch changes that start painting the
+// same pixels twice. Ideally every pixel is written exactly once.
+use crate::Painter;
+
+// Summary of a frame, see OverdrawCounter::stats().
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct OverdrawStats {
+    pub writes: u32,     // All pixel writes, including those outside the screen
+    pub missed: u32,     // Pixels that were never written
+    pub overdrawn: u32,  // Pixels that were written more than once
+    pub extra: u32,      // Writes beyond the first to each pixel
+    pub offscreen: u32,  // Writes outside the screen
+    pub max_count: u16,  // Most writes to a single pixel
+}
+
+// Wraps a painter and counts the writes to each pixel in a caller-provided
+// buffer of w * h counters. Copied rows count as writes when the wrapped
+// painter supports copy_row().
+pub struct OverdrawCounter<'p, 'b, P: Painter> {
+    pub painter: &'p mut P,
+    counts: &'b mut [u16],
+    w: i32,
+    h: i32,
+    offscreen: u32,
+}
+
+impl<'p, 'b, P: Painter> OverdrawCounter<'p, 'b, P> {
+    // Returns None if the buffer is smaller than w * h.
+    pub fn new(painter: &'p mut P, buffer: &'b mut [u16], w: i32, h: i32) -> Option<Self> {
+        if w < 0 || h < 0 {
+            return None;
+        }
+        let counts = buffer.get_mut(..(w as usize) * (h as usize))?;
+        counts.fill(0);
+        Some(OverdrawCounter { painter, counts, w, h, offscreen: 0 })
+    }
+
+    // Clears the counts, call between frames.
+    pub fn reset(&mut self) {
+        self.counts.fill(0);
+        self.offscreen = 0;
+    }
+
+    pub fn count(&self, x: i32, y: i32) -> u16 {
+        if x >= 0 && x < self.w && y >= 0 && y < self.h {
+            self.counts[(y * self.w + x) as usize]
+        } else {
+            0
+        }
+    }
+
+    // Stats of the rectangle from (x0, y0) to (x1, y1), exclusive. Offscreen
+    // writes are only included in stats().
+    pub fn region_stats(&self, x0: i32, y0: i32, x1: i32, y1: i32) -> OverdrawStats {
+        let mut stats = OverdrawStats::default();
+        let (x0, x1) = (x0.clamp(0, self.w), x1.clamp(0, self.w));
+        if x0 >= x1 {
+            return stats;
+        }
+        for y in y0.max(0)..y1.min(self.h) {
+            let row = (y * self.w) as usize;
+            for &count in &self.counts[row + x0 as usize..row + x1 as usize] {
+                stats.writes += count as u32;
+                match count {
+                    0 => stats.missed += 1,
+                    1 => {}
+                    _ => {
+                        stats.overdrawn += 1;
+                        stats.extra += count as u32 - 1;
+                    }
+                }
+                stats.max_count = stats.max_count.max(count);
+            }
+        }
+        stats
+    }
+
+    pub fn row_stats(&self, y: i32) -> OverdrawStats {
+        self.region_stats(0, y, self.w, y + 1)
+    }
+
+    pub fn stats(&self) -> OverdrawStats {
+        let mut stats = self.region_stats(0, 0, self.w, self.h);
+        stats.writes += self.offscreen;
+        stats.offscreen = self.offscreen;
+        stats
+    }
+
+    // The row with the most extra writes and their number, or None if
+    // nothing was overdrawn.
+    pub fn worst_row(&self) -> Option<(i32, u32)> {
+        let mut worst = None;
+        for y in 0..self.h {
+            let extra = self.row_stats(y).extra;
+            if extra > worst.map_or(0, |(_, e)| e) {
+                worst = Some((y, extra));
+            }
+        }
+        worst
+    }
+
+    fn add(&mut self, x_begin: i32, x_end: i32, y: i32) {
+        if x_end <= x_begin {
+            return;
+        }
+        let (begin, end) = (x_begin.max(0), x_end.min(self.w));
+        if y < 0 || y >= self.h || begin >= end {
+            self.offscreen += (x_end - x_begin) as u32;
+            return;
+        }
+        self.offscreen += (x_end - x_begin - (end - begin)) as u32;
+        let row = (y * self.w) as usize;
+        for count in &mut self.counts[row + begin as usize..row + end as usize] {
+            *count = count.saturating_add(1);
+        }
+    }
+}
+
+impl<'p, 'b, P: Painter> Painter for OverdrawCounter<'p, 'b, P> {
+    type ColorType = P::ColorType;
+
+    fn draw(&mut self, x: i32, y: i32, color: &Self::ColorType) {
+        self.add(x, x + 1, y);
+        self.painter.draw(x, y, color);
+    }
+
+    forward_painter_colors!(painter);
+
+    fn fill_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType) {
+        self.add(x_begin, x_end, y);
+        self.painter.fill_span(x_begin, x_end, y, color);
+    }
+
+    fn blend_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType, amount: i32) {
+        self.add(x_begin, x_end, y);
+        self.painter.blend_span(x_begin, x_end, y, color, amount);
+    }
+
+    fn begin_road_row(&mut self, y: i32, left: i32, right: i32, t: i32) {
+        self.painter.begin_road_row(y, left, right, t);
+    }
+
+    fn copy_row(&mut self, y: i32, x_begin: i32, x_end: i32, src_y: i32, x_shift: i32) -> bool {
+        if !self.painter.copy_row(y, x_begin, x_end, src_y, x_shift) {
+            return false;
+        }
+        self.add(x_begin, x_end, y);
+        true
+    }
+}
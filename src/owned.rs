@@ -0,0 +1,114 @@
+// Tracks that own their data, for tools and generators that build tracks at
+// runtime. The renderer itself only borrows, so this is behind the alloc
+// feature.
+use crate::markings::{Decal, Hazard};
+use crate::objects::RoadsideObject;
+use crate::track::TrackError;
+use crate::{validate_segments, Error, Segment, SegmentIndex, Track};
+use alloc::vec::Vec;
+
+pub struct OwnedTrack {
+    segments: Vec<Segment>,
+    starts: Vec<i32>, // Start distance of each segment, plus the total length
+    // Layers placed along the track, see the objects and markings modules.
+    pub objects: Vec<RoadsideObject>,
+    pub decals: Vec<Decal>,
+    pub hazards: Vec<Hazard>,
+}
+
+impl OwnedTrack {
+    pub fn from_vec(segments: Vec<Segment>) -> Self {
+        let mut track = OwnedTrack {
+            segments: Vec::new(),
+            starts: Vec::with_capacity(segments.len() + 1),
+            objects: Vec::new(),
+            decals: Vec::new(),
+            hazards: Vec::new(),
+        };
+        track.starts.push(0);
+        for seg in segments {
+            track.push(seg);
+        }
+        track
+    }
+
+    // Appends a segment to the end of the road.
+    pub fn push(&mut self, segment: Segment) {
+        let end = self.total_length();
+        self.segments.push(segment);
+        self.starts.push(end + segment.length);
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn total_length(&self) -> i32 {
+        self.starts[self.segments.len()]
+    }
+
+    // Returns an index of the segments, see RoadRenderer::set_segment_index().
+    // It's kept up to date by push(), so this doesn't compute anything.
+    pub fn index(&self) -> SegmentIndex<'_> {
+        SegmentIndex {
+            segments: &self.segments,
+            starts: &self.starts,
+        }
+    }
+
+    // Borrows the segments as a Track. Attach index() to it for faster set().
+    pub fn track(&self) -> Track<'_> {
+        Track::new(&self.segments)
+    }
+}
+
+// Builds a track piece by piece, e.g. from a level script, with the edits
+// that are clumsy on a fixed array. Indices are checked like in
+// track::apply_patch(), and build() checks the finished track.
+#[derive(Clone, Default)]
+pub struct TrackBuilder {
+    segments: Vec<Segment>,
+}
+
+impl TrackBuilder {
+    pub fn new() -> Self {
+        TrackBuilder { segments: Vec::new() }
+    }
+
+    // Appends a segment to the end of the road.
+    pub fn push(&mut self, segment: Segment) -> &mut Self {
+        self.segments.push(segment);
+        self
+    }
+
+    pub fn extend(&mut self, segments: &[Segment]) -> &mut Self {
+        self.segments.extend_from_slice(segments);
+        self
+    }
+
+    // Inserts segments before index.
+    pub fn insert(&mut self, index: usize, segments: &[Segment]) -> Result<&mut Self, TrackError> {
+        self.splice(index, 0, segments)
+    }
+
+    // Replaces count segments starting from index with segments, which may
+    // be more or fewer. An empty slice removes them.
+    pub fn splice(&mut self, index: usize, count: usize, segments: &[Segment]) -> Result<&mut Self, TrackError> {
+        let len = self.segments.len();
+        if index > len || count > len - index {
+            return Err(TrackError::PatchOutOfRange);
+        }
+        self.segments.splice(index..index + count, segments.iter().copied());
+        Ok(self)
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    // Checks the track with validate_segments() and indexes it.
+    pub fn build(self) -> Result<OwnedTrack, Error> {
+        validate_segments(&self.segments)?;
+        Ok(OwnedTrack::from_vec(self.segments))
+    }
+}
@@ -0,0 +1,101 @@
+// Color cycling for palette-indexed painters. A ColorCycle rotates a range of
+// palette entries as frames pass, like the waterfalls and neon signs of old
+// games. The rotation is a function of the frame counter given to
+// RoadRenderer::render(), which painters also receive, so the road and the
+// rest of the game always show the same phase.
+//
+// There are two ways to use a cycle: painters that return palette indices can
+// remap them with index(), and targets with a hardware palette can rewrite it
+// once per frame with apply().
+
+#[derive(Copy, Clone, Debug)]
+pub struct ColorCycle {
+    pub start: usize,          // First palette entry of the cycled range
+    pub len: usize,            // Number of entries in the range
+    pub frames_per_step: i32,  // Frames between rotations by one entry
+    pub reverse: bool,         // Rotate toward lower indices
+}
+
+impl ColorCycle {
+    pub fn new(start: usize, len: usize, frames_per_step: i32) -> Self {
+        ColorCycle {
+            start,
+            len,
+            frames_per_step,
+            reverse: false,
+        }
+    }
+
+    // Number of entries the range is rotated by on the given frame.
+    pub fn offset(&self, frame: i32) -> usize {
+        if self.len == 0 || self.frames_per_step <= 0 {
+            return 0;
+        }
+        let steps = frame.div_euclid(self.frames_per_step).rem_euclid(self.len as i32) as usize;
+        if self.reverse {
+            (self.len - steps) % self.len
+        } else {
+            steps
+        }
+    }
+
+    // Returns the entry shown in place of index on the given frame. Indices
+    // outside the range are returned as-is.
+    pub fn index(&self, index: usize, frame: i32) -> usize {
+        if index < self.start || index >= self.start.saturating_add(self.len) {
+            return index;
+        }
+        self.start + (index - self.start + self.offset(frame)) % self.len
+    }
+
+    // Writes the palette for the given frame to out, rotating the range of
+    // base. Entries outside the range are copied as-is. A range running past
+    // the end of base is cut short, so only the entries base has rotate.
+    pub fn apply<C: Copy>(&self, base: &[C], out: &mut [C], frame: i32) {
+        let end = self.start.saturating_add(self.len).min(base.len());
+        let cycle = ColorCycle {
+            len: end.saturating_sub(self.start),
+            ..*self
+        };
+        for (i, color) in out.iter_mut().enumerate().take(base.len()) {
+            *color = base[cycle.index(i, frame)];
+        }
+    }
+}
+
+// Blinks between two colors, e.g. for flashing barriers. Each color is shown
+// for frames_per_state frames.
+pub fn flash<C: Copy>(on: C, off: C, frame: i32, frames_per_state: i32) -> C {
+    if frames_per_state <= 0 || frame.div_euclid(frames_per_state) & 1 == 0 {
+        on
+    } else {
+        off
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_rotates_range() {
+        let base = [0, 1, 2, 3, 4, 5];
+        let mut out = [0; 6];
+        ColorCycle::new(1, 3, 2).apply(&base, &mut out, 2);
+        assert_eq!(out, [0, 2, 3, 1, 4, 5]);
+    }
+
+    #[test]
+    fn apply_clamps_range_to_palette() {
+        let base = [0, 1, 2, 3];
+        let mut out = [9; 4];
+        ColorCycle::new(2, 4, 1).apply(&base, &mut out, 1);
+        assert_eq!(out, [0, 1, 3, 2]);
+
+        ColorCycle::new(6, 2, 1).apply(&base, &mut out, 1);
+        assert_eq!(out, base);
+
+        ColorCycle::new(1, usize::MAX, 1).apply(&base, &mut out, 1);
+        assert_eq!(out, [0, 2, 3, 1]);
+    }
+}
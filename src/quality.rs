@@ -0,0 +1,91 @@
+// Keeps the per-frame rendering cost near a budget by stepping through
+// quality levels. Each level evaluates fewer rows near the horizon (see
+// RoadRenderer::set_row_lod()) or shortens the draw distance, so slow
+// hardware loses detail gradually instead of dropping frames.
+//
+// The cost can be anything that grows with the work of a frame, e.g. a cycle
+// counter read around render(), or the spans of a counting::CountingPainter.
+// Every pixel is drawn at every level, so pixel counts don't go down.
+use crate::{RoadRenderer, FP_POS};
+
+const FULL: i32 = 1 << FP_POS;
+
+// Level of detail of a quality level: rows past lod_z (a fraction of the draw
+// distance, FP1) repeat every lod_rows'th row, and the draw distance is
+// multiplied by distance (FP1).
+struct Level {
+    lod_z: i32,
+    lod_rows: i32,
+    distance: i32,
+}
+
+const LEVELS: [Level; 7] = [
+    Level { lod_z: FULL, lod_rows: 1, distance: FULL },
+    Level { lod_z: FULL / 2, lod_rows: 2, distance: FULL },
+    Level { lod_z: FULL / 4, lod_rows: 2, distance: FULL },
+    // Half vertical resolution
+    Level { lod_z: 0, lod_rows: 2, distance: FULL },
+    Level { lod_z: 0, lod_rows: 2, distance: FULL * 3 / 4 },
+    Level { lod_z: 0, lod_rows: 3, distance: FULL * 3 / 4 },
+    Level { lod_z: 0, lod_rows: 3, distance: FULL / 2 },
+];
+
+pub struct QualityController {
+    pub budget: i32,         // Cost allowed per frame
+    pub max_z: i32,          // Draw distance at full quality, FP1
+    pub recover_frames: i32, // Frames under 3/4 of the budget before raising quality
+    level: usize,
+    frames_under: i32,
+}
+
+impl QualityController {
+    pub fn new(budget: i32, max_z: i32) -> Self {
+        QualityController {
+            budget,
+            max_z,
+            recover_frames: 30,
+            level: 0,
+            frames_under: 0,
+        }
+    }
+
+    // Zero is full quality, higher levels are cheaper.
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    pub fn max_level(&self) -> usize {
+        LEVELS.len() - 1
+    }
+
+    // Call after each frame with its cost. Quality drops right away when the
+    // budget is exceeded, and only rises after recover_frames cheap frames in
+    // a row, so that it doesn't flicker between two levels.
+    pub fn update(&mut self, cost: i32) {
+        if cost > self.budget {
+            self.level = (self.level + 1).min(self.max_level());
+            self.frames_under = 0;
+        } else if (cost as i64) * 4 < (self.budget as i64) * 3 {
+            self.frames_under += 1;
+            if self.frames_under >= self.recover_frames && self.level > 0 {
+                self.level -= 1;
+                self.frames_under = 0;
+            }
+        } else {
+            self.frames_under = 0;
+        }
+    }
+
+    // The draw distance to give to render() at the current level, FP1.
+    pub fn draw_distance(&self) -> i32 {
+        ((self.max_z as i64 * LEVELS[self.level].distance as i64) >> FP_POS) as i32
+    }
+
+    // Sets the row LOD of the renderer for the current level. Call before
+    // render(), which should get draw_distance() as max_z.
+    pub fn apply(&self, renderer: &mut RoadRenderer) {
+        let level = &LEVELS[self.level];
+        let lod_z = ((self.draw_distance() as i64 * level.lod_z as i64) >> FP_POS) as i32;
+        renderer.set_row_lod(lod_z, level.lod_rows);
+    }
+}
@@ -0,0 +1,289 @@
+// Floating point reference of the road projection, and proptest generators
+// for comparing the fixed point renderer against it. The reference follows
+// the same road model as integrate_segment(), but without rounding or
+// overflow, so differences beyond a few pixels point to precision or
+// overflow regressions.
+//
+// check_random() runs the whole comparison over random tracks and cameras,
+// e.g. from a test of the crate using this one:
+//
+//     poisjuoksu::reference::check_random(Config::with_cases(256)).unwrap();
+use crate::{validate_segments, RoadRenderer, Segment, SideInclination, FP_POS};
+use proptest::prelude::*;
+use proptest::test_runner::{Config, TestCaseError, TestError, TestRunner};
+use std::format;
+use std::vec::Vec;
+
+const ONE: f64 = (1 << FP_POS) as f64;
+
+// Where a point ended up, in the units of RoadRenderer::screen_pos().
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ReferencePos {
+    pub x_px: f64,
+    pub y_px: f64,
+    pub z: f64, // FP1, distance in front of the camera
+}
+
+// Camera state for the comparison, like the arguments of render().
+#[derive(Copy, Clone, Debug)]
+pub struct CameraState {
+    pub t: i32,
+    pub x_offset: i32,
+    pub y_offset: i32,
+}
+
+// How far a projected point may be from the reference, in pixels. The fixed
+// point model loses precision with distance, mostly where hills and curves
+// meet, so the allowed error grows with the depth of the point.
+#[derive(Copy, Clone, Debug)]
+pub struct Tolerance {
+    pub pixels: f64,
+    pub per_depth: f64, // Added pixels per unit of depth
+}
+
+impl Tolerance {
+    fn at(&self, z: f64) -> f64 {
+        self.pixels + self.per_depth * z / ONE
+    }
+}
+
+// A point that projected further from the reference than allowed.
+#[derive(Copy, Clone, Debug)]
+pub struct Mismatch {
+    pub t_offset: i32,
+    pub x_offset: i32,
+    pub y_offset: i32,
+    pub fixed: (i32, i32),
+    pub reference: (f64, f64),
+}
+
+#[derive(Copy, Clone, Default)]
+struct State {
+    x_offset: f64, // FP1
+    y_offset: f64, // FP1
+    z_offset: f64, // FP1
+    x_slope: f64,  // FP1
+    y_slope: f64,  // FP1
+}
+
+// integrate_segment() in floating point.
+fn integrate_segment(x_curve: i32, y_curve: i32, length: i32, s: &mut State) {
+    let x_curve = x_curve as f64;
+    let y_curve = y_curve as f64;
+    let length = length as f64;
+    let z;
+
+    if y_curve == 0.0 {
+        let t_factor = (ONE * ONE + s.y_slope * s.y_slope).sqrt();
+        z = length * ONE / t_factor;
+        s.y_offset += s.y_slope * z / ONE;
+    } else {
+        let tsqrtcurve = (y_curve.abs() * ONE).sqrt();
+        let z2 = 4.0 * length / tsqrtcurve;
+        z = (z2 * ONE).sqrt() * ONE.sqrt();
+
+        s.y_offset += y_curve * z2 + s.y_slope * z / ONE;
+        s.y_slope += y_curve * z * 2.0 / ONE;
+    }
+    s.z_offset += z;
+
+    s.x_offset += x_curve * z / ONE * z / ONE + s.x_slope * z / ONE;
+    s.x_slope += 2.0 * x_curve * z / ONE;
+}
+
+// The reference of RoadRenderer::screen_pos(), for the renderer's current
+// camera position.
+pub fn screen_pos(
+    renderer: &RoadRenderer,
+    (w, h): (i32, i32),
+    camera_x_offset: i32,
+    camera_y_offset: i32,
+    point_t_offset: i32,
+    point_x_offset: i32,
+    point_y_offset: i32,
+) -> ReferencePos {
+    let mut s = State {
+        x_offset: camera_x_offset as f64,
+        y_offset: camera_y_offset as f64,
+        ..State::default()
+    };
+    let cursor = &renderer.cursor;
+    let mut t_left = point_t_offset;
+    for index in cursor.segment..renderer.segments.len() {
+        if t_left == 0 {
+            break;
+        }
+        let seg = &renderer.segments[index];
+        let length_left = seg.length - if index == cursor.segment {
+            cursor.t - cursor.base_t
+        } else {
+            0
+        };
+        let length = t_left.min(length_left);
+        integrate_segment(seg.x_curve, seg.y_curve, length, &mut s);
+        t_left -= length;
+    }
+
+    let z = if s.z_offset == 0.0 { 1.0 } else { s.z_offset };
+    let (cx, cy) = renderer.view_center((w, h));
+    let near = renderer.near as f64;
+    ReferencePos {
+        x_px: cx as f64 + near * (point_x_offset as f64 - s.x_offset) / z,
+        y_px: cy as f64 + near * (s.y_offset - point_y_offset as f64) / z,
+        z,
+    }
+}
+
+// Compares RoadRenderer::screen_pos() with the floating point screen_pos()
+// for one point. Points that aren't on screen or are closer than one unit in
+// front of the camera are skipped, as nothing is drawn there.
+pub fn check_screen_pos(
+    renderer: &RoadRenderer,
+    size: (i32, i32),
+    camera_x_offset: i32,
+    camera_y_offset: i32,
+    (t_offset, x_offset, y_offset): (i32, i32, i32),
+    tolerance: Tolerance,
+) -> Result<(), Mismatch> {
+    let reference = screen_pos(
+        renderer,
+        size,
+        camera_x_offset,
+        camera_y_offset,
+        t_offset,
+        x_offset,
+        y_offset,
+    );
+    let (w, h) = size;
+    if reference.z < ONE
+        || !(0.0..=w as f64).contains(&reference.x_px)
+        || !(0.0..=h as f64).contains(&reference.y_px)
+    {
+        return Ok(());
+    }
+
+    let pos = renderer.screen_pos(size, camera_x_offset, camera_y_offset, t_offset, x_offset, y_offset);
+    let tolerance = tolerance.at(reference.z);
+    if (pos.x as f64 - reference.x_px).abs() > tolerance
+        || (pos.y as f64 - reference.y_px).abs() > tolerance
+    {
+        return Err(Mismatch {
+            t_offset,
+            x_offset,
+            y_offset,
+            fixed: (pos.x, pos.y),
+            reference: (reference.x_px, reference.y_px),
+        });
+    }
+    Ok(())
+}
+
+// Compares the road's center and edges every step units up to max_t ahead of
+// the camera, i.e. the geometry render() draws row by row.
+pub fn check_road(
+    renderer: &RoadRenderer,
+    size: (i32, i32),
+    camera_x_offset: i32,
+    camera_y_offset: i32,
+    half_width: i32,
+    step: i32,
+    max_t: i32,
+    tolerance: Tolerance,
+) -> Result<(), Mismatch> {
+    let mut t_offset = step;
+    while t_offset <= max_t {
+        for x_offset in [-half_width, 0, half_width] {
+            check_screen_pos(
+                renderer,
+                size,
+                camera_x_offset,
+                camera_y_offset,
+                (t_offset, x_offset, 0),
+                tolerance,
+            )?;
+        }
+        t_offset += step;
+    }
+    Ok(())
+}
+
+pub fn side_inclination() -> impl Strategy<Value = SideInclination> {
+    prop_oneof![
+        Just(SideInclination::Uphill),
+        Just(SideInclination::Flat),
+        Just(SideInclination::Downhill),
+        Just(SideInclination::Wall),
+    ]
+}
+
+// Segments within the ranges of the example tracks, which always pass
+// validate_segments().
+pub fn segment() -> impl Strategy<Value = Segment> {
+    (
+        side_inclination(),
+        side_inclination(),
+        1..=400i32,
+        -30..=30i32,
+        -4..=4i32,
+    )
+        .prop_map(|(left, right, length, x_curve, y_curve)| {
+            Segment::new((left, right), length << FP_POS, x_curve, y_curve)
+        })
+}
+
+pub fn segments(max_len: usize) -> impl Strategy<Value = Vec<Segment>> {
+    proptest::collection::vec(segment(), 1..=max_len)
+        .prop_filter("invalid track", |segments| validate_segments(segments).is_ok())
+}
+
+// A camera somewhere in the first max_t units of the road, within a car
+// width of the road and above it.
+pub fn camera(max_t: i32) -> impl Strategy<Value = CameraState> {
+    (0..max_t.max(1), -50 << FP_POS..=50 << FP_POS, 1 << FP_POS..=100 << FP_POS)
+        .prop_map(|(t, x_offset, y_offset)| CameraState { t, x_offset, y_offset })
+}
+
+// Runs check_road() over random tracks and cameras at 320x240, on the 2000
+// units of road ahead of the camera. The tolerance is a little above the
+// largest errors of the current fixed point model, so overflows and lost
+// precision show up as failures.
+pub fn check_random(config: Config) -> Result<(), TestError<(Vec<Segment>, CameraState)>> {
+    const TOLERANCE: Tolerance = Tolerance { pixels: 4.0, per_depth: 1.0 / 16.0 };
+
+    let strategy = segments(16).prop_flat_map(|segments| {
+        let length: i32 = segments.iter().map(|seg| seg.length).sum();
+        (Just(segments), camera(length))
+    });
+    TestRunner::new(config).run(&strategy, |(segments, camera)| {
+        let mut renderer = RoadRenderer::new(&segments, 32);
+        renderer.set(camera.t);
+        check_road(
+            &renderer,
+            (320, 240),
+            camera.x_offset,
+            camera.y_offset,
+            50 << FP_POS,
+            10 << FP_POS,
+            2000 << FP_POS,
+            TOLERANCE,
+        )
+        .map_err(|mismatch| TestCaseError::fail(format!("{:?}", mismatch)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // segment() and the tolerance follow the example tracks, which are tuned
+    // for 8 bits. With 4 the curves are 16 times sharper and drift tens of
+    // pixels off the reference, and with 10 the longest tracks run out of
+    // range in integrate_segment().
+    #[test]
+    fn matches_float_reference() {
+        if FP_POS != 8 {
+            return;
+        }
+        check_random(Config::with_cases(64)).unwrap();
+    }
+}
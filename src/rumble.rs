@@ -0,0 +1,90 @@
+// Rumble intensity for force feedback and controller vibration, derived from
+// the same road width, markings and hazards the painter draws with, so that
+// what the player feels matches what they see. Intensities are FP1, from zero
+// to 1 << FP_POS.
+use crate::markings::{self, Decal, Hazard, Surface};
+use crate::{noise, FP_POS};
+
+const FULL: i32 = 1 << FP_POS;
+
+// Rumble strips at the road edges alternate between these every
+// CURB_PERIOD / 2 units of t.
+const CURB_PERIOD: i32 = 4 << FP_POS;
+const CURB_HIGH: i32 = FULL;
+const CURB_LOW: i32 = FULL / 2;
+// Off-road is rough, with some noise on top.
+const OFF_ROAD: i32 = FULL * 3 / 4;
+const OFF_ROAD_NOISE: i32 = FULL / 4;
+// A short pulse when a wheel crosses a lane line or drives over paint.
+const LINE_PULSE: i32 = FULL / 2;
+const MARKING: i32 = FULL / 8;
+
+pub struct Rumble<'a> {
+    pub road_width: i32,       // Half width of the road like Painter::road_width(), FP2
+    pub curb_width: i32,       // Width of the rumble strips inside the road edges, FP2
+    pub lines: &'a [i32],      // Lateral positions of the lane lines, FP2
+    pub hazards: &'a [Hazard], // Sorted by t, see markings::surface_at()
+    pub decals: &'a [Decal],   // Sorted by t, see markings::decal_at()
+    pub landing_speed: i32,    // Vertical speed of a landing at full rumble, FP1
+    pub seed: u32,             // For the noise off-road
+}
+
+impl<'a> Rumble<'a> {
+    pub fn new(road_width: i32) -> Self {
+        Rumble {
+            road_width,
+            curb_width: 4 << (2 * FP_POS),
+            lines: &[],
+            hazards: &[],
+            decals: &[],
+            landing_speed: 4 << FP_POS,
+            seed: 0,
+        }
+    }
+
+    // Rumble of a wheel at (tx, t), which was at prev_tx on the previous
+    // frame. Pass tx as prev_tx to leave out the lane line pulses.
+    pub fn at(&self, t: i32, tx: i32, prev_tx: i32) -> i32 {
+        let atx = tx.abs();
+        let mut rumble = if atx > self.road_width {
+            OFF_ROAD + ((noise::threshold(t >> FP_POS, 0, self.seed) * OFF_ROAD_NOISE) >> FP_POS)
+        } else if atx > self.road_width - self.curb_width {
+            if markings::is_dash(t, CURB_PERIOD, CURB_PERIOD / 2) {
+                CURB_HIGH
+            } else {
+                CURB_LOW
+            }
+        } else {
+            0
+        };
+
+        rumble = rumble.max(match markings::surface_at(self.hazards, tx, t) {
+            Surface::Asphalt => 0,
+            Surface::Puddle => FULL / 2,
+            Surface::Oil => FULL / 8,
+            // Ice is smooth, the lack of rumble is the warning.
+            Surface::Ice => 0,
+        });
+
+        if markings::decal_at(self.decals, tx, t).is_some() {
+            rumble = rumble.max(MARKING);
+        }
+
+        let (low, high) = if prev_tx < tx { (prev_tx, tx) } else { (tx, prev_tx) };
+        if self.lines.iter().any(|&line| line > low && line <= high) {
+            rumble = rumble.max(LINE_PULSE);
+        }
+        rumble
+    }
+
+    // Rumble of landing from a jump with the given downward speed, FP1. The
+    // road doesn't know when the car is in the air, so the game's physics
+    // provides the speed.
+    pub fn landing(&self, vertical_speed: i32) -> i32 {
+        if self.landing_speed <= 0 {
+            return 0;
+        }
+        let rumble = ((vertical_speed.max(0) as i64) << FP_POS) / self.landing_speed as i64;
+        rumble.min(FULL as i64) as i32
+    }
+}
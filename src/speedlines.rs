@@ -0,0 +1,100 @@
+// Streaks flying outward from the vanishing point, for a sense of speed. The
+// streaks are drawn over the sky and terrain after the rest of the frame with
+// Painter::blend_span(), and their number and length grow with the step
+// given to RoadRenderer::advance(). See RoadRenderer::set_speed_lines().
+use crate::{icos, isin, noise, Painter, FP_POS};
+
+// The road columns of each row are recorded into road during render(), so
+// that the streaks can leave the road out.
+pub struct SpeedLines<'a> {
+    road: &'a mut [(i32, i32)], // One entry per screen row
+    pub count: i32,      // Number of streaks at full speed
+    pub length: i32,     // Length of the streaks at full speed, in pixels
+    pub period: i32,     // Frames a streak takes to fly across the screen
+    pub min_speed: i32,  // Speed where the streaks appear, FP1 per frame
+    pub full_speed: i32, // Speed where they reach full count and length
+    pub seed: u32,
+}
+
+impl<'a> SpeedLines<'a> {
+    // The buffer needs an entry for each row of the screen. The rest can be
+    // adjusted through the public fields.
+    pub fn new(buffer: &'a mut [(i32, i32)]) -> Self {
+        SpeedLines {
+            road: buffer,
+            count: 32,
+            length: 24,
+            period: 8,
+            min_speed: 4 << FP_POS,
+            full_speed: 16 << FP_POS,
+            seed: 0,
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.road.fill((0, 0));
+    }
+
+    pub(crate) fn record_road(&mut self, y: i32, begin: i32, end: i32) {
+        if let Some(row) = self.road.get_mut(y as usize) {
+            *row = (begin, end);
+        }
+    }
+
+    // How far the speed is between min_speed and full_speed, FP1.
+    pub fn intensity(&self, speed: i32) -> i32 {
+        if speed <= self.min_speed {
+            0
+        } else if speed >= self.full_speed {
+            1 << FP_POS
+        } else {
+            ((((speed - self.min_speed) as i64) << FP_POS) / (self.full_speed - self.min_speed) as i64) as i32
+        }
+    }
+
+    pub(crate) fn draw<P: Painter>(
+        &self,
+        painter: &mut P,
+        (w, h): (i32, i32),
+        (cx, cy): (i32, i32),
+        speed: i32,
+        frame: i32
+    ) {
+        let intensity = self.intensity(speed); // FP1
+        if intensity == 0 || self.period <= 0 {
+            return;
+        }
+        let color = match painter.speed_line_color(frame) {
+            Some(color) => color,
+            None => return,
+        };
+        let count = (self.count * intensity) >> FP_POS;
+        let length = ((self.length * intensity) >> FP_POS).max(1);
+        let reach = w.max(h);
+        for i in 0..count {
+            // Each streak respawns at a new angle when it has crossed the
+            // screen, the streaks are staggered so that they don't all
+            // respawn together.
+            let time = frame.wrapping_add(i * self.period / count.max(1));
+            let epoch = time.div_euclid(self.period);
+            let age = time.rem_euclid(self.period);
+            let angle = (noise::hash(i, epoch, self.seed) >> 16) as i32;
+            let (dx, dy) = (icos(angle), isin(angle)); // FP2
+            // Step along the major axis one pixel at a time.
+            let major = dx.abs().max(dy.abs()); // FP2
+            let start = reach * age / self.period;
+            for k in start..start + length {
+                let x = cx + (k as i64 * dx as i64 / major as i64) as i32;
+                let y = cy + (k as i64 * dy as i64 / major as i64) as i32;
+                if x < 0 || x >= w || y < 0 || y >= h {
+                    break;
+                }
+                let (begin, end) = self.road.get(y as usize).copied().unwrap_or((0, 0));
+                if x >= begin && x < end {
+                    continue;
+                }
+                painter.blend_span(x, x + 1, y, &color, intensity);
+            }
+        }
+    }
+}
@@ -0,0 +1,298 @@
+// Roads that are generated or loaded piece by piece, e.g. endless
+// procedurally generated roads. A SegmentStream keeps a window of segments
+// around the camera in a fixed buffer, pulling new ones from a SegmentSource
+// as the camera moves on, so memory use stays constant however long the
+// road is. Slices, callbacks (FnSource, track::RecordSource) and
+// track::CompressedSegments are sources too, for tracks stored in a form the
+// renderer can't read directly. A StreamingTrack is the same window without a
+// source, for games that push segments into it as they generate them.
+//
+// A renderer can't borrow segments() while the stream changes them, so give
+// it the whole stream instead. It then moves the window along with the
+// camera itself, keeping its position, buffers and other settings:
+//
+//     let mut road = RoadRenderer::new(&[], near);
+//     road.set_segments_window(&mut stream);
+//     loop {
+//         road.advance(step);
+//         road.update_window();
+//         road.render::<_, W, H>(&mut painter, x_offset, y_offset, max_z, frame);
+//     }
+
+use crate::Segment;
+
+pub trait SegmentSource {
+    // Returns the segment at the given index, counted from the start of the
+    // road, or None if the road ends before it. Called in increasing order
+    // of index.
+    fn segment(&mut self, index: u32) -> Option<Segment>;
+}
+
+// A track that's in memory already, e.g. in external flash that's slow to
+// read. The stream copies the segments near the camera into the window.
+impl SegmentSource for &[Segment] {
+    fn segment(&mut self, index: u32) -> Option<Segment> {
+        self.get(index as usize).copied()
+    }
+}
+
+// Segments returned by a callback, e.g. reading them from program memory
+// with a special accessor or generating them on the fly. The callback gets
+// the index of a segment, and returns None past the end of the road.
+pub struct FnSource<F: FnMut(u32) -> Option<Segment>>(pub F);
+
+impl<F: FnMut(u32) -> Option<Segment>> SegmentSource for FnSource<F> {
+    fn segment(&mut self, index: u32) -> Option<Segment> {
+        (self.0)(index)
+    }
+}
+
+// A window of segments that the game appends to itself, e.g. an endless road
+// generated a few segments at a time. Segments far enough behind the camera
+// are retired to make room for new ones, and positions are rebased to the
+// start of the window, so they stay small however far the camera goes.
+//
+//     while track.free() > 0 {
+//         track.push(generate_segment());
+//     }
+//     t -= track.retire(t);
+//
+// The window is kept in order rather than wrapped around, since the renderer
+// needs the segments as one slice.
+pub struct StreamingTrack<'w> {
+    window: &'w mut [Segment],
+    len: usize,   // Number of valid segments in the window
+    pushed: u32,  // Number of segments pushed so far
+    behind: i32,  // Length of road to keep behind the camera
+    origin: i32,  // Distance of the window from the start of the road, wrapping
+}
+
+impl<'w> StreamingTrack<'w> {
+    // behind is how much road is kept behind the camera, as in
+    // SegmentStream::new().
+    pub fn new(window: &'w mut [Segment], behind: i32) -> Self {
+        StreamingTrack {
+            window,
+            len: 0,
+            pushed: 0,
+            behind,
+            origin: 0,
+        }
+    }
+
+    // Appends a segment to the end of the road. Returns false if the window
+    // is full.
+    pub fn push(&mut self, segment: Segment) -> bool {
+        if self.len == self.window.len() {
+            return false;
+        }
+        self.window[self.len] = segment;
+        self.len += 1;
+        self.pushed += 1;
+        true
+    }
+
+    // Number of segments that can be pushed before the window is full.
+    pub fn free(&self) -> usize {
+        self.window.len() - self.len
+    }
+
+    // Drops the segments that are more than the kept length behind t, which
+    // is relative to the start of the window. Returns how much the start of
+    // the window moved; subtract it from positions relative to the window.
+    pub fn retire(&mut self, t: i32) -> i32 {
+        let mut dropped = 0;
+        let mut shift = 0;
+        while dropped < self.len {
+            let length = self.window[dropped].length;
+            if shift + length > t - self.behind {
+                break;
+            }
+            shift += length;
+            dropped += 1;
+        }
+        if dropped > 0 {
+            self.origin = self.origin.wrapping_add(shift);
+            self.window.copy_within(dropped..self.len, 0);
+            self.len -= dropped;
+        }
+        shift
+    }
+
+    // Length of the road in the window.
+    pub fn length(&self) -> i32 {
+        self.segments().iter().map(|seg| seg.length).sum()
+    }
+
+    // The segments currently in the window, starting from t = 0.
+    pub fn segments(&self) -> &[Segment] {
+        &self.window[..self.len]
+    }
+
+    // Distance of the start of the window from the start of the road, see
+    // RoadRenderer::set_t_origin(). Wraps around on very long roads.
+    pub fn origin(&self) -> i32 {
+        self.origin
+    }
+
+    // Index of the first segment of the window, counted from the first
+    // segment pushed.
+    pub fn first_index(&self) -> u32 {
+        self.pushed - self.len as u32
+    }
+}
+
+pub struct SegmentStream<'w, S: SegmentSource> {
+    source: S,
+    track: StreamingTrack<'w>,
+    ended: bool, // The source has returned None
+}
+
+impl<'w, S: SegmentSource> SegmentStream<'w, S> {
+    // behind is how much road is kept behind the camera, e.g. for a rear
+    // view or sprites just passed. The window should have room for at least
+    // the segments within the draw distance.
+    pub fn new(source: S, window: &'w mut [Segment], behind: i32) -> Self {
+        let mut stream = SegmentStream {
+            source,
+            track: StreamingTrack::new(window, behind),
+            ended: false,
+        };
+        stream.fill();
+        stream
+    }
+
+    fn fill(&mut self) {
+        while !self.ended && self.track.free() > 0 {
+            match self.source.segment(self.track.pushed) {
+                Some(seg) => {
+                    self.track.push(seg);
+                }
+                None => self.ended = true,
+            }
+        }
+    }
+
+    // Drops the segments that are more than the kept length behind t, which
+    // is relative to the start of the window, and pulls in new segments in
+    // their place. Returns how much the start of the window moved; subtract
+    // it from positions relative to the window.
+    pub fn update(&mut self, t: i32) -> i32 {
+        let shift = self.track.retire(t);
+        self.fill();
+        shift
+    }
+
+    // The segments currently in the window, starting from t = 0.
+    pub fn segments(&self) -> &[Segment] {
+        self.track.segments()
+    }
+
+    // Distance of the start of the window from the start of the road, see
+    // RoadRenderer::set_t_origin(). Wraps around on very long roads.
+    pub fn origin(&self) -> i32 {
+        self.track.origin()
+    }
+
+    // Index of the first segment of the window in the source.
+    pub fn first_index(&self) -> u32 {
+        self.track.first_index()
+    }
+
+    pub fn source(&mut self) -> &mut S {
+        &mut self.source
+    }
+}
+
+// A window of a longer road that moves along with the camera, see
+// RoadRenderer::set_segments_window().
+pub trait SegmentWindow {
+    // The segments currently in the window, starting from t = 0.
+    fn segments(&self) -> &[Segment];
+    // Distance of the start of the window from the start of the road.
+    fn origin(&self) -> i32;
+    // Moves the window for a camera at t, relative to its start. Returns how
+    // much the start of the window moved.
+    fn update(&mut self, t: i32) -> i32;
+}
+
+impl<S: SegmentSource> SegmentWindow for SegmentStream<'_, S> {
+    fn segments(&self) -> &[Segment] {
+        SegmentStream::segments(self)
+    }
+
+    fn origin(&self) -> i32 {
+        SegmentStream::origin(self)
+    }
+
+    fn update(&mut self, t: i32) -> i32 {
+        SegmentStream::update(self, t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RoadRenderer, SideInclination::Flat, FP_POS};
+
+    // Segments of different lengths, so that each can be told apart.
+    fn road_segment(index: u32) -> Option<Segment> {
+        let length = ((index % 7 + 1) as i32) << FP_POS;
+        Some(Segment::new((Flat, Flat), length, index as i32 % 3 - 1, 0))
+    }
+
+    struct Road;
+
+    impl SegmentSource for Road {
+        fn segment(&mut self, index: u32) -> Option<Segment> {
+            road_segment(index)
+        }
+    }
+
+    // Start of the segment with the given index.
+    fn road_start(index: u32) -> i32 {
+        (0..index).map(|i| road_segment(i).unwrap().length).sum()
+    }
+
+    #[test]
+    fn renderer_follows_window() {
+        let mut window = [Segment::new((Flat, Flat), 0, 0, 0); 8];
+        let mut stream = SegmentStream::new(Road, &mut window, 2 << FP_POS);
+        let mut road = RoadRenderer::new(&[], 32);
+        road.set_segments_window(&mut stream);
+
+        let step = 3 << (FP_POS - 2);
+        let mut t = 0;
+        for _ in 0..1000 {
+            road.advance(step);
+            t += step;
+            road.update_window();
+            let camera = road.camera_state();
+            assert_eq!(camera.t + camera.t_origin, t);
+            assert!(road.segments().len() <= 8);
+            // The segment under the camera is the same as on the whole road.
+            let index = (0..).find(|&i| road_start(i + 1) > t).unwrap();
+            let mut start = 0;
+            let seg = road.segments().iter().find(|seg| {
+                start += seg.length;
+                start > camera.t
+            });
+            assert_eq!(camera.t - (start - seg.unwrap().length), t - road_start(index));
+            assert_eq!(seg.copied(), road_segment(index));
+        }
+        assert!(t > 200 << FP_POS);
+    }
+
+    #[test]
+    fn update_window_keeps_settings() {
+        let mut window = [Segment::new((Flat, Flat), 0, 0, 0); 8];
+        let mut stream = SegmentStream::new(Road, &mut window, 0);
+        let mut road = RoadRenderer::new(&[], 32);
+        road.set_look_offset(3, -2);
+        road.set_segments_window(&mut stream);
+        road.advance(20 << FP_POS);
+        assert!(road.update_window() > 0);
+        assert_eq!(road.camera_state().look, (3, -2));
+        assert_eq!(road.camera_state().speed, 20 << FP_POS);
+    }
+}
@@ -0,0 +1,123 @@
+// Generates extreme tracks that the renderer still handles, for stress
+// testing it and painters that might assume gentle geometry. Randomness comes
+// from noise::Rng, so a seed always gives the same track.
+use crate::noise::Rng;
+use crate::track::{macro_segment, MACRO_MAX_CURVE, MACRO_MAX_LENGTH};
+use crate::{Segment, SideInclination, FP_POS};
+
+// Limit of curve * length (in whole units) used here, i.e. how much the
+// heading may change over a segment. track!() only limits each segment on
+// its own, but past a turn of about 4096 at the default FP_POS the road after
+// a sharp curve heads so far sideways that the renderer's fixed-point math
+// overflows, so this leaves some margin. Curves are in FP_POS units, so the
+// limit halves with every extra bit.
+const STRESS_MAX_TURN: i32 = 1 << (18 - FP_POS);
+// Steeper crests and dips overflow the projection of hills in longer
+// stretches of them. 64 at the default FP_POS.
+const STRESS_MAX_GRADE: i32 = if 14 - FP_POS > 7 { MACRO_MAX_CURVE } else { 1 << (14 - FP_POS) };
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StressKind {
+    // Curves at the largest curvature, changing direction so that the road
+    // stays within range.
+    MaxCurve,
+    // Crests and dips at the largest y_curve the renderer handles, like
+    // MaxCurve.
+    MaxGrade,
+    // Segments of the minimum length with the largest curves and grades.
+    ShortSegments,
+    // Every combination of side styles, changing every segment, on gentle
+    // S-bends. Doesn't depend on the seed.
+    AlternatingStyles,
+    // A random kind for every four segments, so that the curves of MaxCurve
+    // and MaxGrade still cancel out.
+    Mixed,
+}
+
+// The kind of a single segment, StressKind without Mixed.
+#[derive(Copy, Clone)]
+enum SegmentKind {
+    MaxCurve,
+    MaxGrade,
+    ShortSegments,
+    AlternatingStyles,
+}
+
+const SEGMENT_KINDS: [SegmentKind; 4] = [
+    SegmentKind::MaxCurve,
+    SegmentKind::MaxGrade,
+    SegmentKind::ShortSegments,
+    SegmentKind::AlternatingStyles,
+];
+
+const STYLES: [SideInclination; 4] = [
+    SideInclination::Uphill,
+    SideInclination::Flat,
+    SideInclination::Downhill,
+    SideInclination::Wall,
+];
+
+// The longest segment that can have the given curve, in whole units.
+fn max_length(curve: i32) -> i32 {
+    (STRESS_MAX_TURN / curve.abs()).min(MACRO_MAX_LENGTH)
+}
+
+// Signs of curves in the pattern +, -, -, +, which returns to the original
+// heading and lateral position every four segments of equal length and
+// curvature.
+fn sign(i: usize) -> i32 {
+    if (i + 1) & 2 == 0 { 1 } else { -1 }
+}
+
+fn random_style(rng: &mut Rng) -> (SideInclination, SideInclination) {
+    (STYLES[rng.range(0, 4) as usize], STYLES[rng.range(0, 4) as usize])
+}
+
+fn stress_segment(kind: SegmentKind, i: usize, rng: &mut Rng) -> Segment {
+    let max = MACRO_MAX_CURVE;
+    match kind {
+        SegmentKind::MaxCurve => {
+            let style = random_style(rng);
+            macro_segment(style.0, style.1, max_length(max), sign(i) * max, 0)
+        }
+        SegmentKind::MaxGrade => {
+            let style = random_style(rng);
+            let grade = STRESS_MAX_GRADE;
+            macro_segment(style.0, style.1, max_length(grade), 0, sign(i) * grade)
+        }
+        SegmentKind::ShortSegments => {
+            let style = random_style(rng);
+            macro_segment(style.0, style.1, 1, sign(i) * max, sign(i) * STRESS_MAX_GRADE)
+        }
+        SegmentKind::AlternatingStyles => {
+            let combination = i % (STYLES.len() * STYLES.len());
+            macro_segment(
+                STYLES[combination / STYLES.len()],
+                STYLES[combination % STYLES.len()],
+                50,
+                sign(i) * 16,
+                sign(i) * 2,
+            )
+        }
+    }
+}
+
+// Fills out with a track of the given kind and returns it. Every segment
+// passes the checks of track!() and validate_segments(), though very long
+// tracks can still exceed the total length an i32 holds.
+pub fn stress_track(kind: StressKind, seed: u32, out: &mut [Segment]) -> &[Segment] {
+    let mut rng = Rng::new(seed);
+    let mut segment_kind = SegmentKind::MaxCurve;
+    for (i, segment) in out.iter_mut().enumerate() {
+        segment_kind = match kind {
+            StressKind::MaxCurve => SegmentKind::MaxCurve,
+            StressKind::MaxGrade => SegmentKind::MaxGrade,
+            StressKind::ShortSegments => SegmentKind::ShortSegments,
+            StressKind::AlternatingStyles => SegmentKind::AlternatingStyles,
+            StressKind::Mixed if i % 4 == 0 => SEGMENT_KINDS[rng.range(0, 4) as usize],
+            StressKind::Mixed => segment_kind,
+        };
+        *segment = stress_segment(segment_kind, i, &mut rng);
+    }
+    out
+}
@@ -0,0 +1,48 @@
+// Top-down SVG export of tracks, for inspecting tracks without running the
+// renderer.
+use crate::{icos, isin, RoadRenderer, FP_POS};
+use std::io::{self, Write};
+
+fn write_path<W: Write>(
+    road: &RoadRenderer,
+    step: i32,
+    offset: i32, // FP1, positive is right
+    color: &str,
+    out: &mut W,
+) -> io::Result<()> {
+    write!(out, "<path fill=\"none\" stroke=\"{}\" stroke-width=\"1\" vector-effect=\"non-scaling-stroke\" d=\"", color)?;
+    for (i, p) in road.centerline(step).enumerate() {
+        let x = p.x + ((offset * (icos(p.heading) >> FP_POS)) >> FP_POS);
+        let z = p.z - ((offset * (isin(p.heading) >> FP_POS)) >> FP_POS);
+        // SVG Y points down, so world Z is flipped to keep +z up.
+        write!(out, "{}{},{} ", if i == 0 { "M" } else { "L" }, x, -z)?;
+    }
+    writeln!(out, "\"/>")
+}
+
+// Writes the centerline and road edges of the road as an SVG image. step is
+// the distance between sampled points along the road, road_width is FP2 like
+// in Painter::road_width(). The coordinates are FP1 world units.
+pub fn write_svg<W: Write>(road: &RoadRenderer, step: i32, road_width: i32, out: &mut W) -> io::Result<()> {
+    let half_width = road_width >> FP_POS;
+    let mut min = (0, 0);
+    let mut max = (0, 0);
+    for p in road.centerline(step) {
+        min = (min.0.min(p.x), min.1.min(-p.z));
+        max = (max.0.max(p.x), max.1.max(-p.z));
+    }
+    let margin = half_width * 2;
+
+    writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">",
+        min.0 - margin,
+        min.1 - margin,
+        max.0 - min.0 + 2 * margin,
+        max.1 - min.1 + 2 * margin
+    )?;
+    write_path(road, step, -half_width, "gray", out)?;
+    write_path(road, step, half_width, "gray", out)?;
+    write_path(road, step, 0, "black", out)?;
+    writeln!(out, "</svg>")
+}
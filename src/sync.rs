@@ -0,0 +1,86 @@
+// Compact messages for keeping multiplayer games in sync over serial links or
+// UDP. The simulation is integer-only, so peers that agree on these values
+// render and simulate identically.
+//
+// A message is the number of cars as a byte, followed by zigzag LEB128
+// varints: the camera's t, x_offset and y_offset, then t and x of each car.
+// Car distances are stored relative to the camera's t, so nearby cars only
+// take a couple of bytes each.
+
+use crate::varint;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SyncError {
+    // The output buffer is too small for the message.
+    BufferTooSmall,
+    // The message ends early.
+    Truncated,
+    // The message is malformed.
+    Corrupt,
+    // There are more cars than fit in a message (255) or in the output.
+    TooManyCars,
+}
+
+// The camera, as given to RoadRenderer::set() and render().
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ViewState {
+    pub t: i32,        // Distance from the start of the road
+    pub x_offset: i32, // FP1
+    pub y_offset: i32, // FP1
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CarState {
+    pub t: i32, // Distance from the start of the road
+    pub x: i32, // Offset from the road center, FP1
+}
+
+// Writes a message, returns its size in bytes.
+pub fn encode(view: &ViewState, cars: &[CarState], out: &mut [u8]) -> Result<usize, SyncError> {
+    if cars.len() > u8::MAX as usize {
+        return Err(SyncError::TooManyCars);
+    }
+    *out.first_mut().ok_or(SyncError::BufferTooSmall)? = cars.len() as u8;
+    let mut pos = 1;
+    let mut ok = varint::write(out, &mut pos, view.t)
+        && varint::write(out, &mut pos, view.x_offset)
+        && varint::write(out, &mut pos, view.y_offset);
+    for car in cars {
+        ok = ok
+            && varint::write(out, &mut pos, car.t.wrapping_sub(view.t))
+            && varint::write(out, &mut pos, car.x);
+    }
+    if ok {
+        Ok(pos)
+    } else {
+        Err(SyncError::BufferTooSmall)
+    }
+}
+
+// Reads a message written by encode(). The cars are written to the start of
+// cars. Returns the camera, the cars and the size of the message in bytes.
+pub fn decode<'c>(bytes: &[u8], cars: &'c mut [CarState]) -> Result<(ViewState, &'c [CarState], usize), SyncError> {
+    let read = |pos: &mut usize| {
+        varint::read(bytes, pos).map_err(|e| match e {
+            varint::Error::Truncated => SyncError::Truncated,
+            varint::Error::Overlong => SyncError::Corrupt,
+        })
+    };
+    let count = *bytes.first().ok_or(SyncError::Truncated)? as usize;
+    if count > cars.len() {
+        return Err(SyncError::TooManyCars);
+    }
+    let mut pos = 1;
+    let view = ViewState {
+        t: read(&mut pos)?,
+        x_offset: read(&mut pos)?,
+        y_offset: read(&mut pos)?,
+    };
+    for car in cars[..count].iter_mut() {
+        car.t = read(&mut pos)?.wrapping_add(view.t);
+        car.x = read(&mut pos)?;
+    }
+    Ok((view, &cars[..count], pos))
+}
@@ -0,0 +1,134 @@
+// Live preview in a terminal with crossterm, for looking at tracks without a
+// graphics backend, e.g. over SSH. Each character cell shows two pixels with
+// the upper half block '▀': the foreground color is the upper pixel and the
+// background the lower one. Colors are xterm 256-color palette indices.
+use crate::{markings, PixelSink, RoadStyle, FP_POS};
+use crossterm::cursor::MoveTo;
+use crossterm::queue;
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use std::io::{self, Write};
+use std::vec;
+use std::vec::Vec;
+
+// A PixelSink of palette indices that can be shown in a terminal. The height
+// is rounded up to whole character rows.
+pub struct HalfBlockSink {
+    pixels: Vec<u8>,
+    w: i32,
+    h: i32,
+}
+
+impl HalfBlockSink {
+    pub fn new(w: i32, h: i32) -> Self {
+        let h = (h.max(0) + 1) & !1;
+        let w = w.max(0);
+        HalfBlockSink { pixels: vec![0; (w * h) as usize], w, h }
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    // Draws the frame with its top left corner at the given character cell.
+    // Colors are only sent when they change, which matters over slow links.
+    pub fn present<W: Write>(&self, out: &mut W, column: u16, row: u16) -> io::Result<()> {
+        let w = self.w as usize;
+        for (i, rows) in self.pixels.chunks(2 * w).enumerate() {
+            queue!(out, MoveTo(column, row + i as u16))?;
+            let mut colors = None;
+            for x in 0..w {
+                let cell = (rows[x], rows[w + x]);
+                if colors != Some(cell) {
+                    queue!(
+                        out,
+                        SetForegroundColor(Color::AnsiValue(cell.0)),
+                        SetBackgroundColor(Color::AnsiValue(cell.1))
+                    )?;
+                    colors = Some(cell);
+                }
+                queue!(out, Print('▀'))?;
+            }
+        }
+        queue!(out, ResetColor)?;
+        out.flush()
+    }
+}
+
+impl PixelSink for HalfBlockSink {
+    type ColorType = u8;
+
+    fn draw(&mut self, x: i32, y: i32, color: &u8) {
+        if x >= 0 && x < self.w && y >= 0 && y < self.h {
+            self.pixels[(y * self.w + x) as usize] = *color;
+        }
+    }
+
+    fn fill_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &u8) {
+        let (begin, end) = (x_begin.max(0), x_end.min(self.w));
+        if y >= 0 && y < self.h && begin < end {
+            let row = (y * self.w) as usize;
+            self.pixels[row + begin as usize..row + end as usize].fill(*color);
+        }
+    }
+
+    fn copy_row(&mut self, y: i32, x_begin: i32, x_end: i32, src_y: i32, x_shift: i32) -> bool {
+        let (w, h) = (self.w, self.h);
+        if y >= 0 && y < h && src_y >= 0 && src_y < h {
+            for x in x_begin.max(0)..x_end.min(w) {
+                let sx = x + x_shift;
+                if sx >= 0 && sx < w {
+                    self.pixels[(y * w + x) as usize] = self.pixels[(src_y * w + sx) as usize];
+                }
+            }
+        }
+        true
+    }
+}
+
+// A RoadStyle in xterm 256-color indices: a blue sky getting lighter toward
+// the horizon, a gray road with red and white rumble strips and a dashed
+// center line, and green ground in alternating bands.
+#[derive(Copy, Clone, Debug)]
+pub struct TerminalStyle {
+    pub road_width: i32, // FP2
+    pub height: i32,     // Screen height in pixels, for the sky gradient
+}
+
+impl TerminalStyle {
+    pub fn new(height: i32) -> Self {
+        TerminalStyle { road_width: 50 << (2 * FP_POS), height }
+    }
+}
+
+impl RoadStyle for TerminalStyle {
+    type ColorType = u8;
+
+    fn sky_color(&self, y: i32, _frame: i32) -> u8 {
+        const SKY: [u8; 4] = [18, 19, 25, 31];
+        SKY[(y * SKY.len() as i32 / self.height.max(1)).clamp(0, SKY.len() as i32 - 1) as usize]
+    }
+
+    fn road_color(&self, tx: i32, t: i32, _frame: i32) -> u8 {
+        let atx = tx.abs();
+        let line = 2 << (2 * FP_POS);
+        if atx >= self.road_width - 2 * line {
+            if (t & 0x1FFF) < 0x1000 { 196 } else { 231 }
+        } else if atx < line && markings::is_dash(t, 0x1000, 0x800) {
+            231
+        } else {
+            240
+        }
+    }
+
+    fn ground_color(&self, _tx: i32, t: i32, _frame: i32) -> u8 {
+        if (t & 0x3FFF) < 0x2000 { 28 } else { 34 }
+    }
+
+    fn wall_color(&self, _t: i32, height: i32, _frame: i32) -> u8 {
+        if (height >> FP_POS) & 8 == 0 { 94 } else { 130 }
+    }
+
+    fn road_width(&self) -> i32 {
+        self.road_width
+    }
+}
@@ -0,0 +1,60 @@
+// Slow motion and fast forward. The game ticks a Timescale once per real
+// frame and gives the scaled values to RoadRenderer::advance(), render() and
+// the animations (palette::ColorCycle, objects::RoadsideObject), so that they
+// all stay in step. Everything is integer math, so replays remain
+// deterministic at any scale.
+
+use crate::FP_POS;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Timescale {
+    scale: i32, // Game frames per real frame, FP1
+    prev: i64,  // Game time before the last tick, FP1
+    time: i64,  // Game time, FP1
+}
+
+impl Default for Timescale {
+    fn default() -> Self {
+        Timescale::new()
+    }
+}
+
+impl Timescale {
+    pub fn new() -> Self {
+        Timescale {
+            scale: 1 << FP_POS,
+            prev: 0,
+            time: 0,
+        }
+    }
+
+    // Sets how many game frames pass per real frame, FP1. 1 << FP_POS is
+    // normal speed, 0 pauses.
+    pub fn set_scale(&mut self, scale: i32) {
+        self.scale = scale;
+    }
+
+    pub fn scale(&self) -> i32 {
+        self.scale
+    }
+
+    // Moves on by one real frame.
+    pub fn tick(&mut self) {
+        self.prev = self.time;
+        self.time += self.scale as i64;
+    }
+
+    // The game frame counter, to be given to render() and the animations,
+    // and used for lap times.
+    pub fn frame(&self) -> i32 {
+        (self.time >> FP_POS) as i32
+    }
+
+    // Scales a per-frame amount, e.g. the step given to advance(), to the
+    // last tick. The fractions are carried over between ticks, so slow
+    // motion moves smoothly instead of stopping for small amounts.
+    pub fn step(&self, per_frame: i32) -> i32 {
+        let per_frame = per_frame as i64;
+        (((per_frame * self.time) >> FP_POS) - ((per_frame * self.prev) >> FP_POS)) as i32
+    }
+}
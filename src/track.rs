@@ -0,0 +1,1311 @@
+// Binary track format. The format is designed so that a track in read-only
+// memory (e.g. flash, through include_track!) can be used as the segment
+// table directly, without copying it into RAM.
+//
+// A track file is a container of chunks. Readers skip chunks they don't know,
+// so new chunk types can be added without breaking older readers. All values
+// are in the byte order of the target.
+//
+// offset  size  contents
+// 0       4     magic, "PJTR"
+// 4       2     byte order mark, 0xFEFF
+// 6       2     version, major in the high byte and minor in the low byte
+// 8       4     number of chunks
+// 12      4     CRC-32 of the chunks, zero before version 2.1
+// 16      ...   chunks
+//
+// Each chunk is a 4-byte id, a u32 payload size and the payload, padded with
+// zeroes to a multiple of 4 bytes. Readers accept any minor version of their
+// major version; the minor version only grows when chunk types, header fields
+// or side styles are added. Version 2.2 added SideInclination::Wall, 2.3 the
+// precision of the segments.
+//
+// The CRC-32 (the common IEEE 802.3 one) covers every byte from the end of the
+// header to the end of the last chunk, including padding. Readers check it
+// unless the version is 2.0 and the CRC is zero, so tracks damaged on the way
+// over a serial link or OTA update are rejected instead of rendering as
+// garbage.
+//
+// The segments chunk (CHUNK_SEGMENTS) contains a u32 segment count, a u32
+// encoding (ENCODING_RAW or ENCODING_COMPRESSED) and the segments. The high
+// byte of the encoding is the FP_POS the segments were written with, or zero
+// for the default of 8, which is also what tracks before version 2.3 use.
+// Tracks of another precision than the reader's are rejected.
+//
+// With ENCODING_RAW, the segments are 16*n bytes in the memory layout of
+// Segment:
+//   u8 left side style, u8 right side style, 2 bytes of padding,
+//   i32 length, i32 x_curve, i32 y_curve
+// The whole buffer must be aligned to at least 4 bytes.
+//
+// With ENCODING_COMPRESSED, each segment starts with a tag byte:
+//   bits 0-1  left side style
+//   bits 2-3  right side style
+//   bit 4     length follows
+//   bit 5     x_curve follows
+//   bit 6     y_curve follows
+//   bit 7     run length follows
+// Fields that follow are zigzag LEB128 varints of the difference to the
+// previous segment, in the order length, x_curve, y_curve, run length. Fields
+// that don't follow are the same as in the previous segment. A run length of n
+// repeats the segment n more times. The first segment is compared to an
+// all-zero segment. Compressed tracks have no alignment requirements, but must
+// be decoded before rendering.
+use crate::stream::SegmentSource;
+use crate::{varint, Segment, SideInclination, FP_POS};
+use core::mem::{align_of, offset_of, size_of};
+
+pub const MAGIC: [u8; 4] = *b"PJTR";
+pub const VERSION_MAJOR: u8 = 2;
+pub const VERSION_MINOR: u8 = 3;
+pub const VERSION: u16 = ((VERSION_MAJOR as u16) << 8) | VERSION_MINOR as u16;
+pub const HEADER_SIZE: usize = 16;
+pub const CHUNK_HEADER_SIZE: usize = 8;
+pub const SEGMENT_SIZE: usize = 16;
+pub const ENCODING_RAW: u32 = 0;
+pub const ENCODING_COMPRESSED: u32 = 1;
+
+// Known chunk types.
+pub const CHUNK_SEGMENTS: [u8; 4] = *b"SEGS";
+pub const CHUNK_OBJECTS: [u8; 4] = *b"OBJS";
+pub const CHUNK_DECALS: [u8; 4] = *b"DECL";
+pub const CHUNK_METADATA: [u8; 4] = *b"META";
+
+const BYTE_ORDER_MARK: u16 = 0xFEFF;
+const CHECKSUM_MINOR: u8 = 1;
+const SEGMENTS_HEADER_SIZE: usize = 8;
+const DEFAULT_FP_POS: u32 = 8;
+
+// FP_POS as stored in the high byte of the encoding.
+const fn precision_bits() -> u32 {
+    if FP_POS as u32 == DEFAULT_FP_POS {
+        0
+    } else {
+        (FP_POS as u32) << 24
+    }
+}
+
+// Segments are reinterpreted in-place, so the in-memory layout must match the
+// format exactly. If it somehow doesn't, this fails to compile instead of
+// misreading tracks.
+const _: () = assert!(
+    size_of::<Segment>() == SEGMENT_SIZE
+        && align_of::<Segment>() <= 4
+        && offset_of!(Segment, side_style.0) == 0
+        && offset_of!(Segment, side_style.1) == 1
+        && offset_of!(Segment, length) == 4
+        && offset_of!(Segment, x_curve) == 8
+        && offset_of!(Segment, y_curve) == 12
+);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TrackError {
+    // The data doesn't start with MAGIC.
+    BadMagic,
+    // The track was written for a target with the opposite byte order.
+    WrongEndianness,
+    // The track was written with an incompatible major version of the format.
+    UnsupportedVersion { found: u16, supported: u16 },
+    // The data is not aligned well enough to be used in-place.
+    Misaligned,
+    // The data ends before everything declared in the headers.
+    Truncated,
+    // The container has no chunk of the given type.
+    MissingChunk([u8; 4]),
+    // The segment at the given index has an unknown side style.
+    InvalidSideStyle(usize),
+    // The output buffer is too small for the encoded track.
+    BufferTooSmall,
+    // The track is compressed and can't be used in-place.
+    Compressed,
+    // The track uses an unknown encoding.
+    UnsupportedEncoding(u32),
+    // Compressed data is malformed.
+    Corrupt,
+    // A patch refers to segments or objects past the end of their list.
+    PatchOutOfRange,
+    // The chunks don't match the checksum in the header.
+    BadChecksum { stored: u32, computed: u32 },
+    // The segments were written with another FP_POS than the reader's.
+    WrongPrecision { found: u8, supported: u8 },
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_ne_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_ne_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+const fn padded(size: usize) -> usize {
+    (size + 3) & !3
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+// CRC-32 as used in the header. Can be continued over several buffers by
+// passing the previous result as crc, start with 0.
+pub fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &b in bytes {
+        crc = CRC_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+// A chunk of a track container.
+#[derive(Copy, Clone)]
+pub struct Chunk<'a> {
+    pub id: [u8; 4],
+    pub payload: &'a [u8],
+}
+
+// A validated track container, see open().
+#[derive(Copy, Clone)]
+pub struct Container<'a> {
+    data: &'a [u8],
+    version: u16,
+    chunk_count: usize,
+}
+
+// Validates the header of a track container.
+pub fn open(bytes: &[u8]) -> Result<Container<'_>, TrackError> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(TrackError::Truncated);
+    }
+    if bytes[0..4] != MAGIC {
+        return Err(TrackError::BadMagic);
+    }
+    let bom = read_u16(bytes, 4);
+    if bom == BYTE_ORDER_MARK.swap_bytes() {
+        return Err(TrackError::WrongEndianness);
+    } else if bom != BYTE_ORDER_MARK {
+        return Err(TrackError::BadMagic);
+    }
+    let version = read_u16(bytes, 6);
+    if (version >> 8) as u8 != VERSION_MAJOR {
+        return Err(TrackError::UnsupportedVersion {
+            found: version,
+            supported: VERSION,
+        });
+    }
+    let container = Container {
+        data: bytes,
+        version,
+        chunk_count: read_u32(bytes, 8) as usize,
+    };
+    // Version 2.0 had no CRC and wrote zero there. Anything else is checked,
+    // so that damage to the version or the CRC can't turn the check off.
+    if version as u8 >= CHECKSUM_MINOR || read_u32(bytes, 12) != 0 {
+        let mut end = HEADER_SIZE;
+        for chunk in container.chunks() {
+            let chunk = chunk?;
+            let start = chunk.payload.as_ptr() as usize - bytes.as_ptr() as usize;
+            end = (start + padded(chunk.payload.len())).min(bytes.len());
+        }
+        let stored = read_u32(bytes, 12);
+        let computed = crc32(0, &bytes[HEADER_SIZE..end]);
+        if stored != computed {
+            return Err(TrackError::BadChecksum { stored, computed });
+        }
+    }
+    Ok(container)
+}
+
+impl<'a> Container<'a> {
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    // Iterates over all chunks, including unknown ones.
+    pub fn chunks(&self) -> Chunks<'a> {
+        Chunks {
+            data: self.data,
+            pos: HEADER_SIZE,
+            remaining: self.chunk_count,
+        }
+    }
+
+    // Returns the payload of the first chunk with the given id.
+    pub fn chunk(&self, id: [u8; 4]) -> Result<&'a [u8], TrackError> {
+        for chunk in self.chunks() {
+            let chunk = chunk?;
+            if chunk.id == id {
+                return Ok(chunk.payload);
+            }
+        }
+        Err(TrackError::MissingChunk(id))
+    }
+
+    // Returns the segments of the track.
+    pub fn track(&self) -> Result<TrackData<'a>, TrackError> {
+        let payload = self.chunk(CHUNK_SEGMENTS)?;
+        if payload.len() < SEGMENTS_HEADER_SIZE {
+            return Err(TrackError::Truncated);
+        }
+        let count = read_u32(payload, 0) as usize;
+        let data = &payload[SEGMENTS_HEADER_SIZE..];
+        let encoding = read_u32(payload, 4);
+        let precision = match encoding >> 24 {
+            0 => DEFAULT_FP_POS,
+            bits => bits,
+        };
+        if precision != FP_POS as u32 {
+            return Err(TrackError::WrongPrecision {
+                found: precision as u8,
+                supported: FP_POS as u8,
+            });
+        }
+        match encoding & 0x00FF_FFFF {
+            ENCODING_RAW => raw_segments(data, count).map(TrackData::Raw),
+            ENCODING_COMPRESSED => Ok(TrackData::Compressed(CompressedSegments::new(data, count))),
+            encoding => Err(TrackError::UnsupportedEncoding(encoding)),
+        }
+    }
+}
+
+pub struct Chunks<'a> {
+    data: &'a [u8],
+    pos: usize,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<Chunk<'a>, TrackError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        if self.data.len() < self.pos + CHUNK_HEADER_SIZE {
+            self.remaining = 0;
+            return Some(Err(TrackError::Truncated));
+        }
+        let mut id = [0; 4];
+        id.copy_from_slice(&self.data[self.pos..self.pos + 4]);
+        let size = read_u32(self.data, self.pos + 4) as usize;
+        let start = self.pos + CHUNK_HEADER_SIZE;
+        if self.data.len() - start < size {
+            self.remaining = 0;
+            return Some(Err(TrackError::Truncated));
+        }
+        self.pos = start + padded(size);
+        self.remaining -= 1;
+        Some(Ok(Chunk {
+            id,
+            payload: &self.data[start..start + size],
+        }))
+    }
+}
+
+fn raw_segments(data: &[u8], count: usize) -> Result<&[Segment], TrackError> {
+    if data.len() / SEGMENT_SIZE < count {
+        return Err(TrackError::Truncated);
+    }
+    if !(data.as_ptr() as usize).is_multiple_of(align_of::<Segment>()) {
+        return Err(TrackError::Misaligned);
+    }
+
+    // Every bit pattern is valid for the rest of the fields, but the side
+    // styles must be known enum values.
+    for i in 0..count {
+        let style = &data[i * SEGMENT_SIZE..i * SEGMENT_SIZE + 2];
+        if style[0] > SideInclination::Wall as u8 || style[1] > SideInclination::Wall as u8 {
+            return Err(TrackError::InvalidSideStyle(i));
+        }
+    }
+
+    // Safety: the size, alignment and field offsets of Segment are checked at
+    // compile time above, and the side styles were just validated. The
+    // returned slice borrows data, so it cannot outlive it.
+    Ok(unsafe { core::slice::from_raw_parts(data.as_ptr() as *const Segment, count) })
+}
+
+// Validates a binary track and returns its segments, borrowing the data. The
+// result can be given to RoadRenderer::new() directly. Compressed tracks are
+// rejected, use load() for those.
+pub fn segments_from_bytes(bytes: &[u8]) -> Result<&[Segment], TrackError> {
+    match load(bytes)? {
+        TrackData::Raw(segments) => Ok(segments),
+        TrackData::Compressed(_) => Err(TrackError::Compressed),
+    }
+}
+
+// Validates a binary track of either encoding.
+pub fn load(bytes: &[u8]) -> Result<TrackData<'_>, TrackError> {
+    open(bytes)?.track()
+}
+
+// A loaded binary track. Raw tracks are used in-place, compressed ones are
+// decoded on the fly.
+pub enum TrackData<'a> {
+    Raw(&'a [Segment]),
+    Compressed(CompressedSegments<'a>),
+}
+
+impl<'a> TrackData<'a> {
+    // Number of segments in the track.
+    pub fn len(&self) -> usize {
+        match self {
+            TrackData::Raw(segments) => segments.len(),
+            TrackData::Compressed(decoder) => decoder.remaining,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Returns the segments of the track, decoding them into out if needed.
+    pub fn segments<'b>(self, out: &'b mut [Segment]) -> Result<&'b [Segment], TrackError>
+    where
+        'a: 'b,
+    {
+        match self {
+            TrackData::Raw(segments) => Ok(segments),
+            TrackData::Compressed(decoder) => {
+                let count = decoder.remaining;
+                if out.len() < count {
+                    return Err(TrackError::BufferTooSmall);
+                }
+                for (slot, seg) in out.iter_mut().zip(decoder) {
+                    *slot = seg?;
+                }
+                Ok(&out[..count])
+            }
+        }
+    }
+}
+
+const TAG_LENGTH: u8 = 1 << 4;
+const TAG_X_CURVE: u8 = 1 << 5;
+const TAG_Y_CURVE: u8 = 1 << 6;
+const TAG_RUN: u8 = 1 << 7;
+
+fn side_from_bits(bits: u8) -> Option<SideInclination> {
+    match bits {
+        0 => Some(SideInclination::Uphill),
+        1 => Some(SideInclination::Flat),
+        2 => Some(SideInclination::Downhill),
+        3 => Some(SideInclination::Wall),
+        _ => None,
+    }
+}
+
+// Streaming decoder for compressed tracks, see load().
+pub struct CompressedSegments<'a> {
+    data: &'a [u8],
+    pos: usize,
+    prev: Segment,
+    run: u32,
+    remaining: usize,
+    index: u32, // Index of the next segment
+    count: usize,
+}
+
+impl<'a> CompressedSegments<'a> {
+    fn new(data: &'a [u8], count: usize) -> Self {
+        CompressedSegments {
+            data,
+            pos: 0,
+            prev: Segment::new((SideInclination::Uphill, SideInclination::Uphill), 0, 0, 0),
+            run: 0,
+            remaining: count,
+            index: 0,
+            count,
+        }
+    }
+
+    fn read_varint(&mut self) -> Result<i32, TrackError> {
+        varint::read(self.data, &mut self.pos).map_err(|e| match e {
+            varint::Error::Truncated => TrackError::Truncated,
+            varint::Error::Overlong => TrackError::Corrupt,
+        })
+    }
+
+    fn read_segment(&mut self) -> Result<Segment, TrackError> {
+        if self.run > 0 {
+            self.run -= 1;
+            return Ok(self.prev);
+        }
+        let tag = *self.data.get(self.pos).ok_or(TrackError::Truncated)?;
+        self.pos += 1;
+        let left = side_from_bits(tag & 3).ok_or(TrackError::Corrupt)?;
+        let right = side_from_bits((tag >> 2) & 3).ok_or(TrackError::Corrupt)?;
+        let mut seg = self.prev;
+        seg.side_style = (left, right);
+        if tag & TAG_LENGTH != 0 {
+            seg.length = seg.length.wrapping_add(self.read_varint()?);
+        }
+        if tag & TAG_X_CURVE != 0 {
+            seg.x_curve = seg.x_curve.wrapping_add(self.read_varint()?);
+        }
+        if tag & TAG_Y_CURVE != 0 {
+            seg.y_curve = seg.y_curve.wrapping_add(self.read_varint()?);
+        }
+        if tag & TAG_RUN != 0 {
+            self.run = self.read_varint()? as u32;
+        }
+        self.prev = seg;
+        Ok(seg)
+    }
+}
+
+// Segments read through a callback, for tracks in memory that can't be read
+// through a slice, like AVR program memory or an SPI flash chip. The records
+// are laid out like an array of Segment in memory, e.g. a static array placed
+// in program memory. read(offset, buf) fills buf from offset bytes into the
+// array. Give it to a stream::SegmentStream to render from.
+pub struct RecordSource<F: FnMut(usize, &mut [u8])> {
+    read: F,
+    count: u32,
+}
+
+impl<F: FnMut(usize, &mut [u8])> RecordSource<F> {
+    pub fn new(read: F, count: u32) -> Self {
+        RecordSource { read, count }
+    }
+}
+
+// The road ends early at a record with an unknown side style.
+impl<F: FnMut(usize, &mut [u8])> SegmentSource for RecordSource<F> {
+    fn segment(&mut self, index: u32) -> Option<Segment> {
+        if index >= self.count {
+            return None;
+        }
+        let mut record = [0; SEGMENT_SIZE];
+        (self.read)(index as usize * SEGMENT_SIZE, &mut record);
+        let field = |at: usize| i32::from_ne_bytes([record[at], record[at + 1], record[at + 2], record[at + 3]]);
+        Some(Segment::new(
+            (side_from_bits(record[0])?, side_from_bits(record[1])?),
+            field(4),
+            field(8),
+            field(12),
+        ))
+    }
+}
+
+// Decompresses the track only as far as a stream::SegmentStream has pulled it
+// in, so a long compressed track never has to fit in RAM. The road ends at
+// the first corrupt segment. Going back to an earlier segment decodes the
+// track again from the start.
+impl<'a> SegmentSource for CompressedSegments<'a> {
+    fn segment(&mut self, index: u32) -> Option<Segment> {
+        if index < self.index {
+            *self = CompressedSegments::new(self.data, self.count);
+        }
+        while self.index < index {
+            self.next()?.ok()?;
+        }
+        self.next()?.ok()
+    }
+}
+
+impl<'a> Iterator for CompressedSegments<'a> {
+    type Item = Result<Segment, TrackError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let result = self.read_segment();
+        // Stop after the first error, the rest of the data is meaningless.
+        self.remaining = if result.is_ok() { self.remaining - 1 } else { 0 };
+        self.index += 1;
+        Some(result)
+    }
+}
+
+fn write_varint(out: &mut [u8], pos: &mut usize, value: i32) -> Result<(), TrackError> {
+    if varint::write(out, pos, value) {
+        Ok(())
+    } else {
+        Err(TrackError::BufferTooSmall)
+    }
+}
+
+fn write_raw(segments: &[Segment], out: &mut [u8]) -> Result<usize, TrackError> {
+    let size = segments.len() * SEGMENT_SIZE;
+    if out.len() < size {
+        return Err(TrackError::BufferTooSmall);
+    }
+    for (seg, record) in segments.iter().zip(out[..size].chunks_exact_mut(SEGMENT_SIZE)) {
+        record[0] = seg.side_style.0 as u8;
+        record[1] = seg.side_style.1 as u8;
+        record[2..4].copy_from_slice(&[0; 2]);
+        record[4..8].copy_from_slice(&seg.length.to_ne_bytes());
+        record[8..12].copy_from_slice(&seg.x_curve.to_ne_bytes());
+        record[12..16].copy_from_slice(&seg.y_curve.to_ne_bytes());
+    }
+    Ok(size)
+}
+
+fn write_compressed(segments: &[Segment], out: &mut [u8]) -> Result<usize, TrackError> {
+    let mut pos = 0;
+    let mut prev = Segment::new((SideInclination::Uphill, SideInclination::Uphill), 0, 0, 0);
+    let mut i = 0;
+    while i < segments.len() {
+        let seg = segments[i];
+        let mut run = 0;
+        while i + 1 + run < segments.len() && segments[i + 1 + run] == seg {
+            run += 1;
+        }
+
+        let mut tag = seg.side_style.0 as u8 | ((seg.side_style.1 as u8) << 2);
+        if seg.length != prev.length {
+            tag |= TAG_LENGTH;
+        }
+        if seg.x_curve != prev.x_curve {
+            tag |= TAG_X_CURVE;
+        }
+        if seg.y_curve != prev.y_curve {
+            tag |= TAG_Y_CURVE;
+        }
+        if run > 0 {
+            tag |= TAG_RUN;
+        }
+        *out.get_mut(pos).ok_or(TrackError::BufferTooSmall)? = tag;
+        pos += 1;
+        if tag & TAG_LENGTH != 0 {
+            write_varint(out, &mut pos, seg.length.wrapping_sub(prev.length))?;
+        }
+        if tag & TAG_X_CURVE != 0 {
+            write_varint(out, &mut pos, seg.x_curve.wrapping_sub(prev.x_curve))?;
+        }
+        if tag & TAG_Y_CURVE != 0 {
+            write_varint(out, &mut pos, seg.y_curve.wrapping_sub(prev.y_curve))?;
+        }
+        if run > 0 {
+            write_varint(out, &mut pos, run as i32)?;
+        }
+        prev = seg;
+        i += 1 + run;
+    }
+    Ok(pos)
+}
+
+// Writes a track container into a buffer, chunk by chunk.
+pub struct ContainerWriter<'b> {
+    out: &'b mut [u8],
+    pos: usize,
+    chunk_count: u32,
+}
+
+impl<'b> ContainerWriter<'b> {
+    pub fn new(out: &'b mut [u8]) -> Result<Self, TrackError> {
+        if out.len() < HEADER_SIZE {
+            return Err(TrackError::BufferTooSmall);
+        }
+        out[0..4].copy_from_slice(&MAGIC);
+        out[4..6].copy_from_slice(&BYTE_ORDER_MARK.to_ne_bytes());
+        out[6..8].copy_from_slice(&VERSION.to_ne_bytes());
+        out[8..16].copy_from_slice(&[0; 8]);
+        Ok(ContainerWriter {
+            out,
+            pos: HEADER_SIZE,
+            chunk_count: 0,
+        })
+    }
+
+    // Adds a chunk whose payload is written by f, which returns the payload
+    // size.
+    fn write_chunk<F>(&mut self, id: [u8; 4], f: F) -> Result<(), TrackError>
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize, TrackError>,
+    {
+        let start = self.pos + CHUNK_HEADER_SIZE;
+        if self.out.len() < start {
+            return Err(TrackError::BufferTooSmall);
+        }
+        let size = f(&mut self.out[start..])?;
+        let end = start + padded(size);
+        if self.out.len() < end {
+            return Err(TrackError::BufferTooSmall);
+        }
+        self.out[self.pos..self.pos + 4].copy_from_slice(&id);
+        self.out[self.pos + 4..start].copy_from_slice(&(size as u32).to_ne_bytes());
+        for b in self.out[start + size..end].iter_mut() {
+            *b = 0;
+        }
+        self.pos = end;
+        self.chunk_count += 1;
+        Ok(())
+    }
+
+    // Adds a chunk with an arbitrary payload.
+    pub fn add_chunk(&mut self, id: [u8; 4], payload: &[u8]) -> Result<(), TrackError> {
+        self.write_chunk(id, |out| {
+            let dst = out.get_mut(..payload.len()).ok_or(TrackError::BufferTooSmall)?;
+            dst.copy_from_slice(payload);
+            Ok(payload.len())
+        })
+    }
+
+    // Adds the segments chunk.
+    pub fn add_segments(&mut self, segments: &[Segment], encoding: u32) -> Result<(), TrackError> {
+        self.write_chunk(CHUNK_SEGMENTS, |out| {
+            if out.len() < SEGMENTS_HEADER_SIZE {
+                return Err(TrackError::BufferTooSmall);
+            }
+            out[0..4].copy_from_slice(&(segments.len() as u32).to_ne_bytes());
+            out[4..8].copy_from_slice(&(encoding | precision_bits()).to_ne_bytes());
+            let data = &mut out[SEGMENTS_HEADER_SIZE..];
+            let size = match encoding {
+                ENCODING_RAW => write_raw(segments, data)?,
+                ENCODING_COMPRESSED => write_compressed(segments, data)?,
+                _ => return Err(TrackError::UnsupportedEncoding(encoding)),
+            };
+            Ok(SEGMENTS_HEADER_SIZE + size)
+        })
+    }
+
+    // Finishes the container, returns its size in bytes.
+    pub fn finish(self) -> usize {
+        let crc = crc32(0, &self.out[HEADER_SIZE..self.pos]);
+        self.out[8..12].copy_from_slice(&self.chunk_count.to_ne_bytes());
+        self.out[12..16].copy_from_slice(&crc.to_ne_bytes());
+        self.pos
+    }
+}
+
+// Returns the size of a raw binary track with the given number of segments
+// and no other chunks.
+pub const fn encoded_size(segment_count: usize) -> usize {
+    HEADER_SIZE + CHUNK_HEADER_SIZE + SEGMENTS_HEADER_SIZE + segment_count * SEGMENT_SIZE
+}
+
+// Writes segments as a raw binary track. Returns the number of bytes written.
+pub fn encode_segments(segments: &[Segment], out: &mut [u8]) -> Result<usize, TrackError> {
+    let mut writer = ContainerWriter::new(out)?;
+    writer.add_segments(segments, ENCODING_RAW)?;
+    Ok(writer.finish())
+}
+
+// Writes segments as a compressed binary track. Returns the number of bytes
+// written.
+pub fn encode_compressed(segments: &[Segment], out: &mut [u8]) -> Result<usize, TrackError> {
+    let mut writer = ContainerWriter::new(out)?;
+    writer.add_segments(segments, ENCODING_COMPRESSED)?;
+    Ok(writer.finish())
+}
+
+// An edit to a track stored in RAM, e.g. sent by a live track editor.
+pub enum TrackPatch<'p> {
+    // Replaces count segments starting from index with segments.
+    Replace {
+        index: usize,
+        count: usize,
+        segments: &'p [Segment],
+    },
+    // Inserts segments before index.
+    Insert { index: usize, segments: &'p [Segment] },
+    // Removes count segments starting from index.
+    Remove { index: usize, count: usize },
+}
+
+// Applies a patch to the first *len segments of storage, updating *len. The
+// rest of storage is room for growth. The renderer borrows the track, so take
+// its camera_state() before patching, then create it again and
+// restore_camera() to re-sync it with the patched track.
+pub fn apply_patch(storage: &mut [Segment], len: &mut usize, patch: &TrackPatch) -> Result<(), TrackError> {
+    let (index, count, segments): (usize, usize, &[Segment]) = match *patch {
+        TrackPatch::Replace { index, count, segments } => (index, count, segments),
+        TrackPatch::Insert { index, segments } => (index, 0, segments),
+        TrackPatch::Remove { index, count } => (index, count, &[]),
+    };
+    splice(storage, len, index, count, segments)
+}
+
+// An edit to one of the object lists that go with a track
+// (objects::RoadsideObject, markings::Decal, markings::Hazard), by index like
+// TrackPatch. Games find objects by t, so edits should keep the list sorted.
+pub enum PlacementPatch<'p, T> {
+    // Replaces count items starting from index with items.
+    Replace {
+        index: usize,
+        count: usize,
+        items: &'p [T],
+    },
+    // Inserts items before index.
+    Insert { index: usize, items: &'p [T] },
+    // Removes count items starting from index.
+    Remove { index: usize, count: usize },
+}
+
+// Applies a patch to the first *len items of storage like apply_patch().
+// Objects aren't borrowed by the renderer, so nothing needs to be re-synced.
+pub fn apply_placement_patch<T: Copy>(
+    storage: &mut [T],
+    len: &mut usize,
+    patch: &PlacementPatch<T>,
+) -> Result<(), TrackError> {
+    let (index, count, items): (usize, usize, &[T]) = match *patch {
+        PlacementPatch::Replace { index, count, items } => (index, count, items),
+        PlacementPatch::Insert { index, items } => (index, 0, items),
+        PlacementPatch::Remove { index, count } => (index, count, &[]),
+    };
+    splice(storage, len, index, count, items)
+}
+
+// Replaces count items of storage starting from index with items.
+fn splice<T: Copy>(storage: &mut [T], len: &mut usize, index: usize, count: usize, items: &[T]) -> Result<(), TrackError> {
+    if index > *len || count > *len - index {
+        return Err(TrackError::PatchOutOfRange);
+    }
+    let new_len = *len - count + items.len();
+    if new_len > storage.len() {
+        return Err(TrackError::BufferTooSmall);
+    }
+    // Move the tail of the list to its new place, then fill the gap.
+    storage.copy_within(index + count..*len, index + items.len());
+    storage[index..index + items.len()].copy_from_slice(items);
+    *len = new_len;
+    Ok(())
+}
+
+// Serializes segments with postcard, e.g. for sending them over a serial link.
+// Returns the used part of buf.
+#[cfg(feature = "postcard")]
+pub fn segments_to_postcard<'b>(segments: &[Segment], buf: &'b mut [u8]) -> Result<&'b mut [u8], postcard::Error> {
+    to_postcard(segments, buf)
+}
+
+// Deserializes segments written by segments_to_postcard() into out, without
+// needing an allocator.
+#[cfg(feature = "postcard")]
+pub fn segments_from_postcard<'b>(bytes: &[u8], out: &'b mut [Segment]) -> Result<&'b [Segment], postcard::Error> {
+    from_postcard(bytes, out)
+}
+
+// Like segments_to_postcard(), for the other lists that go with a track: the
+// object layers (objects::RoadsideObject, markings::Decal, markings::Hazard,
+// tunnels::Tunnel) and the frames of a replay (sync::ViewState,
+// sync::CarState, CameraState).
+#[cfg(feature = "postcard")]
+pub fn to_postcard<'b, T: serde::Serialize>(items: &[T], buf: &'b mut [u8]) -> Result<&'b mut [u8], postcard::Error> {
+    postcard::to_slice(items, buf)
+}
+
+// Deserializes a list written by to_postcard() into out, like
+// segments_from_postcard().
+#[cfg(feature = "postcard")]
+pub fn from_postcard<'b, T: serde::de::DeserializeOwned>(bytes: &[u8], out: &'b mut [T]) -> Result<&'b [T], postcard::Error> {
+    use serde::de::{DeserializeSeed, Deserializer, Error, SeqAccess, Visitor};
+
+    struct SliceSeed<'s, T>(&'s mut [T]);
+
+    impl<'de, 's, T: serde::de::DeserializeOwned> DeserializeSeed<'de> for SliceSeed<'s, T> {
+        type Value = usize;
+
+        fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<usize, D::Error> {
+            deserializer.deserialize_seq(self)
+        }
+    }
+
+    impl<'de, 's, T: serde::de::DeserializeOwned> Visitor<'de> for SliceSeed<'s, T> {
+        type Value = usize;
+
+        fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+            write!(f, "at most {} items", self.0.len())
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<usize, A::Error> {
+            let mut count = 0;
+            while let Some(item) = seq.next_element::<T>()? {
+                if count >= self.0.len() {
+                    return Err(A::Error::invalid_length(count + 1, &self));
+                }
+                self.0[count] = item;
+                count += 1;
+            }
+            Ok(count)
+        }
+    }
+
+    let mut deserializer = postcard::Deserializer::from_bytes(bytes);
+    let count = SliceSeed(out).deserialize(&mut deserializer)?;
+    Ok(&out[..count])
+}
+
+// Includes a binary track file like include_bytes!, but aligned so that it can
+// be given to segments_from_bytes().
+#[macro_export]
+macro_rules! include_track {
+    ($path:expr) => {{
+        #[repr(C, align(4))]
+        struct Aligned<T: ?Sized>(T);
+        static ALIGNED: &Aligned<[u8]> = &Aligned(*include_bytes!($path));
+        &ALIGNED.0
+    }};
+}
+
+// Limits checked by track!(). Lengths are in whole units, i.e. before the
+// FP_POS shift. A curve times its length squared has to stay below
+// MACRO_MAX_BEND, or the renderer's fixed-point math overflows on the segment:
+// the (x_curve * z >> FP_POS) * z term of integrate_segment() is the bend
+// shifted up by FP_POS.
+pub(crate) const MACRO_MAX_LENGTH: i32 = 4096;
+pub(crate) const MACRO_MAX_CURVE: i32 = 255;
+pub(crate) const MACRO_MAX_BEND: i64 = 1 << (31 - FP_POS);
+
+// Builds one segment for track!(). The checks run at compile time, as track!()
+// evaluates this in a constant.
+#[doc(hidden)]
+pub const fn macro_segment(
+    left: SideInclination,
+    right: SideInclination,
+    length: i32,
+    x_curve: i32,
+    y_curve: i32,
+) -> Segment {
+    assert!(length > 0, "track!: segment length must be positive");
+    assert!(length <= MACRO_MAX_LENGTH, "track!: segment is too long, split it");
+    assert!(
+        x_curve >= -MACRO_MAX_CURVE && x_curve <= MACRO_MAX_CURVE,
+        "track!: curve is out of range"
+    );
+    assert!(
+        y_curve >= -MACRO_MAX_CURVE && y_curve <= MACRO_MAX_CURVE,
+        "track!: crest or dip is out of range"
+    );
+    let bend = x_curve as i64 * length as i64 * length as i64;
+    assert!(
+        bend > -MACRO_MAX_BEND && bend < MACRO_MAX_BEND,
+        "track!: curve is too sharp for the segment length, split it"
+    );
+    Segment::new((left, right), length << crate::FP_POS, x_curve, y_curve)
+}
+
+// Limit of check_render_range() on the draw distance times the screen width
+// over the near plane, which sets how wide the farthest row is in road space.
+// That width is FP2, so every bit of FP_POS halves the limit twice.
+pub(crate) const RANGE_MAX_EXTENT: i64 = 1 << (31 - 2 * FP_POS);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RangeError {
+    // The draw distance is too far for the screen width and near plane.
+    TooFar,
+    // The road in view from the segment at this index turns too much.
+    TurnTooSharp(usize),
+    // The road in view from the segment at this index climbs or falls too
+    // much.
+    TooSteep(usize),
+}
+
+const fn fits(v: i64) -> bool {
+    v >= i32::MIN as i64 && v <= i32::MAX as i64
+}
+
+// Checks that render() can't overflow on the track from any camera position,
+// on a screen width pixels wide with the given near plane and draw distance
+// (max_z, in whole units). This is stricter than validate_segments(), which
+// only checks each segment on its own. It's a const fn, so a track can be
+// checked at compile time with checked_track().
+//
+// The road is integrated a whole segment at a time, so the slope built up by
+// the turns and hills in view gets multiplied by the length of each following
+// segment. This repeats those products of integrate_segment() in i64, taking
+// the depth of each segment as its length. That's never less than the depth
+// of segments longer than 64 units.
+pub const fn check_render_range(
+    segments: &[Segment],
+    width: i32,
+    near: i32,
+    max_z: i32,
+) -> Result<(), RangeError> {
+    if near <= 0 || (max_z as i64) * (width as i64) > RANGE_MAX_EXTENT * near as i64 {
+        return Err(RangeError::TooFar);
+    }
+    let max_z = (max_z as i64) << FP_POS;
+    let mut i = 0;
+    while i < segments.len() {
+        // Slopes are relative to a camera on segment i, which sees up to
+        // max_z past the end of the segment at most. Starting the slopes
+        // from the start of the segment covers every camera position on it.
+        let (mut x_slope, mut y_slope): (i64, i64) = (0, 0); // FP1
+        let mut distance: i64 = 0;
+        let mut j = i;
+        while j < segments.len() && (j == i || distance < max_z) {
+            let seg = &segments[j];
+            let z = seg.length as i64; // FP1
+            let (x_curve, y_curve) = (seg.x_curve as i64, seg.y_curve as i64);
+            if !fits(x_slope * z) || !fits(x_curve * z) || !fits(((x_curve * z) >> FP_POS) * z) {
+                return Err(RangeError::TurnTooSharp(i));
+            }
+            if !fits(y_slope * z) || !fits(y_slope * y_slope + (1 << (2 * FP_POS))) || !fits(2 * y_curve * z) {
+                return Err(RangeError::TooSteep(i));
+            }
+            x_slope += (2 * x_curve * z) >> FP_POS;
+            y_slope += (2 * y_curve * z) >> FP_POS;
+            if j > i {
+                distance += z;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+// Returns the track if check_render_range() accepts it, and panics
+// otherwise. Evaluated in a constant, an unsafe track fails the build:
+//
+//   static TRACK: [Segment; 3] = track::checked_track(track! { ... }, 320, 32, 2000);
+pub const fn checked_track<const N: usize>(
+    segments: [Segment; N],
+    width: i32,
+    near: i32,
+    max_z: i32,
+) -> [Segment; N] {
+    match check_render_range(&segments, width, near, max_z) {
+        Ok(()) => segments,
+        Err(RangeError::TooFar) => panic!("checked_track: draw distance is too far for the screen"),
+        Err(RangeError::TurnTooSharp(_)) => panic!("checked_track: the road turns too much within the draw distance"),
+        Err(RangeError::TooSteep(_)) => panic!("checked_track: the road climbs or falls too much within the draw distance"),
+    }
+}
+
+// Builds a track at compile time from readable entries, separated by ';':
+//
+//   straight 200;
+//   curve left 20 for 100;
+//   curve right 10 for 100, uphill/flat;
+//   straight 300, crest 1;
+//   straight 300, dip 1, downhill/uphill;
+//
+// Lengths are in whole units. An entry can be followed by "crest n" or "dip n"
+// (the y_curve of the segment) and the left/right side styles (uphill, flat,
+// downhill or wall, flat/flat by default). Values can be literals or any constant
+// expression in parentheses. The result is an array, so a track can be stored
+// in read-only memory with e.g.
+//
+//   static TRACK: &[Segment] = &track! { ... };
+//
+// Values are checked when compiling; a segment that would overflow the
+// renderer fails the build.
+#[macro_export]
+macro_rules! track {
+    // Entries
+    (@next [$($done:tt)*]) => { [$($done)*] };
+    (@next [$($done:tt)*] straight $length:tt $($rest:tt)*) => {
+        $crate::track!(@mods [$($done)*] [$length, 0] [] $($rest)*)
+    };
+    (@next [$($done:tt)*] curve left $amount:tt for $length:tt $($rest:tt)*) => {
+        $crate::track!(@mods [$($done)*] [$length, $amount] [] $($rest)*)
+    };
+    (@next [$($done:tt)*] curve right $amount:tt for $length:tt $($rest:tt)*) => {
+        $crate::track!(@mods [$($done)*] [$length, -($amount)] [] $($rest)*)
+    };
+    (@next [$($done:tt)*] $other:tt $($rest:tt)*) => {
+        compile_error!(concat!("track!: unknown entry '", stringify!($other), "'"))
+    };
+
+    // Collects the modifiers of an entry up to the next ';'
+    (@mods [$($done:tt)*] $base:tt [$($mods:tt)*] ; $($rest:tt)*) => {
+        $crate::track!(@next [$($done)* $crate::track!(@segment $base [0, flat, flat] $($mods)*),] $($rest)*)
+    };
+    (@mods [$($done:tt)*] $base:tt [$($mods:tt)*]) => {
+        $crate::track!(@next [$($done)* $crate::track!(@segment $base [0, flat, flat] $($mods)*),])
+    };
+    (@mods $done:tt $base:tt [$($mods:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::track!(@mods $done $base [$($mods)* $next] $($rest)*)
+    };
+
+    // Modifiers
+    (@segment $base:tt $state:tt , $($mods:tt)*) => {
+        $crate::track!(@segment $base $state $($mods)*)
+    };
+    (@segment $base:tt [$y:expr, $left:ident, $right:ident] crest $amount:tt $($mods:tt)*) => {
+        $crate::track!(@segment $base [$amount, $left, $right] $($mods)*)
+    };
+    (@segment $base:tt [$y:expr, $left:ident, $right:ident] dip $amount:tt $($mods:tt)*) => {
+        $crate::track!(@segment $base [-($amount), $left, $right] $($mods)*)
+    };
+    (@segment $base:tt [$y:expr, $l:ident, $r:ident] $left:ident / $right:ident $($mods:tt)*) => {
+        $crate::track!(@segment $base [$y, $left, $right] $($mods)*)
+    };
+    (@segment [$length:tt, $x:expr] [$y:expr, $left:ident, $right:ident]) => {{
+        const SEGMENT: $crate::Segment = $crate::track::macro_segment(
+            $crate::track!(@side $left),
+            $crate::track!(@side $right),
+            $length,
+            $x,
+            $y,
+        );
+        SEGMENT
+    }};
+    (@segment $base:tt $state:tt $other:tt $($mods:tt)*) => {
+        compile_error!(concat!("track!: unknown modifier '", stringify!($other), "'"))
+    };
+
+    (@side uphill) => { $crate::SideInclination::Uphill };
+    (@side flat) => { $crate::SideInclination::Flat };
+    (@side downhill) => { $crate::SideInclination::Downhill };
+    (@side wall) => { $crate::SideInclination::Wall };
+    (@side $other:tt) => {
+        compile_error!(concat!("track!: unknown side style '", stringify!($other), "'"))
+    };
+
+    ($($entries:tt)*) => { $crate::track!(@next [] $($entries)*) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SideInclination::*;
+
+    const SEGMENTS: [Segment; 3] = [
+        Segment::new((Flat, Flat), 200 << FP_POS, 0, 0),
+        Segment::new((Flat, Uphill), 100 << FP_POS, -20, 1),
+        Segment::new((Wall, Downhill), 300 << FP_POS, 3, -2),
+    ];
+
+    // Rewrites the precision byte of the segments chunk and fixes the CRC.
+    fn set_precision(bytes: &mut [u8], precision: u8) {
+        let encoding = HEADER_SIZE + CHUNK_HEADER_SIZE + 4;
+        let high = if cfg!(target_endian = "little") { encoding + 3 } else { encoding };
+        bytes[high] = precision;
+        let crc = crc32(0, &bytes[HEADER_SIZE..]);
+        bytes[12..16].copy_from_slice(&crc.to_ne_bytes());
+    }
+
+    // Runs at the start and the end, single segments between them, and
+    // deltas that wrap around.
+    fn runs() -> [Segment; 12] {
+        let a = Segment::new((Flat, Flat), 10 << FP_POS, 0, 0);
+        let b = Segment::new((Wall, Uphill), 1, i32::MAX, i32::MIN);
+        let c = Segment::new((Downhill, Wall), i32::MAX, i32::MIN, 7);
+        [a, a, a, b, c, b, b, a, c, c, c, c]
+    }
+
+    // Raw segments are used in-place, so they need to be aligned.
+    #[repr(C, align(4))]
+    struct Aligned([u8; 512]);
+
+    #[test]
+    fn round_trip() {
+        for track in [&SEGMENTS[..], &runs(), &runs()[..4], &runs()[8..], &[]] {
+            for encoding in [ENCODING_RAW, ENCODING_COMPRESSED] {
+                // A chunk after the segments, so that a run at the end of
+                // them is followed by other data.
+                let mut aligned = Aligned([0; 512]);
+                let bytes = &mut aligned.0;
+                let mut writer = ContainerWriter::new(bytes).unwrap();
+                writer.add_segments(track, encoding).unwrap();
+                writer.add_chunk(CHUNK_METADATA, b"name").unwrap();
+                let size = writer.finish();
+
+                let mut out = [SEGMENTS[0]; 16];
+                let decoded = load(&bytes[..size]).unwrap().segments(&mut out).unwrap();
+                assert_eq!(decoded, track, "encoding {}", encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn runs_compress() {
+        let mut bytes = [0; 256];
+        let size = encode_compressed(&[SEGMENTS[1]; 100], &mut bytes).unwrap();
+        // A tag, three fields and a run count after the headers.
+        assert!(size < HEADER_SIZE + CHUNK_HEADER_SIZE + SEGMENTS_HEADER_SIZE + 16);
+        let mut out = [SEGMENTS[0]; 100];
+        let decoded = load(&bytes[..size]).unwrap().segments(&mut out).unwrap();
+        assert_eq!(decoded, [SEGMENTS[1]; 100]);
+    }
+
+    #[test]
+    fn truncated_input_is_rejected() {
+        let mut bytes = [0; 256];
+        let size = encode_compressed(&runs(), &mut bytes).unwrap();
+        for len in 0..size {
+            assert!(load(&bytes[..len]).is_err(), "loaded {} of {} bytes", len, size);
+        }
+
+        // Compressed data cut short inside a valid container decodes up to
+        // where it ends and then fails.
+        let mut data = [0; 128];
+        let data_size = write_compressed(&runs(), &mut data).unwrap();
+        for len in 0..data_size {
+            let mut decoder = CompressedSegments::new(&data[..len], runs().len());
+            let decoded = decoder.by_ref().take_while(|seg| seg.is_ok()).count();
+            assert!(decoded < runs().len());
+            let mut decoder = CompressedSegments::new(&data[..len], runs().len());
+            assert_eq!(decoder.nth(decoded), Some(Err(TrackError::Truncated)));
+            assert_eq!(decoder.next(), None);
+        }
+    }
+
+    #[test]
+    fn source_goes_back() {
+        let mut data = [0; 128];
+        let size = write_compressed(&runs(), &mut data).unwrap();
+        let mut decoder = CompressedSegments::new(&data[..size], runs().len());
+        for index in [0, 5, 11, 3, 3, 0, 9] {
+            assert_eq!(decoder.segment(index), Some(runs()[index as usize]));
+        }
+        assert_eq!(decoder.segment(12), None);
+        assert_eq!(decoder.segment(1), Some(runs()[1]));
+    }
+
+    #[test]
+    fn crc32_matches_check_value() {
+        assert_eq!(crc32(0, b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xCBF4_3926);
+        assert_eq!(crc32(0, b""), 0);
+    }
+
+    #[test]
+    fn corrupted_chunks_are_rejected() {
+        let mut bytes = [0; 256];
+        let size = encode_compressed(&runs(), &mut bytes).unwrap();
+        let payload = HEADER_SIZE + CHUNK_HEADER_SIZE;
+        for i in HEADER_SIZE..size {
+            for bit in [0x01, 0x80] {
+                bytes[i] ^= bit;
+                let result = load(&bytes[..size]);
+                if i >= payload {
+                    assert!(matches!(result, Err(TrackError::BadChecksum { .. })), "byte {}", i);
+                } else {
+                    // A changed chunk header may also break the chunk list.
+                    assert!(result.is_err(), "byte {}", i);
+                }
+                bytes[i] ^= bit;
+            }
+        }
+        assert!(load(&bytes[..size]).is_ok());
+    }
+
+    #[test]
+    fn checksum_is_skipped_only_for_unchecked_tracks() {
+        let mut bytes = [0; 256];
+        let size = encode_compressed(&runs(), &mut bytes).unwrap();
+        let bytes = &mut bytes[..size];
+        bytes[6..8].copy_from_slice(&((VERSION_MAJOR as u16) << 8).to_ne_bytes());
+        assert!(open(bytes).is_ok());
+        bytes[size - 1] ^= 1;
+        assert!(matches!(open(bytes), Err(TrackError::BadChecksum { .. })));
+        bytes[12..16].copy_from_slice(&[0; 4]);
+        assert!(open(bytes).is_ok());
+        bytes[6..8].copy_from_slice(&VERSION.to_ne_bytes());
+        assert!(matches!(open(bytes), Err(TrackError::BadChecksum { stored: 0, .. })));
+    }
+
+    #[test]
+    fn precision_is_recorded() {
+        let mut bytes = [0; 256];
+        let size = encode_compressed(&SEGMENTS, &mut bytes).unwrap();
+        let bytes = &mut bytes[..size];
+        assert!(load(bytes).is_ok());
+
+        let other = if FP_POS == 8 { 10 } else { 8 };
+        set_precision(bytes, other);
+        assert!(matches!(
+            load(bytes),
+            Err(TrackError::WrongPrecision { found, supported })
+                if found == other && supported == FP_POS as u8
+        ));
+
+        // Zero means the default precision, as written before version 2.3.
+        set_precision(bytes, 0);
+        assert_eq!(load(bytes).is_ok(), FP_POS == 8);
+    }
+
+    #[cfg(feature = "postcard")]
+    #[test]
+    fn postcard_round_trip() {
+        let mut buf = [0; 256];
+        let bytes = segments_to_postcard(&SEGMENTS, &mut buf).unwrap();
+        let mut out = [SEGMENTS[0]; 4];
+        assert_eq!(segments_from_postcard(bytes, &mut out).unwrap(), &SEGMENTS);
+        let mut short = [SEGMENTS[0]; 2];
+        assert!(segments_from_postcard(bytes, &mut short).is_err());
+    }
+
+    #[cfg(feature = "postcard")]
+    #[test]
+    fn postcard_layers_round_trip() {
+        use crate::markings::{Decal, Hazard, Marking, Surface};
+        use crate::objects::RoadsideObject;
+        use crate::sync::{CarState, ViewState};
+
+        let objects = [RoadsideObject::new(5 << FP_POS, -3 << FP_POS, 7), RoadsideObject::animated(-1, 2, 3, 4, 5)];
+        let decals = [Decal::new(Marking::Checkered { columns: 8, rows: 2 }, 100, -200, 300, 400)];
+        let hazards = [Hazard::new(Surface::Oil, 1, 2, -3, 4), Hazard::new(Surface::Ice, i32::MAX, 0, i32::MIN, 1)];
+        let cars = [CarState { t: 10, x: -20 }, CarState { t: i32::MIN, x: i32::MAX }];
+        let views = [ViewState { t: 1, x_offset: -2, y_offset: 3 }];
+
+        let mut buf = [0; 256];
+        let bytes = to_postcard(&objects, &mut buf).unwrap();
+        let mut out = [RoadsideObject::new(0, 0, 0); 2];
+        let read = from_postcard(bytes, &mut out).unwrap();
+        assert!(read.iter().zip(&objects).all(|(a, b)| (a.t, a.x, a.sprite, a.frames, a.frames_per_image)
+            == (b.t, b.x, b.sprite, b.frames, b.frames_per_image)));
+
+        let bytes = to_postcard(&decals, &mut buf).unwrap();
+        let mut out = [Decal::new(Marking::Stop, 0, 0, 0, 0); 1];
+        let read = from_postcard(bytes, &mut out).unwrap();
+        assert_eq!((read[0].marking, read[0].t, read[0].cell_length), (decals[0].marking, 100, 400));
+
+        let bytes = to_postcard(&hazards, &mut buf).unwrap();
+        let mut out = [Hazard::new(Surface::Asphalt, 0, 0, 0, 0); 3];
+        let read = from_postcard(bytes, &mut out).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!((read[1].surface, read[1].t, read[1].tx), (Surface::Ice, i32::MAX, i32::MIN));
+
+        let bytes = to_postcard(&cars, &mut buf).unwrap();
+        let mut out = [CarState::default(); 2];
+        assert_eq!(from_postcard(bytes, &mut out).unwrap(), &cars);
+
+        let bytes = to_postcard(&views, &mut buf).unwrap();
+        let mut out = [ViewState::default(); 1];
+        assert_eq!(from_postcard(bytes, &mut out).unwrap(), &views);
+    }
+
+    #[test]
+    fn placement_patches() {
+        use crate::objects::RoadsideObject;
+
+        let tree = |t| RoadsideObject::new(t, 0, 1);
+        let mut storage = [tree(0); 4];
+        let mut len = 0;
+        let patches = [
+            PlacementPatch::Insert { index: 0, items: &[tree(10), tree(30)] },
+            PlacementPatch::Insert { index: 1, items: &[tree(20)] },
+            PlacementPatch::Replace { index: 2, count: 1, items: &[tree(40), tree(50)] },
+            PlacementPatch::Remove { index: 0, count: 1 },
+        ];
+        for patch in &patches {
+            apply_placement_patch(&mut storage, &mut len, patch).unwrap();
+        }
+        assert_eq!(len, 3);
+        assert_eq!([storage[0].t, storage[1].t, storage[2].t], [20, 40, 50]);
+
+        let patch = PlacementPatch::Remove { index: 2, count: 2 };
+        assert_eq!(apply_placement_patch(&mut storage, &mut len, &patch), Err(TrackError::PatchOutOfRange));
+        let patch = PlacementPatch::Insert { index: 4, items: &[] };
+        assert_eq!(apply_placement_patch(&mut storage, &mut len, &patch), Err(TrackError::PatchOutOfRange));
+        let patch = PlacementPatch::Insert { index: 3, items: &[tree(60), tree(70)] };
+        assert_eq!(apply_placement_patch(&mut storage, &mut len, &patch), Err(TrackError::BufferTooSmall));
+        assert_eq!(len, 3);
+    }
+}
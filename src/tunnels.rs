@@ -0,0 +1,84 @@
+// Lighting for tunnels. The renderer has no tunnel geometry, so a tunnel is
+// just a range of the road; painters ask light_at() for the brightness at the
+// t they're given and darken their road, wall and ground colors with it.
+// Brightness is FP1, from 0 (black) to 1 << FP_POS (daylight).
+use crate::FP_POS;
+
+// A tunnel from start to end, distances from the start of the road. Sorted
+// lists of these are given to light_at().
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tunnel {
+    pub start: i32,
+    pub end: i32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TunnelLighting {
+    pub fade_length: i32,  // Distance from an entrance to full darkness, FP1
+    pub darkness: i32,     // Brightness deep in the tunnel, FP1
+    pub light_period: i32, // Distance between ceiling lights, FP1, 0 for none
+    pub light_length: i32, // Length of the bright band under a light, FP1
+    pub light: i32,        // Brightness under a light, FP1
+}
+
+impl TunnelLighting {
+    // Returns the brightness at distance t.
+    // Brightness falls off linearly from both entrances, and ceiling lights
+    // are bright bands at regular intervals from the entrance.
+    pub fn light_at(&self, tunnels: &[Tunnel], t: i32) -> i32 {
+        for tunnel in tunnels {
+            if tunnel.start > t {
+                break;
+            }
+            if t >= tunnel.end {
+                continue;
+            }
+            let depth = (t - tunnel.start).min(tunnel.end - t);
+            let fade = self.fade_length.max(1);
+            let mut light = if depth >= fade {
+                self.darkness
+            } else {
+                (1 << FP_POS) - (((1 << FP_POS) - self.darkness) * depth) / fade
+            };
+            if self.light_period > 0 && (t - tunnel.start) % self.light_period < self.light_length {
+                light = light.max(self.light);
+            }
+            return light;
+        }
+        1 << FP_POS
+    }
+}
+
+// Scales a color channel by a brightness from light_at().
+pub fn shade(channel: i32, light: i32) -> i32 {
+    (channel * light) >> FP_POS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn light_fades_in_tunnels() {
+        let unit = 1 << FP_POS;
+        let tunnels = [Tunnel { start: 10 * unit, end: 30 * unit }, Tunnel { start: 50 * unit, end: 52 * unit }];
+        let lighting = TunnelLighting {
+            fade_length: 4 * unit,
+            darkness: unit / 4,
+            light_period: 0,
+            light_length: 0,
+            light: unit,
+        };
+        assert_eq!(lighting.light_at(&tunnels, 0), unit);
+        assert_eq!(lighting.light_at(&tunnels, 30 * unit), unit);
+        assert_eq!(lighting.light_at(&tunnels, 12 * unit), unit / 2 + unit / 8);
+        assert_eq!(lighting.light_at(&tunnels, 20 * unit), unit / 4);
+        assert_eq!(lighting.light_at(&tunnels, 51 * unit), unit - 3 * unit / 16);
+        let lit = TunnelLighting { light_period: 5 * unit, light_length: unit, ..lighting };
+        assert_eq!(lit.light_at(&tunnels, 20 * unit), unit);
+        assert_eq!(lit.light_at(&tunnels, 22 * unit), unit / 4);
+        assert_eq!(shade(200, unit), 200);
+        assert_eq!(shade(200, unit / 4), 50);
+    }
+}
@@ -0,0 +1,108 @@
+// Zigzag LEB128 varints, shared by the compressed track encoding and the
+// sync messages.
+
+pub(crate) enum Error {
+    Truncated,
+    Overlong,
+}
+
+// Writes value at out[*pos], advancing pos. Returns false if out is too
+// small.
+pub(crate) fn write(out: &mut [u8], pos: &mut usize, value: i32) -> bool {
+    let mut v = ((value << 1) ^ (value >> 31)) as u32;
+    loop {
+        let byte = (v & 0x7F) as u8;
+        v >>= 7;
+        let slot = match out.get_mut(*pos) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        *slot = if v != 0 { byte | 0x80 } else { byte };
+        *pos += 1;
+        if v == 0 {
+            return true;
+        }
+    }
+}
+
+// Reads a value from data[*pos], advancing pos.
+pub(crate) fn read(data: &[u8], pos: &mut usize) -> Result<i32, Error> {
+    let mut value: u32 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*pos).ok_or(Error::Truncated)?;
+        *pos += 1;
+        if shift >= 32 {
+            return Err(Error::Overlong);
+        }
+        value |= ((byte & 0x7F) as u32) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    // Zigzag
+    Ok(((value >> 1) as i32) ^ -((value & 1) as i32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(value: i32) -> usize {
+        let mut buf = [0; 5];
+        let mut pos = 0;
+        assert!(write(&mut buf, &mut pos, value));
+        let mut read_pos = 0;
+        assert!(matches!(read(&buf, &mut read_pos), Ok(v) if v == value), "{}", value);
+        assert_eq!(read_pos, pos);
+        pos
+    }
+
+    #[test]
+    fn values_round_trip() {
+        // Zigzag maps -64..64 to one byte, and each byte adds seven bits.
+        let cases = [
+            (0, 1),
+            (1, 1),
+            (-1, 1),
+            (63, 1),
+            (-64, 1),
+            (64, 2),
+            (-65, 2),
+            (8191, 2),
+            (-8192, 2),
+            (8192, 3),
+            ((1 << 20) - 1, 3),
+            (1 << 20, 4),
+            ((1 << 27) - 1, 4),
+            (-(1 << 27), 4),
+            (1 << 27, 5),
+            (i32::MAX, 5),
+            (i32::MIN, 5),
+        ];
+        for (value, size) in cases {
+            assert_eq!(round_trip(value), size, "{}", value);
+        }
+        for shift in 0..31 {
+            for value in [1 << shift, (1 << shift) - 1, -(1 << shift), -(1 << shift) - 1] {
+                round_trip(value);
+            }
+        }
+    }
+
+    #[test]
+    fn short_buffers() {
+        let mut buf = [0; 5];
+        let mut pos = 0;
+        assert!(!write(&mut buf[..4], &mut pos, i32::MIN));
+        let mut pos = 0;
+        assert!(write(&mut buf, &mut pos, i32::MIN));
+        for len in 0..5 {
+            let mut pos = 0;
+            assert!(matches!(read(&buf[..len], &mut pos), Err(Error::Truncated)));
+        }
+        let mut pos = 0;
+        assert!(matches!(read(&[0x80; 6], &mut pos), Err(Error::Overlong)));
+    }
+}
@@ -0,0 +1,296 @@
+// Helpers for drawing more than one view of the road on the same screen.
+
+use crate::math::{Backend, Math};
+use crate::{icos, isin, Painter, RoadRenderer, Segment, FP_POS};
+
+// Wraps a painter so that a view is drawn into a w x h rectangle at (x, y) of
+// the painter's screen. The renderer draws the view as if it was the whole
+// screen, so no pixels fall outside the rectangle.
+pub struct Viewport<'p, P: Painter> {
+    pub painter: &'p mut P,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl<'p, P: Painter> Viewport<'p, P> {
+    pub fn new(painter: &'p mut P, x: i32, y: i32) -> Self {
+        Viewport { painter, x, y }
+    }
+}
+
+impl<'p, P: Painter> Painter for Viewport<'p, P> {
+    type ColorType = P::ColorType;
+
+    fn draw(&mut self, x: i32, y: i32, color: &Self::ColorType) {
+        self.painter.draw(x + self.x, y + self.y, color);
+    }
+
+    forward_painter_colors!(painter);
+
+    fn fill_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType) {
+        self.painter
+            .fill_span(x_begin + self.x, x_end + self.x, y + self.y, color);
+    }
+
+    fn blend_span(&mut self, x_begin: i32, x_end: i32, y: i32, color: &Self::ColorType, amount: i32) {
+        self.painter
+            .blend_span(x_begin + self.x, x_end + self.x, y + self.y, color, amount);
+    }
+
+    fn begin_road_row(&mut self, y: i32, left: i32, right: i32, t: i32) {
+        self.painter
+            .begin_road_row(y + self.y, left + self.x, right + self.x, t);
+    }
+
+    fn copy_row(&mut self, y: i32, x_begin: i32, x_end: i32, src_y: i32, x_shift: i32) -> bool {
+        self.painter
+            .copy_row(y + self.y, x_begin + self.x, x_end + self.x, src_y + self.y, x_shift)
+    }
+}
+
+// Camera of one player, see SplitScreen.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Camera {
+    pub x_offset: i32, // FP1
+    pub y_offset: i32, // FP1
+}
+
+// Two players on the same track, each with their own renderer and camera.
+// The views are W x H each, stacked on top of each other or side by side.
+pub struct SplitScreen<'a> {
+    pub players: [RoadRenderer<'a>; 2],
+    pub cameras: [Camera; 2],
+    pub side_by_side: bool,
+}
+
+impl<'a> SplitScreen<'a> {
+    pub fn new(segments: &'a [Segment], near: i32, side_by_side: bool) -> Self {
+        SplitScreen {
+            players: [RoadRenderer::new(segments, near), RoadRenderer::new(segments, near)],
+            cameras: [Camera::default(); 2],
+            side_by_side,
+        }
+    }
+
+    // Attaches a column horizon buffer for each player's view, see
+    // RoadRenderer::set_column_horizon_buffer(). The buffers need one entry
+    // per column of a single view.
+    pub fn set_column_horizon_buffers(&mut self, buffers: [Option<&'a mut [i32]>; 2]) {
+        let [first, second] = buffers;
+        self.players[0].set_column_horizon_buffer(first);
+        self.players[1].set_column_horizon_buffer(second);
+    }
+
+    // Returns the position of the given player's view on the screen.
+    pub fn view_origin<const W: usize, const H: usize>(&self, player: usize) -> (i32, i32) {
+        let index = player as i32;
+        if self.side_by_side {
+            (index * W as i32, 0)
+        } else {
+            (0, index * H as i32)
+        }
+    }
+
+    // Renders both views.
+    pub fn render<P: Painter, const W: usize, const H: usize>(&mut self, painter: &mut P, max_z: i32, frame: i32) {
+        for player in 0..2 {
+            let (x, y) = self.view_origin::<W, H>(player);
+            let camera = self.cameras[player];
+            let mut viewport = Viewport::new(painter, x, y);
+            self.players[player].render::<Viewport<P>, W, H>(
+                &mut viewport,
+                camera.x_offset,
+                camera.y_offset,
+                max_z,
+                frame,
+            );
+        }
+    }
+}
+
+// A smaller W x H view drawn over the main view at (x, y) of the screen, e.g.
+// a camera following a rival. It shares the segments of the main renderer's
+// track and has its own position along it. Render it after the main view.
+pub struct PictureInPicture<'a> {
+    pub renderer: RoadRenderer<'a>,
+    pub camera: Camera,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl<'a> PictureInPicture<'a> {
+    pub fn new(segments: &'a [Segment], near: i32, x: i32, y: i32) -> Self {
+        PictureInPicture {
+            renderer: RoadRenderer::new(segments, near),
+            camera: Camera::default(),
+            x,
+            y,
+        }
+    }
+
+    // Moves the view to distance t from the start of the road.
+    pub fn follow(&mut self, t: i32) {
+        self.renderer.set(t);
+    }
+
+    pub fn render<P: Painter, const W: usize, const H: usize>(&mut self, painter: &mut P, max_z: i32, frame: i32) {
+        let mut viewport = Viewport::new(painter, self.x, self.y);
+        self.renderer.render::<Viewport<P>, W, H>(
+            &mut viewport,
+            self.camera.x_offset,
+            self.camera.y_offset,
+            max_z,
+            frame,
+        );
+    }
+}
+
+// A keyframe of a scripted camera path, see CameraPath.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct CameraKey {
+    pub time: i32,       // Frame at which the camera reaches this key
+    pub t: i32,          // Distance from the start of the road
+    pub x_offset: i32,   // FP1
+    pub look_ahead: i32, // Draw distance, the max_z given to render(), FP1
+}
+
+// Plays back a list of camera keyframes through a renderer, e.g. for an
+// attract mode flying along the track on the title screen. The camera moves
+// linearly between the keys.
+pub struct CameraPath<'k> {
+    pub keys: &'k [CameraKey], // Sorted by time
+    pub height: i32,           // y_offset of the camera, FP1
+    pub looping: bool,         // Start over after the last key
+}
+
+impl<'k> CameraPath<'k> {
+    pub fn new(keys: &'k [CameraKey], height: i32, looping: bool) -> Self {
+        CameraPath { keys, height, looping }
+    }
+
+    // Returns the camera at the given time. Before the first key and after
+    // the last one (unless looping), the camera stays at that key.
+    pub fn sample(&self, time: i32) -> Option<CameraKey> {
+        let first = self.keys.first()?;
+        let last = self.keys.last()?;
+        let duration = last.time - first.time;
+        let time = if self.looping && duration > 0 {
+            first.time + (time - first.time).rem_euclid(duration)
+        } else {
+            time
+        };
+        let next = self.keys.partition_point(|key| key.time <= time);
+        if next == 0 {
+            return Some(*first);
+        }
+        if next == self.keys.len() {
+            return Some(*last);
+        }
+        let (a, b) = (&self.keys[next - 1], &self.keys[next]);
+        // Exact division instead of an FP1 factor, so that the camera moves
+        // evenly even when keys are far apart.
+        let lerp = |from: i32, to: i32| {
+            from + ((to - from) as i64 * (time - a.time) as i64 / (b.time - a.time) as i64) as i32
+        };
+        Some(CameraKey {
+            time,
+            t: lerp(a.t, b.t),
+            x_offset: lerp(a.x_offset, b.x_offset),
+            look_ahead: lerp(a.look_ahead, b.look_ahead),
+        })
+    }
+
+    // Moves the renderer to the camera at the given time and renders the
+    // view. Returns false if there are no keys.
+    pub fn render<P: Painter, const W: usize, const H: usize>(
+        &self,
+        renderer: &mut RoadRenderer,
+        painter: &mut P,
+        time: i32,
+        frame: i32,
+    ) -> bool {
+        let key = match self.sample(time) {
+            Some(key) => key,
+            None => return false,
+        };
+        renderer.set(key.t);
+        renderer.render::<P, W, H>(painter, key.x_offset, self.height, key.look_ahead, frame);
+        true
+    }
+}
+
+// A camera circling a point on the road from above, e.g. for a photo mode.
+// The renderer always looks along the road, so turning towards the focus is
+// approximated with RoadRenderer::set_look_offset(). This works best within
+// about FULL_TURN / 8 of looking straight down the road.
+//
+// Sides and walls are drawn the way they look from close to the road, e.g.
+// walls reach up to the top of the screen, so from high above they would
+// cover the road. The height is therefore limited to the distance, i.e. the
+// camera looks down on the focus at up to 45 degrees.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct OrbitCamera {
+    pub focus_t: i32,  // Distance of the focus from the start of the road
+    pub distance: i32, // Horizontal distance from the focus, FP1
+    pub height: i32,   // y_offset of the camera, FP1, at most distance
+    pub angle: i32,    // Binary angle, zero is behind the focus
+}
+
+impl OrbitCamera {
+    // Places the renderer's camera and renders the view, with the focus in
+    // the middle of the screen. The look offset is left set on the renderer.
+    pub fn render<P: Painter, const W: usize, const H: usize>(
+        &self,
+        renderer: &mut RoadRenderer,
+        painter: &mut P,
+        max_z: i32,
+        frame: i32,
+    ) {
+        let t = self.focus_t - Backend::mul_shr(self.distance, icos(self.angle), 2 * FP_POS);
+        let x_offset = Backend::mul_shr(self.distance, isin(self.angle), 2 * FP_POS); // FP1
+        let height = self.height.min(self.distance);
+        renderer.set(t);
+        renderer.set_look_offset(0, 0);
+        let (w, h) = (W as i32, H as i32);
+        let pos = renderer.screen_pos((w, h), x_offset, height, self.focus_t - t, 0, 0);
+        if pos.inv_z > 0 {
+            renderer.set_look_offset(pos.x - w / 2, pos.y - h / 2);
+        }
+        renderer.render::<P, W, H>(painter, x_offset, height, max_z, frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ascii::{AsciiSink, AsciiStyle};
+    use crate::StyledPainter;
+
+    fn orbit_frame(camera: &OrbitCamera, buffer: &mut [u8; 32 * 16]) -> (i32, i32) {
+        let track = crate::track! {
+            straight 20;
+            curve left 4 for 20, wall/wall;
+            straight 40;
+        };
+        let style = AsciiStyle::default();
+        let mut sink = AsciiSink::new(buffer, 32, 16, |c: &u8| *c).unwrap();
+        let mut painter = StyledPainter::new(&mut sink, &style);
+        let mut road = RoadRenderer::new(&track, 32);
+        camera.render::<_, 32, 16>(&mut road, &mut painter, 100 << FP_POS, 0);
+        road.camera_state().look
+    }
+
+    #[test]
+    fn orbit_height_is_limited() {
+        let unit = 1 << FP_POS;
+        let camera = OrbitCamera { focus_t: 30 * unit, distance: 8 * unit, height: 8 * unit, angle: 0 };
+        let mut limit = [0; 32 * 16];
+        let look = orbit_frame(&camera, &mut limit);
+        // Looking down at 45 degrees turns the view down by about as many
+        // rows as the near plane.
+        assert!(look.1 > 0 && look.1 <= 32);
+        let mut high = [0; 32 * 16];
+        assert_eq!(orbit_frame(&OrbitCamera { height: 100 * unit, ..camera }, &mut high), look);
+        assert!(limit == high);
+    }
+}